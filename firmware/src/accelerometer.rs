@@ -0,0 +1,171 @@
+use chrono::{Local, NaiveDate};
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::{i2cdev::linux::LinuxI2CError, I2CError, I2cdev};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::logger;
+
+// MPU-6050 breakout on the pi's i2c header: SDA on GPIO 2, SCL on GPIO 3,
+// AD0 to ground for address 0x68
+pub const DEFAULT_I2C_PATH: &str = "/dev/i2c-1";
+const ADDRESS: u8 = 0x68;
+
+// registers, see the MPU-6000/6050 register map
+const PWR_MGMT_1: u8 = 0x6B;
+const ACCEL_XOUT_H: u8 = 0x3B;
+
+// the accelerometer wakes up at +-2g, 16384 per g
+const LSB_PER_G: f32 = 16384.0;
+const SAMPLE_EVERY: Duration = Duration::from_millis(20);
+
+// a step is the total acceleration going over the threshold, then nothing
+// counts for the lockout so one footfall isn't counted twice
+const STEP_THRESHOLD_G: f32 = 1.2;
+const STEP_LOCKOUT: Duration = Duration::from_millis(300);
+
+// the count is written out at most this often, and when the day changes
+const SAVE_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Error, Debug)]
+pub enum AccelError {
+    #[error("Failed to open the i2c bus: {0}")]
+    Open(#[from] LinuxI2CError),
+    #[error("I2C error: {0}")]
+    Bus(#[from] I2CError),
+    #[error("HOME is not set, don't know where to keep the step count")]
+    NoHome,
+    #[error("Failed to read or write the step count: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Bad step count file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// ~/.rpigotchi/steps.json
+pub fn steps_path() -> Result<PathBuf, AccelError> {
+    let home = env::var_os("HOME").ok_or(AccelError::NoHome)?;
+    Ok(PathBuf::from(home).join(".rpigotchi").join("steps.json"))
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedSteps {
+    date: NaiveDate,
+    steps: u32,
+}
+
+// steps so far today. the counting happens in a thread of its own, this is
+// just a handle on the count
+pub struct StepCounter {
+    steps: Arc<AtomicU32>,
+}
+
+impl StepCounter {
+    // wakes the MPU-6050 on the bus at i2c_path and starts counting, picking
+    // up today's count from disk if there is one
+    pub fn start(i2c_path: &str) -> Result<Self, AccelError> {
+        let mut i2c = I2cdev::new(i2c_path)?;
+        // clears the sleep bit, everything else stays at the defaults
+        i2c.write(ADDRESS, &[PWR_MGMT_1, 0])?;
+
+        let path = steps_path()?;
+        let today = Local::now().date_naive();
+        let saved = match fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<SavedSteps>(&text)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => SavedSteps {
+                date: today,
+                steps: 0,
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let start = if saved.date == today { saved.steps } else { 0 };
+
+        let steps = Arc::new(AtomicU32::new(start));
+        let counter = steps.clone();
+        thread::spawn(move || count_steps(i2c, counter, path));
+
+        info!("Step counter started at {} steps", start);
+        Ok(StepCounter { steps })
+    }
+
+    pub fn steps(&self) -> u32 {
+        self.steps.load(Ordering::Relaxed)
+    }
+}
+
+// total acceleration in g, 1 at rest
+fn read_magnitude(i2c: &mut I2cdev) -> Result<f32, AccelError> {
+    let mut raw = [0u8; 6];
+    i2c.write_read(ADDRESS, &[ACCEL_XOUT_H], &mut raw)?;
+
+    let axis = |i: usize| i16::from_be_bytes([raw[i], raw[i + 1]]) as f32 / LSB_PER_G;
+    let (x, y, z) = (axis(0), axis(2), axis(4));
+    Ok((x * x + y * y + z * z).sqrt())
+}
+
+fn save(path: &Path, date: NaiveDate, steps: u32) -> Result<(), AccelError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(&SavedSteps { date, steps })?)?;
+    Ok(())
+}
+
+// runs until the bus goes away
+fn count_steps(mut i2c: I2cdev, steps: Arc<AtomicU32>, path: PathBuf) {
+    let mut day = Local::now().date_naive();
+    let mut above = false;
+    let mut last_step = Instant::now() - STEP_LOCKOUT;
+    let mut saved_at = Instant::now();
+    let mut saved_steps = steps.load(Ordering::Relaxed);
+
+    loop {
+        let magnitude = match read_magnitude(&mut i2c) {
+            Ok(magnitude) => magnitude,
+            Err(e) => {
+                error!("Step counter stopped: {}", e);
+                logger::record_error(format!("Step counter stopped: {}", e));
+                return;
+            }
+        };
+
+        // counts on the way up through the threshold only
+        let now = Instant::now();
+        if magnitude > STEP_THRESHOLD_G {
+            if !above && now.duration_since(last_step) >= STEP_LOCKOUT {
+                steps.fetch_add(1, Ordering::Relaxed);
+                last_step = now;
+            }
+            above = true;
+        } else {
+            above = false;
+        }
+
+        // midnight, yesterday's steps don't count any more
+        let today = Local::now().date_naive();
+        let new_day = today != day;
+        if new_day {
+            day = today;
+            steps.store(0, Ordering::Relaxed);
+        }
+
+        let count = steps.load(Ordering::Relaxed);
+        if new_day || (count != saved_steps && saved_at.elapsed() >= SAVE_EVERY) {
+            if let Err(e) = save(&path, day, count) {
+                error!("Failed to save step count: {}", e);
+                logger::record_error(format!("Failed to save step count: {}", e));
+            }
+            saved_at = Instant::now();
+            saved_steps = count;
+        }
+
+        thread::sleep(SAMPLE_EVERY);
+    }
+}
@@ -0,0 +1,118 @@
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::{i2cdev::linux::LinuxI2CError, I2CError, I2cdev};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::config::BatterySection;
+use crate::logger;
+
+// the voltage doesn't move fast, no point reading it more than this
+const SAMPLE_EVERY: Duration = Duration::from_secs(30);
+
+// single cell LiPo under a light load, volts to percent. in between two
+// points it's a straight line, under the last one is empty
+const LIPO_CURVE: [(f32, u8); 11] = [
+    (4.20, 100),
+    (4.10, 90),
+    (4.00, 80),
+    (3.92, 70),
+    (3.85, 60),
+    (3.80, 50),
+    (3.75, 40),
+    (3.70, 30),
+    (3.62, 20),
+    (3.50, 10),
+    (3.00, 0),
+];
+
+#[derive(Error, Debug)]
+pub enum BatteryError {
+    #[error("Failed to open the i2c bus: {0}")]
+    Open(#[from] LinuxI2CError),
+    #[error("I2C error: {0}")]
+    Bus(#[from] I2CError),
+}
+
+pub fn percent_from_voltage(volts: f32) -> u8 {
+    for pair in LIPO_CURVE.windows(2) {
+        let (high_v, high_p) = pair[0];
+        let (low_v, low_p) = pair[1];
+        if volts >= high_v {
+            return high_p;
+        }
+        if volts > low_v {
+            let t = (volts - low_v) / (high_v - low_v);
+            return low_p + ((high_p - low_p) as f32 * t).round() as u8;
+        }
+    }
+    0
+}
+
+// the charge left on a UPS HAT. the ADC is read in a thread of its own, this
+// is just a handle on the last reading
+pub struct BatteryMonitor {
+    percent: Arc<AtomicU8>,
+}
+
+impl BatteryMonitor {
+    // opens the bus and reads once so a HAT that isn't there fails straight
+    // away, then keeps reading in the background
+    pub fn start(cfg: &BatterySection) -> Result<Self, BatteryError> {
+        let mut adc = Adc {
+            i2c: I2cdev::new(&cfg.i2c_path)?,
+            address: cfg.address,
+            register: cfg.register,
+            volts_per_count: cfg.adc_reference / cfg.adc_max as f32 * cfg.divider_ratio,
+        };
+        let volts = adc.read_voltage()?;
+        let start = percent_from_voltage(volts);
+
+        let percent = Arc::new(AtomicU8::new(start));
+        let reading = percent.clone();
+        thread::spawn(move || watch(adc, reading));
+
+        info!("Battery at {:.2}V, {}%", volts, start);
+        Ok(BatteryMonitor { percent })
+    }
+
+    pub fn charge_percent(&self) -> u8 {
+        self.percent.load(Ordering::Relaxed)
+    }
+}
+
+struct Adc {
+    i2c: I2cdev,
+    address: u8,
+    register: u8,
+    volts_per_count: f32,
+}
+
+impl Adc {
+    // the count is 16 bits big endian, the divider scales it back up to the
+    // battery's voltage
+    fn read_voltage(&mut self) -> Result<f32, BatteryError> {
+        let mut raw = [0u8; 2];
+        self.i2c
+            .write_read(self.address, &[self.register], &mut raw)?;
+        Ok(u16::from_be_bytes(raw) as f32 * self.volts_per_count)
+    }
+}
+
+// runs until the bus goes away
+fn watch(mut adc: Adc, percent: Arc<AtomicU8>) {
+    loop {
+        thread::sleep(SAMPLE_EVERY);
+        match adc.read_voltage() {
+            Ok(volts) => percent.store(percent_from_voltage(volts), Ordering::Relaxed),
+            Err(e) => {
+                error!("Battery monitor stopped: {}", e);
+                logger::record_error(format!("Battery monitor stopped: {}", e));
+                return;
+            }
+        }
+    }
+}
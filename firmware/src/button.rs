@@ -0,0 +1,254 @@
+use embedded_hal::digital::InputPin;
+use std::time::{Duration, Instant};
+
+use crate::gpio::GpioInput;
+use crate::EpaperError;
+
+// the buttons short their pin to ground, each pin needs a pull-up resistor
+// (10k to 3.3V) so it reads high while the button is up. default wiring is
+// feed on GPIO 5, play on GPIO 6 and rest (sleep_pin) on GPIO 13
+
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(50);
+// held down this long it's a long press instead
+pub const DEFAULT_LONG_PRESS: Duration = Duration::from_millis(800);
+
+// a short press comes when the button goes back up, a held one as soon as
+// it's been down for the long press time, and nothing on letting go of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ButtonEvent {
+    Feed,
+    Play,
+    Rest,
+    FeedHeld,
+    PlayHeld,
+    RestHeld,
+}
+
+// turns a bouncy level into clean presses. the level has to hold for the
+// whole window before it counts, and only going from up to down is a press,
+// holding it down isn't another one
+#[derive(Debug, Clone, Copy)]
+pub struct Debouncer {
+    debounce: Duration,
+    // last level seen and when it changed to that
+    raw_low: bool,
+    changed_at: Instant,
+    // level once it has held for the window
+    stable_low: bool,
+}
+
+impl Debouncer {
+    pub fn new(debounce: Duration) -> Self {
+        Debouncer {
+            debounce,
+            raw_low: false,
+            changed_at: Instant::now(),
+            stable_low: false,
+        }
+    }
+
+    // feed it every level read, true once per press
+    pub fn update(&mut self, low: bool, now: Instant) -> bool {
+        self.settle(low, now) == Some(true)
+    }
+
+    // same, but for going up as well. Some(low) once it's held for the
+    // window at a new level
+    pub fn settle(&mut self, low: bool, now: Instant) -> Option<bool> {
+        if low != self.raw_low {
+            self.raw_low = low;
+            self.changed_at = now;
+        }
+        if self.stable_low == low || now.duration_since(self.changed_at) < self.debounce {
+            return None;
+        }
+
+        self.stable_low = low;
+        Some(low)
+    }
+}
+
+struct Button {
+    pin: GpioInput,
+    // what a short press and a long one come out as
+    event: ButtonEvent,
+    held: ButtonEvent,
+    debouncer: Debouncer,
+    long_press: Duration,
+    // when it went down, None while it's up
+    down_at: Option<Instant>,
+    // the long press went out for this time down
+    held_sent: bool,
+}
+
+impl Button {
+    fn new(pin: GpioInput, event: ButtonEvent, held: ButtonEvent) -> Self {
+        Button {
+            pin,
+            event,
+            held,
+            debouncer: Debouncer::new(DEFAULT_DEBOUNCE),
+            long_press: DEFAULT_LONG_PRESS,
+            down_at: None,
+            held_sent: false,
+        }
+    }
+
+    fn poll(&mut self) -> Result<Option<ButtonEvent>, EpaperError> {
+        let low = self.pin.is_low().map_err(EpaperError::Pin)?;
+        let now = Instant::now();
+        Ok(match self.debouncer.settle(low, now) {
+            Some(true) => {
+                self.down_at = Some(now);
+                self.held_sent = false;
+                None
+            }
+            Some(false) => match self.down_at.take() {
+                Some(_) if !self.held_sent => Some(self.event),
+                _ => None,
+            },
+            None => match self.down_at {
+                Some(at) if !self.held_sent && now.duration_since(at) >= self.long_press => {
+                    self.held_sent = true;
+                    Some(self.held)
+                }
+                _ => None,
+            },
+        })
+    }
+}
+
+pub struct ButtonManager {
+    buttons: [Button; 3],
+}
+
+impl ButtonManager {
+    pub fn new(feed: GpioInput, play: GpioInput, sleep: GpioInput) -> Self {
+        ButtonManager {
+            buttons: [
+                Button::new(feed, ButtonEvent::Feed, ButtonEvent::FeedHeld),
+                Button::new(play, ButtonEvent::Play, ButtonEvent::PlayHeld),
+                Button::new(sleep, ButtonEvent::Rest, ButtonEvent::RestHeld),
+            ],
+        }
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        for button in &mut self.buttons {
+            button.debouncer = Debouncer::new(debounce);
+        }
+        self
+    }
+
+    pub fn with_long_press(mut self, long_press: Duration) -> Self {
+        for button in &mut self.buttons {
+            button.long_press = long_press;
+        }
+        self
+    }
+
+    // has to be called more often than the debounce window to catch short
+    // presses. None when nothing was pressed, if two buttons go down at
+    // once the other one comes next poll
+    pub fn poll(&mut self) -> Result<Option<ButtonEvent>, EpaperError> {
+        for button in &mut self.buttons {
+            if let Some(event) = button.poll()? {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    // every button that was pressed since the last call, in feed, play,
+    // rest order. same polling rate as poll()
+    pub fn button_events(&mut self) -> Result<Vec<ButtonEvent>, EpaperError> {
+        let mut events = Vec::new();
+        for button in &mut self.buttons {
+            events.extend(button.poll()?);
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockPin;
+    use std::thread;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn a_bouncing_press_is_one_press() {
+        let mut debouncer = Debouncer::new(DEFAULT_DEBOUNCE);
+        let start = Instant::now();
+        let mut presses = 0;
+        // chatters every 2ms for 20ms going down, then stays down
+        for t in 0..200 {
+            let low = t >= 20 || t % 4 < 2;
+            presses += usize::from(debouncer.update(low, start + MS * t));
+        }
+        // and the same going back up
+        for t in 200..400 {
+            let low = t < 220 && t % 4 < 2;
+            presses += usize::from(debouncer.update(low, start + MS * t));
+        }
+        assert_eq!(presses, 1);
+    }
+
+    #[test]
+    fn settle_reports_down_then_up_once_each() {
+        let mut debouncer = Debouncer::new(DEFAULT_DEBOUNCE);
+        let start = Instant::now();
+        assert_eq!(debouncer.settle(true, start), None);
+        assert_eq!(debouncer.settle(true, start + DEFAULT_DEBOUNCE), Some(true));
+        assert_eq!(debouncer.settle(true, start + DEFAULT_DEBOUNCE * 2), None);
+        let up = start + DEFAULT_DEBOUNCE * 3;
+        assert_eq!(debouncer.settle(false, up), None);
+        assert_eq!(debouncer.settle(false, up + DEFAULT_DEBOUNCE), Some(false));
+    }
+
+    // polls until something comes out or it's clearly not going to
+    fn next_event(buttons: &mut ButtonManager) -> Option<ButtonEvent> {
+        for _ in 0..1000 {
+            if let Some(event) = buttons.poll().unwrap() {
+                return Some(event);
+            }
+            thread::sleep(MS);
+        }
+        None
+    }
+
+    fn buttons() -> (ButtonManager, MockPin) {
+        let feed = MockPin::new(true);
+        let up = || GpioInput::new(MockPin::new(true));
+        let buttons = ButtonManager::new(GpioInput::new(feed.clone()), up(), up())
+            .with_debounce(MS * 2)
+            .with_long_press(MS * 100);
+        (buttons, feed)
+    }
+
+    #[test]
+    fn holding_a_button_sends_held_and_nothing_on_letting_go() {
+        let (mut buttons, feed) = buttons();
+        feed.set_level(false);
+        assert_eq!(next_event(&mut buttons), Some(ButtonEvent::FeedHeld));
+        feed.set_level(true);
+        for _ in 0..20 {
+            assert_eq!(buttons.poll().unwrap(), None);
+            thread::sleep(MS);
+        }
+    }
+
+    #[test]
+    fn a_short_press_comes_out_on_letting_go() {
+        let (mut buttons, feed) = buttons();
+        feed.set_level(false);
+        for _ in 0..5 {
+            assert_eq!(buttons.poll().unwrap(), None);
+            thread::sleep(MS);
+        }
+        feed.set_level(true);
+        assert_eq!(next_event(&mut buttons), Some(ButtonEvent::Feed));
+    }
+}
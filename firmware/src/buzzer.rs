@@ -0,0 +1,54 @@
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use linux_embedded_hal::Delay;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use crate::gpio::GpioOutput;
+
+// the pomodoro's alarm, an A5
+pub const ALARM_HZ: u32 = 880;
+pub const ALARM_LENGTH: Duration = Duration::from_millis(500);
+
+// a passive buzzer between a gpio pin and ground. it only makes a sound
+// while the pin is toggled, so tones are bit-banged in a thread of their
+// own and beep() doesn't hold up the display
+pub struct Buzzer {
+    tones: mpsc::Sender<(u32, Duration)>,
+}
+
+impl Buzzer {
+    pub fn new(pin: GpioOutput) -> Self {
+        let (tones, rx) = mpsc::channel();
+        thread::spawn(move || play(pin, rx));
+        Buzzer { tones }
+    }
+
+    // queues a square wave of hz for length
+    pub fn beep(&self, hz: u32, length: Duration) {
+        let _ = self.tones.send((hz, length));
+    }
+}
+
+// runs until the Buzzer is dropped
+fn play(mut pin: GpioOutput, tones: mpsc::Receiver<(u32, Duration)>) {
+    let mut delay = Delay {};
+    for (hz, length) in tones {
+        let half_period = 500_000_000 / hz.max(1);
+        let until = Instant::now() + length;
+        while Instant::now() < until {
+            if let Err(e) = pin.set_high().and_then(|_| {
+                delay.delay_ns(half_period);
+                pin.set_low()
+            }) {
+                warn!("Buzzer pin failed: {:?}", e);
+                break;
+            }
+            delay.delay_ns(half_period);
+        }
+        // left low, a passive buzzer held high just draws current
+        let _ = pin.set_low();
+    }
+}
@@ -0,0 +1,107 @@
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::config::{ConfigError, DisplayConfig, SpotifySection};
+use crate::display;
+use crate::menu;
+use crate::spotify;
+use crate::EpaperError;
+
+// what the binary can be asked to do. every subcommand takes --config, no
+// subcommand at all is `run`
+#[derive(Parser, Debug)]
+#[command(name = "piknife", version, about = "rpigotchi e-paper firmware")]
+pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        help = "TOML config to use [default: /etc/rpigotchi.toml]"
+    )]
+    pub config: Option<PathBuf>,
+    // the window is picked when building, this only checks it's there
+    #[arg(
+        long,
+        global = true,
+        help = "Draw in a desktop window instead, needs a build with --features simulator"
+    )]
+    pub simulator: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    #[command(about = "Run the pet, the default")]
+    Run,
+    #[command(about = "Go round the screens by themselves until Ctrl+C")]
+    Demo,
+    #[command(
+        about = "Leave the self test pattern on the display",
+        alias = "test-graphics"
+    )]
+    TestDisplay,
+    #[command(about = "Leave some text on the display")]
+    ShowText {
+        #[arg(help = "Wrapped to fit, in the middle of the screen")]
+        text: String,
+    },
+    #[command(about = "Clear the display to white")]
+    Clear,
+    #[command(about = "Log in to Spotify and save the token, once before the first run")]
+    Authorize,
+    #[command(about = "Print the version")]
+    Version,
+}
+
+impl Cli {
+    // with whatever's been picked on the settings screen over it
+    pub fn load_config(&self) -> Result<DisplayConfig, ConfigError> {
+        let mut cfg = match &self.config {
+            // one asked for by name has to be there, only the default can be
+            // missing
+            Some(path) => {
+                std::fs::metadata(path)?;
+                DisplayConfig::from_file(path)?
+            }
+            None => DisplayConfig::load()?,
+        };
+        menu::apply_saved(&mut cfg);
+        Ok(cfg)
+    }
+}
+
+pub fn run(cli: &Cli) -> Result<(), EpaperError> {
+    let command = cli.command.clone().unwrap_or(Command::Run);
+    if command == Command::Version {
+        println!("piknife {}", env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    if cli.simulator && !cfg!(feature = "simulator") {
+        return Err(EpaperError::Command(
+            "this build has no simulator, build it with --features simulator".to_string(),
+        ));
+    }
+
+    let cfg = cli.load_config()?;
+    match command {
+        Command::Run => display::run_epaper_app(&cfg),
+        Command::Demo => display::run_demo_sequence(&cfg),
+        Command::TestDisplay => display::test_display(&cfg),
+        Command::ShowText { text } => display::show_text(&cfg, &text),
+        Command::Clear => display::clear_display(&cfg),
+        Command::Authorize => authorize(&cfg.spotify),
+        Command::Version => Ok(()),
+    }
+}
+
+// always the browser flow, even with a token saved already, so a new one
+// gets whatever scopes have been added since
+fn authorize(cfg: &SpotifySection) -> Result<(), EpaperError> {
+    let client = spotify::get_client_data()?.with_redirect_port(cfg.redirect_port);
+    let token = client.authorize()?;
+    token.save(&cfg.token_path)?;
+    println!("Logged in, token saved to {}", cfg.token_path.display());
+    Ok(())
+}
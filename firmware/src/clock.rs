@@ -0,0 +1,37 @@
+use chrono::{DateTime, Datelike, FixedOffset, Local, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::config::DisplayConfig;
+use crate::rtc::SYNCED_YEAR;
+
+// shown instead of the time until there is one worth showing
+pub const WAITING_FOR_SYNC: &str = "waiting for time sync";
+const TIME_FORMAT: &str = "%H:%M:%S";
+
+// time in the configured zone, or in the pi's own without one
+pub fn in_zone<T: TimeZone>(time: DateTime<T>, zone: Option<Tz>) -> DateTime<FixedOffset> {
+    match zone {
+        Some(zone) => time.with_timezone(&zone).fixed_offset(),
+        None => time.with_timezone(&Local).fixed_offset(),
+    }
+}
+
+// a pi with no RTC that hasn't reached NTP yet boots still counting from
+// 1970, a time like that isn't worth showing
+pub fn is_synced(time: &DateTime<FixedOffset>) -> bool {
+    time.year() >= SYNCED_YEAR
+}
+
+// HH:MM:SS, or WAITING_FOR_SYNC while the clock's wrong
+pub fn time_string(time: &DateTime<FixedOffset>) -> String {
+    if is_synced(time) {
+        time.format(TIME_FORMAT).to_string()
+    } else {
+        WAITING_FOR_SYNC.to_string()
+    }
+}
+
+// the system time, for anything without an rtc::Clock to hand
+pub fn current_time_string(cfg: &DisplayConfig) -> String {
+    time_string(&in_zone(Utc::now(), cfg.clock.timezone))
+}
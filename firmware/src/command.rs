@@ -0,0 +1,55 @@
+use tokio::sync::oneshot;
+
+use crate::utils::FontSize;
+
+// what other threads can ask of the display thread. it owns the EpaperApp
+// and drains these between frames, nothing else touches the screen
+#[derive(Debug)]
+pub enum DisplayCommand {
+    DrawText {
+        text: String,
+        x: i32,
+        y: i32,
+        size: FontSize,
+    },
+    // whole screen, 1 bit per pixel, rows padded to whole bytes, set is black
+    DrawImage {
+        bitmap: Vec<u8>,
+    },
+    // centered on a cleared screen
+    DrawQr {
+        data: String,
+        module_size: u8,
+    },
+    Clear,
+    // full refresh of what's on screen, gets rid of ghosting
+    Refresh,
+    // blank with a full refresh then the screen that was up drawn again,
+    // for a panel that's ghosted badly. answered once it's all done
+    ClearScreen,
+    // a labeled bar that goes back to the last screen once current gets to
+    // total. current is clamped to total, a total of 0 spins instead
+    Progress {
+        label: String,
+        current: u32,
+        total: u32,
+    },
+    ShowPet,
+    ShowClock,
+    ShowSpotify,
+    ShowPlaylists,
+    ShowSettings,
+    FeedPet,
+    PlayWithPet,
+    // start also puts the pomodoro screen up
+    PomodoroStart,
+    PomodoroPause,
+    PomodoroSkip,
+}
+
+// a command plus where to send how it went. the display thread answers
+// with Err(message) if it couldn't do it
+pub struct Request {
+    pub command: DisplayCommand,
+    pub reply: oneshot::Sender<Result<(), String>>,
+}
@@ -0,0 +1,627 @@
+use chrono_tz::Tz;
+use embedded_graphics::geometry::Size;
+use epd_waveshare::graphics::DisplayRotation;
+use serde::{Deserialize, Deserializer};
+use signal_hook::{consts::SIGHUP, iterator::Signals};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::menu;
+use crate::region;
+use crate::scheduler::{Cron, Schedule, ScheduleError, ScheduledTask};
+use crate::spotify;
+
+// highest GPIO broken out on the pi header
+const MAX_PIN: u64 = 27;
+// what the panel's controller copes with, in Hz
+const MIN_SPI_SPEED: u32 = 1_000_000;
+const MAX_SPI_SPEED: u32 = 32_000_000;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse config: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("{first} and {second} are both set to pin {pin}")]
+    PinConflict {
+        first: &'static str,
+        second: &'static str,
+        pin: u64,
+    },
+    #[error("{name} pin {pin} is not a header GPIO (0-{MAX_PIN})")]
+    InvalidPin { name: &'static str, pin: u64 },
+    #[error("max_speed_hz is {0}, it has to be 1-32MHz")]
+    SpiSpeed(u32),
+    #[error("dead pixel ({x}, {y}) is off the {width}x{height} screen")]
+    DeadPixel {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+    #[error("{0}")]
+    Schedule(#[from] ScheduleError),
+}
+
+// where the config is read from when nothing else is given
+pub const DEFAULT_PATH: &str = "/etc/rpigotchi.toml";
+
+// wiring and SPI setup for the display. the file has a [display] and a [gpio]
+// section, anything left out keeps the default
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct DisplayConfig {
+    pub display: DisplaySection,
+    pub gpio: GpioSection,
+    pub spotify: SpotifySection,
+    pub websocket: WebsocketSection,
+    pub battery: BatterySection,
+    pub weather: WeatherSection,
+    pub steps: StepsSection,
+    pub clock: ClockSection,
+    pub pomodoro: PomodoroSection,
+    pub temperature: TemperatureSection,
+    pub pet: PetSection,
+    // [[schedule]] entries, see scheduler.rs
+    pub schedule: Vec<ScheduledTask>,
+    // the file it was read from, SIGHUP reads it again
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DisplaySection {
+    pub spi_path: String,
+    pub max_speed_hz: u32,
+    #[serde(deserialize_with = "rotation_degrees")]
+    pub rotation: DisplayRotation,
+    // partial refreshes between full ones, those clear the ghosting
+    pub full_refresh_every: u32,
+    // sleep the panel (keeping what's on it) after this long without a
+    // button press
+    pub idle_minutes: u64,
+    // blank and sleep the panel after this long without a button press
+    pub screensaver_minutes: u64,
+    // opening the panel is tried this many times, init_backoff_ms apart
+    // and doubling, before giving up
+    pub init_attempts: u32,
+    pub init_backoff_ms: u64,
+    // how often a frame gets drawn while the panel's awake
+    pub frame_ms: u64,
+    // pictures of your own for the splash at startup and the screensaver,
+    // scaled and cropped to the screen. the defaults if left out
+    pub splash_image: Option<PathBuf>,
+    pub idle_image: Option<PathBuf>,
+    // start on the screen that was up before the restart, or always on the
+    // pet's stats
+    pub resume_last_screen: bool,
+    // pixels stuck on a cheap panel, x and y the way it's rotated. the self
+    // test leaves them out and the settings cursor keeps off them
+    pub dead_pixels: Vec<(u32, u32)>,
+    // send every full frame twice and compare crcs of what went out, a
+    // mismatch sends it again. doubles the write time
+    pub verify_writes: bool,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct GpioSection {
+    pub cs_pin: u64,
+    pub busy_pin: u64,
+    pub dc_pin: u64,
+    pub rst_pin: u64,
+    // pet buttons, see button.rs for the wiring
+    pub feed_pin: u64,
+    pub play_pin: u64,
+    pub sleep_pin: u64,
+    pub debounce_ms: u64,
+    // held down this long a button does its spotify thing instead
+    pub long_press_ms: u64,
+    // a passive buzzer for the pomodoro alarm, none if left out
+    pub buzzer_pin: Option<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct SpotifySection {
+    // where the oauth token is kept between runs
+    pub token_path: PathBuf,
+    // the currently playing track is asked for at most this often
+    pub track_cache_secs: u64,
+    // the now playing screen as one scrolling line instead of the full
+    // layout
+    pub marquee: bool,
+    // where `piknife authorize` listens for spotify's redirect, the
+    // dashboard's redirect uri has to have the same port
+    pub redirect_port: u16,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct WebsocketSection {
+    // commands come in on ws://<pi>:port
+    pub port: u16,
+}
+
+// a UPS HAT's ADC on the i2c bus, see battery.rs. off unless there is one
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BatterySection {
+    pub enabled: bool,
+    pub i2c_path: String,
+    pub address: u8,
+    // where the 16 bit count is read from
+    pub register: u8,
+    // the count at the reference voltage
+    pub adc_max: u16,
+    pub adc_reference: f32,
+    // battery volts per volt at the ADC pin
+    pub divider_ratio: f32,
+}
+
+impl Default for BatterySection {
+    fn default() -> Self {
+        BatterySection {
+            enabled: false,
+            i2c_path: "/dev/i2c-1".to_string(),
+            address: 0x48,
+            register: 0x00,
+            adc_max: 4095,
+            adc_reference: 3.3,
+            divider_ratio: 2.0,
+        }
+    }
+}
+
+// where the weather mode gets the weather for, it has none until both are set
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct WeatherSection {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ClockSection {
+    // an IANA zone name like "Europe/London" for the times on screen and
+    // the schedule. left out it's whatever the pi is set to, UTC usually
+    pub timezone: Option<Tz>,
+    // a DS3231 on i2c_path keeps the time until NTP syncs
+    pub use_rtc: bool,
+    pub i2c_path: String,
+}
+
+impl Default for ClockSection {
+    fn default() -> Self {
+        ClockSection {
+            timezone: None,
+            use_rtc: false,
+            i2c_path: crate::rtc::DEFAULT_I2C_PATH.to_string(),
+        }
+    }
+}
+
+// lengths for the pomodoro screen, see pomodoro.rs
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PomodoroSection {
+    pub work_minutes: u64,
+    pub break_minutes: u64,
+    // work sessions before the long break
+    pub sessions: u8,
+}
+
+impl Default for PomodoroSection {
+    fn default() -> Self {
+        PomodoroSection {
+            work_minutes: 25,
+            break_minutes: 5,
+            sessions: 4,
+        }
+    }
+}
+
+// shown over the pet's face, nothing if it's empty
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct PetSection {
+    pub name: String,
+}
+
+// when the cpu's hot enough to put up an alert, see temperature.rs
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct TemperatureSection {
+    pub cpu_alert_celsius: f32,
+}
+
+impl Default for TemperatureSection {
+    fn default() -> Self {
+        TemperatureSection {
+            cpu_alert_celsius: crate::temperature::DEFAULT_CPU_ALERT,
+        }
+    }
+}
+
+// the MPU-6050 step counter, see accelerometer.rs
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct StepsSection {
+    pub enabled: bool,
+    pub i2c_path: String,
+    pub goal: u32,
+}
+
+impl Default for StepsSection {
+    fn default() -> Self {
+        StepsSection {
+            enabled: false,
+            i2c_path: crate::accelerometer::DEFAULT_I2C_PATH.to_string(),
+            goal: crate::widget::DEFAULT_STEP_GOAL,
+        }
+    }
+}
+
+impl Default for WebsocketSection {
+    fn default() -> Self {
+        WebsocketSection { port: 8765 }
+    }
+}
+
+impl Default for DisplaySection {
+    fn default() -> Self {
+        DisplaySection {
+            spi_path: "/dev/spidev0.0".to_string(),
+            max_speed_hz: 4_000_000,
+            rotation: DisplayRotation::Rotate270,
+            full_refresh_every: 30,
+            idle_minutes: 5,
+            screensaver_minutes: 30,
+            init_attempts: 3,
+            init_backoff_ms: 200,
+            frame_ms: 500,
+            splash_image: None,
+            idle_image: None,
+            resume_last_screen: true,
+            dead_pixels: Vec::new(),
+            verify_writes: false,
+        }
+    }
+}
+
+impl Default for GpioSection {
+    fn default() -> Self {
+        GpioSection {
+            cs_pin: 26,
+            busy_pin: 24,
+            dc_pin: 25,
+            rst_pin: 17,
+            feed_pin: 5,
+            play_pin: 6,
+            sleep_pin: 13,
+            debounce_ms: 50,
+            long_press_ms: 800,
+            buzzer_pin: None,
+        }
+    }
+}
+
+impl Default for SpotifySection {
+    fn default() -> Self {
+        let home = std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        SpotifySection {
+            token_path: home.join(".rpigotchi").join("token.json"),
+            track_cache_secs: spotify::DEFAULT_TRACK_TTL.as_secs(),
+            marquee: false,
+            redirect_port: spotify::DEFAULT_REDIRECT_PORT,
+        }
+    }
+}
+
+// rotation is written as degrees, 0/90/180/270, either as a number or a
+// string ("90")
+fn rotation_degrees<'de, D: Deserializer<'de>>(d: D) -> Result<DisplayRotation, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Degrees {
+        Number(u32),
+        Text(String),
+    }
+
+    let degrees = match Degrees::deserialize(d)? {
+        Degrees::Number(n) => n.to_string(),
+        Degrees::Text(text) => text.trim().to_string(),
+    };
+    match degrees.as_str() {
+        "0" => Ok(DisplayRotation::Rotate0),
+        "90" => Ok(DisplayRotation::Rotate90),
+        "180" => Ok(DisplayRotation::Rotate180),
+        "270" => Ok(DisplayRotation::Rotate270),
+        other => Err(serde::de::Error::custom(format!(
+            "rotation must be 0, 90, 180 or 270, not {}",
+            other
+        ))),
+    }
+}
+
+impl DisplayConfig {
+    // a missing file isn't an error, the defaults are used with a warning
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                warn!("No config at {}, using the defaults", path.display());
+                return Ok(DisplayConfig {
+                    path: Some(path.to_path_buf()),
+                    ..DisplayConfig::default()
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let mut cfg: DisplayConfig = toml::from_str(&text)?;
+        cfg.path = Some(path.to_path_buf());
+        Ok(cfg)
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::from_file(Path::new(DEFAULT_PATH))
+    }
+
+    fn pins(&self) -> Vec<(&'static str, u64)> {
+        let mut pins = vec![
+            ("cs", self.gpio.cs_pin),
+            ("busy", self.gpio.busy_pin),
+            ("dc", self.gpio.dc_pin),
+            ("rst", self.gpio.rst_pin),
+            ("feed", self.gpio.feed_pin),
+            ("play", self.gpio.play_pin),
+            ("sleep", self.gpio.sleep_pin),
+        ];
+        pins.extend(self.gpio.buzzer_pin.map(|pin| ("buzzer", pin)));
+        pins
+    }
+
+    // everything wrong with it, empty if it's good to go. the rotation
+    // needs no checking, a bad one doesn't get past parsing
+    pub fn validate(&self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+        let pins = self.pins();
+
+        for (i, &(name, pin)) in pins.iter().enumerate() {
+            if pin > MAX_PIN {
+                errors.push(ConfigError::InvalidPin { name, pin });
+            }
+            for &(other, _) in pins[i + 1..].iter().filter(|(_, p)| *p == pin) {
+                errors.push(ConfigError::PinConflict {
+                    first: name,
+                    second: other,
+                    pin,
+                });
+            }
+        }
+
+        // the simulator and headless builds don't go near spi. a device
+        // that isn't there is only a warning, right after power on it can
+        // still be on its way and opening the panel is retried
+        if cfg!(not(any(feature = "simulator", feature = "headless")))
+            && !Path::new(&self.display.spi_path).exists()
+        {
+            warn!(
+                "SPI device {} isn't there yet, is SPI turned on in raspi-config?",
+                self.display.spi_path
+            );
+        }
+        if !(MIN_SPI_SPEED..=MAX_SPI_SPEED).contains(&self.display.max_speed_hz) {
+            errors.push(ConfigError::SpiSpeed(self.display.max_speed_hz));
+        }
+
+        let Size { width, height } = region::rotated_size(self.display.rotation);
+        for &(x, y) in &self.display.dead_pixels {
+            if x >= width || y >= height {
+                errors.push(ConfigError::DeadPixel {
+                    x,
+                    y,
+                    width,
+                    height,
+                });
+            }
+        }
+
+        for task in &self.schedule {
+            if let Schedule::Cron(line) = &task.schedule {
+                if let Err(e) = Cron::parse(line) {
+                    errors.push(e.into());
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+// the config the display thread goes by, swapped whole when it's reloaded.
+// readers take the Arc once and use it for the frame, so a reload that
+// lands half way through drawing one can't leave it with some old fields
+// and some new. clones all share the one config
+#[derive(Clone)]
+pub struct SharedConfig(Arc<Mutex<Arc<DisplayConfig>>>);
+
+impl SharedConfig {
+    pub fn new(cfg: DisplayConfig) -> Self {
+        SharedConfig(Arc::new(Mutex::new(Arc::new(cfg))))
+    }
+
+    pub fn load(&self) -> Arc<DisplayConfig> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn store(&self, cfg: DisplayConfig) {
+        *self.0.lock().unwrap() = Arc::new(cfg);
+    }
+
+    // reads the file the config came from again, with the settings screen's
+    // picks over it. it's only swapped in once it's parsed and validated,
+    // anything wrong and the old one stays
+    pub fn reload(&self) -> Result<(), Vec<ConfigError>> {
+        let path = self.load().path.clone();
+        let path = path.unwrap_or_else(|| PathBuf::from(DEFAULT_PATH));
+        let mut cfg = DisplayConfig::from_file(&path).map_err(|e| vec![e])?;
+        menu::apply_saved(&mut cfg);
+        let errors = cfg.validate();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.store(cfg);
+        Ok(())
+    }
+}
+
+// `kill -HUP <pid>` reloads the config from a thread of its own, the
+// display picks it up at the start of its next frame
+pub fn reload_on_sighup(config: SharedConfig) -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGHUP])?;
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            match config.reload() {
+                Ok(()) => info!("Received SIGHUP, config reloaded"),
+                Err(errors) => {
+                    for e in errors {
+                        error!("Config not reloaded: {}", e);
+                    }
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_refresh(every: u32) -> DisplayConfig {
+        let mut cfg = DisplayConfig::default();
+        cfg.display.full_refresh_every = every;
+        cfg.display.frame_ms = every as u64 * 10;
+        cfg.pet.name = every.to_string();
+        cfg
+    }
+
+    // a file of its own in the temp dir, gone again once the test's done
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, text: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("piknife-{}-{}", std::process::id(), name));
+            std::fs::write(&path, text).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn dead_pixels_have_to_be_on_screen() {
+        let mut cfg = DisplayConfig::default();
+        let Size { width, height } = region::rotated_size(cfg.display.rotation);
+        cfg.display.dead_pixels = vec![(0, 0), (width - 1, height - 1), (width, 3), (4, height)];
+        let off: Vec<_> = cfg
+            .validate()
+            .into_iter()
+            .filter_map(|e| match e {
+                ConfigError::DeadPixel { x, y, .. } => Some((x, y)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(off, [(width, 3), (4, height)]);
+    }
+
+    #[test]
+    fn dead_pixels_are_read_as_pairs() {
+        let cfg: DisplayConfig =
+            toml::from_str("[display]\ndead_pixels = [[10, 20], [3, 4]]\n").unwrap();
+        assert_eq!(cfg.display.dead_pixels, [(10, 20), (3, 4)]);
+    }
+
+    #[test]
+    fn the_rtc_is_only_used_when_asked_for() {
+        let cfg: DisplayConfig = toml::from_str("[clock]\ntimezone = \"UTC\"\n").unwrap();
+        assert!(!cfg.clock.use_rtc);
+        assert_eq!(cfg.clock.i2c_path, crate::rtc::DEFAULT_I2C_PATH);
+
+        let cfg: DisplayConfig =
+            toml::from_str("[clock]\nuse_rtc = true\ni2c_path = \"/dev/i2c-3\"\n").unwrap();
+        assert!(cfg.clock.use_rtc);
+        assert_eq!(cfg.clock.i2c_path, "/dev/i2c-3");
+    }
+
+    #[test]
+    fn swapping_under_a_reader_is_never_half_applied() {
+        let shared = SharedConfig::new(with_refresh(10));
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for i in 0..2000 {
+                    shared.store(with_refresh(if i % 2 == 0 { 20 } else { 10 }));
+                }
+            })
+        };
+        // what a frame does, one load then every field from it
+        for _ in 0..2000 {
+            let cfg = shared.load();
+            let every = cfg.display.full_refresh_every;
+            assert_eq!(cfg.display.frame_ms, every as u64 * 10);
+            assert_eq!(cfg.pet.name, every.to_string());
+        }
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn reload_swaps_in_a_good_file() {
+        // there's no spidev off the pi, anything that exists keeps the
+        // missing device warning out of the way
+        let file = TempFile::new(
+            "good.toml",
+            "[display]\nspi_path = \"/dev/null\"\nfull_refresh_every = 7\n",
+        );
+        let shared = SharedConfig::new(DisplayConfig {
+            path: Some(file.0.clone()),
+            ..DisplayConfig::default()
+        });
+        shared.reload().unwrap();
+        assert_eq!(shared.load().display.full_refresh_every, 7);
+        assert_eq!(shared.load().path.as_ref(), Some(&file.0));
+    }
+
+    #[test]
+    fn reload_keeps_the_old_one_when_the_new_one_is_bad() {
+        let file = TempFile::new(
+            "bad.toml",
+            "[display]\nfull_refresh_every = 7\n[gpio]\nfeed_pin = 6\nplay_pin = 6\n",
+        );
+        let shared = SharedConfig::new(DisplayConfig {
+            path: Some(file.0.clone()),
+            ..DisplayConfig::default()
+        });
+        let before = shared.load();
+        assert!(shared.reload().is_err());
+        assert!(Arc::ptr_eq(&before, &shared.load()));
+
+        std::fs::write(&file.0, "[display\n").unwrap();
+        assert!(shared.reload().is_err());
+        assert!(Arc::ptr_eq(&before, &shared.load()));
+    }
+}
@@ -0,0 +1,136 @@
+use embedded_graphics::{
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+};
+use epd_waveshare::color::Color;
+
+use crate::utils::{draw_text, Canvas};
+
+// size of the checkerboard squares along the top edge
+const CHECKER: u32 = 8;
+// box round each known dead pixel, odd so the pixel's in the middle
+const DEAD_BOX: u32 = 7;
+
+// full screen self test, every pixel region gets toggled at least once
+// so stuck or ghosting pixels are easy to spot by eye. the dead ones from
+// the config are boxed, they're known about and not worth reporting again
+pub fn draw_test_pattern(display: &mut impl Canvas, dead: &[(u32, u32)]) {
+    let size = display.bounding_box().size;
+    let (w, h) = (size.width as i32, size.height as i32);
+
+    display.clear(Color::White).ok();
+
+    // outer border, checks the panel edges line up
+    let _ = Rectangle::new(Point::zero(), size)
+        .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+        .draw(display);
+
+    // checkerboard along the top
+    for cx in 0..(size.width / CHECKER) {
+        for cy in 0..2 {
+            if (cx + cy) % 2 == 0 {
+                let _ = Rectangle::new(
+                    Point::new((cx * CHECKER) as i32, (cy * CHECKER) as i32),
+                    Size::new(CHECKER, CHECKER),
+                )
+                .into_styled(PrimitiveStyle::with_fill(Color::Black))
+                .draw(display);
+            }
+        }
+    }
+
+    // diagonals corner to corner
+    let line = PrimitiveStyle::with_stroke(Color::Black, 1);
+    let _ = Line::new(Point::new(0, 0), Point::new(w - 1, h - 1))
+        .into_styled(line)
+        .draw(display);
+    let _ = Line::new(Point::new(w - 1, 0), Point::new(0, h - 1))
+        .into_styled(line)
+        .draw(display);
+
+    // solid block bottom right, checks large black areas
+    let _ = Rectangle::new(Point::new(w - 40, h - 30), Size::new(40, 30))
+        .into_styled(PrimitiveStyle::with_fill(Color::Black))
+        .draw(display);
+
+    // circle in the middle for aspect ratio
+    let _ = Circle::with_center(Point::new(w / 2, h / 2), 40)
+        .into_styled(PrimitiveStyle::with_stroke(Color::Black, 2))
+        .draw(display);
+
+    let box_style = PrimitiveStyle::with_stroke(Color::Black, 1);
+    for &(x, y) in dead {
+        let _ = Rectangle::with_center(Point::new(x as i32, y as i32), Size::new_equal(DEAD_BOX))
+            .into_styled(box_style)
+            .draw(display);
+    }
+
+    draw_text(display, "SELF TEST", 2, h - 12);
+}
+
+// what to look for on the pattern, for the log. known dead pixels are
+// left out, only the ones the config doesn't know about need reporting
+pub fn stuck_pixel_report(dead: &[(u32, u32)]) -> String {
+    if dead.is_empty() {
+        return "look for pixels stuck black or white".to_string();
+    }
+    let known = dead
+        .iter()
+        .map(|(x, y)| format!("({}, {})", x, y))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "look for pixels stuck black or white outside the {} boxed ones already in dead_pixels: {}",
+        dead.len(),
+        known
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{DrawCall, MockScreen};
+
+    #[test]
+    fn known_dead_pixels_are_left_out_of_the_report() {
+        assert_eq!(
+            stuck_pixel_report(&[]),
+            "look for pixels stuck black or white"
+        );
+        let report = stuck_pixel_report(&[(3, 4), (100, 50)]);
+        assert!(report.contains("2 boxed"), "{}", report);
+        assert!(report.ends_with("(3, 4) (100, 50)"), "{}", report);
+    }
+
+    #[test]
+    fn each_dead_pixel_gets_a_box_round_it() {
+        let mut plain = MockScreen::default();
+        draw_test_pattern(&mut plain, &[]);
+        let mut masked = MockScreen::default();
+        draw_test_pattern(&mut masked, &[(100, 50)]);
+
+        // the same pattern and text either side of the box
+        let start = plain
+            .calls
+            .iter()
+            .zip(&masked.calls)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let boxed = &masked.calls[start..start + masked.calls.len() - plain.calls.len()];
+        let around = Rectangle::with_center(Point::new(100, 50), Size::new_equal(DEAD_BOX));
+        assert!(!boxed.is_empty());
+        for call in boxed {
+            let area = match call {
+                DrawCall::FillSolid(area, Color::Black) => *area,
+                DrawCall::Pixels(Some(area)) => *area,
+                other => panic!("not part of a box: {:?}", other),
+            };
+            assert!(
+                around.contains(area.top_left),
+                "{:?} outside {:?}",
+                area,
+                around
+            );
+        }
+    }
+}
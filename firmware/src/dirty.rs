@@ -0,0 +1,91 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use epd_waveshare::color::Color;
+use std::ops::Deref;
+
+use crate::driver::EpdDisplay;
+use crate::panel::PanelDisplay;
+use crate::region::{self, union};
+
+// bounding box of everything that changed since it was last taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DirtyRect(Option<Rectangle>);
+
+impl DirtyRect {
+    pub fn add(&mut self, area: Rectangle) {
+        self.0 = Some(match self.0 {
+            Some(dirty) => union(&dirty, &area),
+            None => area,
+        });
+    }
+
+    pub fn get(&self) -> Option<Rectangle> {
+        self.0
+    }
+
+    // the area to refresh, leaving the tracker clean
+    pub fn take(&mut self) -> Option<Rectangle> {
+        self.0.take()
+    }
+}
+
+// the display buffer, but keeping track of which part of it drawing actually
+// changed. redrawing a pixel with the color it already has doesn't count, so
+// clearing and redrawing a whole frame only dirties what's different
+pub struct DirtyDisplay<D = PanelDisplay> {
+    display: D,
+    dirty: DirtyRect,
+}
+
+impl<D: EpdDisplay> DirtyDisplay<D> {
+    pub fn new(display: D) -> Self {
+        DirtyDisplay {
+            display,
+            dirty: DirtyRect::default(),
+        }
+    }
+
+    pub fn dirty(&self) -> Option<Rectangle> {
+        self.dirty.get()
+    }
+
+    pub fn take_dirty(&mut self) -> Option<Rectangle> {
+        self.dirty.take()
+    }
+}
+
+// reading is fine, writes have to go through DrawTarget
+impl<D> Deref for DirtyDisplay<D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        &self.display
+    }
+}
+
+impl<D: EpdDisplay> Dimensions for DirtyDisplay<D> {
+    fn bounding_box(&self) -> Rectangle {
+        self.display.bounding_box()
+    }
+}
+
+impl<D: EpdDisplay> DrawTarget for DirtyDisplay<D> {
+    type Color = Color;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Color>>,
+    {
+        for Pixel(p, color) in pixels {
+            // None is off screen, nothing to draw there anyway
+            match region::get_pixel(&self.display, p) {
+                Some(old) if old != color => {
+                    self.display.draw_iter([Pixel(p, color)])?;
+                    self.dirty.add(Rectangle::new(p, Size::new(1, 1)));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
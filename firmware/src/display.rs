@@ -0,0 +1,2675 @@
+use chrono::{DateTime, FixedOffset, NaiveTime};
+use chrono_tz::Tz;
+use embedded_graphics::{
+    mono_font::MonoFont,
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+};
+use epd_waveshare::{color::*, prelude::*};
+use linux_embedded_hal::SPIError;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+
+use crate::accelerometer::StepCounter;
+use crate::battery::BatteryMonitor;
+use crate::button::{ButtonEvent, ButtonManager};
+use crate::buzzer::{self, Buzzer};
+use crate::clock::{in_zone, is_synced, time_string, WAITING_FOR_SYNC};
+use crate::command::{DisplayCommand, Request};
+use crate::config::{self, ConfigError, DisplayConfig, SharedConfig};
+use crate::diag;
+use crate::dirty::DirtyDisplay;
+use crate::driver::{self, EpdDisplay, EpdDriver};
+use crate::face;
+use crate::framebuffer::FrameBuffer;
+use crate::gpio::HardwareBackend;
+#[cfg(feature = "headless")]
+use crate::headless::TermPanel as Panel;
+use crate::logger;
+use crate::menu::{self, Menu};
+use crate::network::NetworkMonitor;
+use crate::notification::{
+    Notification, NotificationPriority, NotificationQueue, SharedQueue, TOAST_DURATION,
+};
+use crate::panel::{PanelDisplay, HEIGHT, WIDTH};
+use crate::pet::{self, Pet};
+use crate::playlist::PlaylistBrowserMode;
+use crate::pomodoro::PomodoroTimer;
+use crate::progress::{self, Progress};
+use crate::qr;
+use crate::region::{self, Window};
+use crate::render::RenderLoop;
+use crate::rtc;
+use crate::scheduler::{self, DisplayMode, TaskRunner};
+use crate::screensaver::{ScreenSaver, Stage};
+// with the simulator feature the panel is a window on the desktop, with
+// headless it's printed to the terminal. either way the pins go nowhere and
+// everything else runs as it does on the pi
+#[cfg(any(feature = "simulator", feature = "headless"))]
+use crate::mock::MockBackend as Backend;
+#[cfg(feature = "simulator")]
+use crate::simulator::SimPanel as Panel;
+use crate::splash::{self, Picture, SPLASH_HOLD};
+use crate::spotify::{
+    self, AlbumArt, ArtCache, NowPlaying, PlayingKind, SpotifyError, TrackCache,
+    TrackChangeDetector,
+};
+use crate::sprites::Sprite;
+use crate::state::{DeviceState, PetState, SharedState};
+use crate::sysinfo::SystemMonitor;
+use crate::temperature::TemperatureMonitor;
+use crate::transition::{self, Direction, DisplayModeManager, Transition};
+use crate::utils::{
+    aligned_x, draw_album_art, draw_analog_clock, draw_battery, draw_progress_bar, draw_sprite,
+    draw_text, draw_text_sized, draw_text_styled, text_height, text_width, truncate_text,
+    wrap_text, Alignment, FontSize, BATTERY_BLINK_PERCENT, FONT_10X20, FONT_6X10,
+};
+use crate::weather::{WeatherClient, WeatherData};
+use crate::widget::{
+    NetworkStatusWidget, StepWidget, SystemInfoWidget, ThermometerWidget, WeatherWidget, Widget,
+};
+use crate::ws;
+#[cfg(not(any(feature = "simulator", feature = "headless")))]
+use crate::{driver::Panel, gpio::GpioInterruptBackend as Backend};
+
+const BUTTON_POLL: Duration = Duration::from_millis(10);
+// while the panel sleeps nothing gets drawn, the loop only comes round for
+// the pet, the battery and the like. buttons still need polling inside the
+// debounce window
+const IDLE_FRAME_INTERVAL: Duration = Duration::from_secs(5);
+const IDLE_BUTTON_POLL: Duration = Duration::from_millis(25);
+
+// now playing marquee: the text starts after the note icon, moves this many
+// px per call and leaves this much space before it comes round again
+const MARQUEE_LEFT: i32 = 14;
+const MARQUEE_STEP: i32 = 8;
+const MARQUEE_GAP: i32 = 40;
+
+// a change over more than this much of the panel goes out as the whole
+// frame, the window commands and its bigger LUT waits cost more than they
+// save by then
+const WHOLE_FRAME_PERCENT: u32 = 60;
+
+// the pet is saved this often as well as on the way out
+const PET_SAVE_EVERY: Duration = Duration::from_secs(5 * 60);
+
+// what --demo goes round
+pub const DEMO_SEQUENCE: [(DisplayMode, Duration); 5] = [
+    (DisplayMode::Clock, Duration::from_secs(10)),
+    (DisplayMode::NowPlaying, Duration::from_secs(10)),
+    (DisplayMode::PetStats, Duration::from_secs(10)),
+    (DisplayMode::Weather, Duration::from_secs(10)),
+    (DisplayMode::SystemInfo, Duration::from_secs(10)),
+];
+
+// how long to wait after a weather fetch before trying another, the client
+// caches good ones for longer anyway
+const WEATHER_RETRY: Duration = Duration::from_secs(60);
+// room kept clear around widget screens, the status bar is along the top
+const CONTENT_MARGIN: i32 = 4;
+const STATUS_BAR_HEIGHT: i32 = 14;
+
+// battery levels that get a warning and a shutdown, in percent
+const LOW_BATTERY_PERCENT: u8 = 15;
+const CRITICAL_BATTERY_PERCENT: u8 = 5;
+// draw_battery's outline and nub
+const BATTERY_ICON_WIDTH: i32 = 20;
+
+// notification popup size and the height of the ticker along the bottom
+const POPUP_WIDTH: u32 = 200;
+const POPUP_HEIGHT: u32 = 64;
+const TICKER_HEIGHT: u32 = 12;
+// room around a toast's text inside its border
+const TOAST_PADDING: u32 = 4;
+
+// how long the SIGUSR2 test pattern stays on screen
+const TEST_PATTERN_HOLD: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum EpaperError {
+    #[error("SPI error: {0}")]
+    Spi(#[from] SPIError),
+    #[error("GPIO error: {0}")]
+    Gpio(#[from] linux_embedded_hal::sysfs_gpio::Error),
+    #[error("GPIO error: {0}")]
+    Rppal(#[from] rppal::gpio::Error),
+    #[error("GPIO pin error: {0}")]
+    Pin(embedded_hal::digital::ErrorKind),
+    #[error("Display initialization error: {0}")]
+    DisplayInit(String),
+    #[error("Failed to open SPI device {path} (is SPI turned on in raspi-config?): {source}")]
+    SpiOpen {
+        path: String,
+        #[source]
+        source: SPIError,
+    },
+    #[error("Failed to configure SPI: {0}")]
+    SpiConfigure(#[source] SPIError),
+    // sending a frame to the controller's RAM
+    #[error("Failed to send the frame to the display: {0}")]
+    Draw(#[source] SPIError),
+    // telling the panel to show what's in RAM, or switching LUTs
+    #[error("Display refresh failed: {0}")]
+    Refresh(#[source] SPIError),
+    // [display] verify_writes gave up on a frame
+    #[error("Frame still didn't match after {0} tries")]
+    WriteVerify(u8),
+    // putting the panel into deep sleep or waking it back up
+    #[error("Failed to sleep or wake the display: {0}")]
+    Sleep(#[source] SPIError),
+    #[error("Failed to initialize the e-paper controller: {0}")]
+    EpdInit(String),
+    #[error("Timed out exporting GPIO pin {0}")]
+    PinExportTimeout(u8),
+    #[error("Config error: {0}")]
+    Config(#[from] ConfigError),
+    // everything validate() found, with_config logs them one by one
+    #[error("Invalid config: {}", list_errors(.0))]
+    InvalidConfig(Vec<ConfigError>),
+    #[error("WebSocket server error: {0}")]
+    WebSocket(std::io::Error),
+    // a websocket command that can't be done, goes back to the client
+    #[error("{0}")]
+    Command(String),
+    #[error("Spotify error: {0}")]
+    Spotify(#[from] SpotifyError),
+    #[error("Failed to decode image: {0}")]
+    ImageDecode(String),
+    // data too long for a QR code, or one too big for where it's drawn
+    #[error("QR code error: {0}")]
+    QrCode(String),
+}
+
+fn list_errors(errors: &[ConfigError]) -> String {
+    errors
+        .iter()
+        .map(ConfigError::to_string)
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// what the loop draws each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    Mode(DisplayMode),
+    // drawn by websocket commands, left alone by the loop
+    Custom,
+    // a DisplayCommand::Progress, redrawn every frame for the spinner until
+    // it's done
+    Progress,
+}
+
+// where the weather mode's weather comes from. fetching blocks the display
+// thread, so it's only tried once a minute
+struct WeatherSource {
+    client: WeatherClient,
+    latitude: f64,
+    longitude: f64,
+    last: Option<WeatherData>,
+    checked_at: Option<Instant>,
+}
+
+// what the held buttons do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Playback {
+    Previous,
+    PlayPause,
+    Next,
+}
+
+// the user's spotify account, for the playlist browser and the held buttons. calls block the
+// display thread like the weather does
+struct SpotifySource {
+    client: spotify::Client,
+    token: spotify::Token,
+    // what's playing, from the cache so spotify is only asked every
+    // track_cache_secs
+    track: Option<NowPlaying>,
+    tracks: TrackCache,
+    // the cover for the now playing screen
+    arts: ArtCache,
+    changes: TrackChangeDetector,
+}
+
+// the app over any panel and frame buffer, so it can run against the mocks
+// as well as the real thing. EpaperApp below is the real thing
+pub struct GenericEpaperApp<D: EpdDisplay, E: EpdDriver> {
+    epd: E,
+    // tracks what changed since the last refresh
+    display: DirtyDisplay<D>,
+    buttons: ButtonManager,
+    screensaver: ScreenSaver,
+    // epd.sleep() was called and nothing has woken it since
+    panel_asleep: bool,
+    screen: Screen,
+    refresh: RefreshLut,
+    // partial (quick LUT) refreshes since the last full one
+    partial_refreshes: u32,
+    // do a full refresh after this many partial ones, they leave ghosting
+    full_refresh_every: u32,
+    // scroll position of the now playing line and what it's scrolling
+    marquee_offset: i32,
+    marquee_text: String,
+    released: bool,
+    // alerts from anywhere, drawn over whatever screen is up
+    notifications: SharedQueue,
+    // for get_state over the websocket
+    state: SharedState,
+    ticker_offset: i32,
+    // what the panel is showing, partial updates send what differs from it
+    front: FrameBuffer,
+    battery: Option<BatteryMonitor>,
+    // the low battery warning went up and the charge hasn't recovered since
+    battery_warned: bool,
+    // the battery icon is drawn inverted this frame
+    battery_blink: bool,
+    // switches screen when a [[schedule]] entry fires
+    scheduler: TaskRunner,
+    weather: Option<WeatherSource>,
+    steps: Option<StepCounter>,
+    step_goal: u32,
+    // [display] frame_ms, buttons are polled in between
+    frame_interval: Duration,
+    // [clock] timezone, the pi's own if None
+    timezone: Option<Tz>,
+    // [pet] name
+    pet_name: String,
+    // the settings screen, it changes the three above live
+    settings: Menu,
+    // where leaving the settings screen goes back to
+    settings_back_to: DisplayMode,
+    system: SystemMonitor,
+    // which mode's up and how the last switch is to be shown
+    modes: DisplayModeManager,
+    network: NetworkMonitor,
+    temperature: TemperatureMonitor,
+    // runs whatever screen is up, the pomodoro screen just shows it
+    pomodoro: PomodoroTimer,
+    buzzer: Option<Buzzer>,
+    spotify: Option<SpotifySource>,
+    playlists: PlaylistBrowserMode,
+    // [display] splash_image and idle_image, None draws the defaults
+    splash: Option<Picture>,
+    idle_image: Option<Picture>,
+    // the last DisplayCommand::Progress and what was up before it
+    progress: Option<Progress>,
+    progress_back_to: Screen,
+    // swapped whole on SIGHUP, checked once a frame
+    // the RTC until NTP syncs, with [clock] use_rtc on
+    clock: rtc::Clock,
+    config: SharedConfig,
+    // the one the settings above came from
+    applied: Arc<DisplayConfig>,
+}
+
+pub type EpaperApp = GenericEpaperApp<PanelDisplay, Panel>;
+
+impl EpaperApp {
+    pub fn new(backend: &impl HardwareBackend) -> Result<Self, EpaperError> {
+        Self::with_config(&DisplayConfig::default(), backend)
+    }
+
+    pub fn with_config(
+        cfg: &DisplayConfig,
+        backend: &impl HardwareBackend,
+    ) -> Result<Self, EpaperError> {
+        let errors = cfg.validate();
+        if !errors.is_empty() {
+            for e in &errors {
+                error!("Config: {}", e);
+            }
+            return Err(EpaperError::InvalidConfig(errors));
+        }
+
+        let epd = Panel::open(cfg, backend)?;
+        GenericEpaperApp::with_panel(cfg, epd, backend)
+    }
+}
+
+impl<E: EpdDriver> GenericEpaperApp<PanelDisplay, E> {
+    // the rest of with_config once the panel's open, the buttons come off
+    // the same backend. the tests hand it a panel over a MockSpi
+    fn with_panel(
+        cfg: &DisplayConfig,
+        epd: E,
+        backend: &impl HardwareBackend,
+    ) -> Result<Self, EpaperError> {
+        // validate() made sure the pins fit in a u8
+        let buttons = ButtonManager::new(
+            backend.input_pin(cfg.gpio.feed_pin as u8)?,
+            backend.input_pin(cfg.gpio.play_pin as u8)?,
+            backend.input_pin(cfg.gpio.sleep_pin as u8)?,
+        )
+        .with_debounce(Duration::from_millis(cfg.gpio.debounce_ms))
+        .with_long_press(Duration::from_millis(cfg.gpio.long_press_ms));
+
+        Ok(Self::from_parts(cfg, PanelDisplay::default(), epd, buttons))
+    }
+}
+
+impl<D: EpdDisplay, E: EpdDriver> GenericEpaperApp<D, E> {
+    // whatever epd and display, the rest of cfg for the settings
+    pub fn from_parts(cfg: &DisplayConfig, mut display: D, epd: E, buttons: ButtonManager) -> Self {
+        display.set_rotation(cfg.display.rotation);
+        let config = SharedConfig::new(cfg.clone());
+        let applied = config.load();
+        let size = display.bounding_box().size;
+        let picture = |path: &Option<PathBuf>| {
+            splash::load_or_default(path.as_deref(), size.width, size.height)
+        };
+
+        GenericEpaperApp {
+            epd,
+            display: DirtyDisplay::new(display),
+            buttons,
+            screen: Screen::Mode(DisplayMode::default()),
+            screensaver: ScreenSaver::new(
+                Duration::from_secs(cfg.display.idle_minutes * 60),
+                Duration::from_secs(cfg.display.screensaver_minutes * 60),
+            ),
+            panel_asleep: false,
+            refresh: RefreshLut::Full,
+            partial_refreshes: 0,
+            full_refresh_every: cfg.display.full_refresh_every,
+            marquee_offset: 0,
+            marquee_text: String::new(),
+            released: false,
+            notifications: NotificationQueue::shared(),
+            state: SharedState::new(),
+            ticker_offset: 0,
+            front: FrameBuffer::new(),
+            battery: None,
+            battery_warned: false,
+            battery_blink: false,
+            scheduler: TaskRunner::new(&cfg.schedule),
+            weather: None,
+            steps: None,
+            step_goal: cfg.steps.goal,
+            frame_interval: Duration::from_millis(cfg.display.frame_ms),
+            timezone: cfg.clock.timezone,
+            pet_name: cfg.pet.name.clone(),
+            settings: Menu::new(cfg),
+            settings_back_to: DisplayMode::PetStats,
+            system: SystemMonitor::new(),
+            modes: DisplayModeManager::new(),
+            network: NetworkMonitor::new(),
+            temperature: TemperatureMonitor::new(cfg.temperature.cpu_alert_celsius),
+            pomodoro: PomodoroTimer::new(
+                Duration::from_secs(cfg.pomodoro.work_minutes * 60),
+                Duration::from_secs(cfg.pomodoro.break_minutes * 60),
+                cfg.pomodoro.sessions,
+            ),
+            buzzer: None,
+            spotify: None,
+            playlists: PlaylistBrowserMode::new(),
+            splash: picture(&cfg.display.splash_image),
+            idle_image: picture(&cfg.display.idle_image),
+            progress: None,
+            clock: rtc::Clock::from_config(&cfg.clock),
+            progress_back_to: Screen::Mode(DisplayMode::default()),
+            config,
+            applied,
+        }
+    }
+
+    pub fn with_battery(mut self, battery: BatteryMonitor) -> Self {
+        self.battery = Some(battery);
+        self
+    }
+
+    pub fn with_weather(mut self, latitude: f64, longitude: f64) -> Self {
+        self.weather = Some(WeatherSource {
+            client: WeatherClient::new(),
+            latitude,
+            longitude,
+            last: None,
+            checked_at: None,
+        });
+        self
+    }
+
+    pub fn with_steps(mut self, steps: StepCounter) -> Self {
+        self.steps = Some(steps);
+        self
+    }
+
+    pub fn with_spotify(mut self, client: spotify::Client, token: spotify::Token) -> Self {
+        self.spotify = Some(SpotifySource {
+            client,
+            token,
+            track: None,
+            tracks: TrackCache::new(Duration::from_secs(self.applied.spotify.track_cache_secs)),
+            arts: ArtCache::default(),
+            changes: TrackChangeDetector::default(),
+        });
+        self
+    }
+
+    // sounds the pomodoro alarm, it's silent without one
+    pub fn with_buzzer(mut self, buzzer: Buzzer) -> Self {
+        self.buzzer = Some(buzzer);
+        self
+    }
+
+    // the screen to start on, the saved one usually
+    pub fn with_mode(mut self, mode: DisplayMode) -> Self {
+        self.screen = Screen::Mode(mode);
+        self.modes.switch(mode, Transition::Instant);
+        self
+    }
+
+    // whether mode has what it needs to show anything, a weather screen
+    // with no location say
+    pub fn has_mode(&self, mode: DisplayMode) -> bool {
+        match mode {
+            DisplayMode::Weather => self.weather.is_some(),
+            DisplayMode::StepCount => self.steps.is_some(),
+            DisplayMode::NowPlaying | DisplayMode::Playlists => self.spotify.is_some(),
+            _ => true,
+        }
+    }
+
+    // the saved screen to start on, the pet's stats if it's been turned off
+    // since
+    fn resumable(&self, saved: DisplayMode) -> DisplayMode {
+        if self.has_mode(saved) {
+            return saved;
+        }
+        info!(
+            "{:?} was up last but it's off now, starting on the pet",
+            saved
+        );
+        DisplayMode::default()
+    }
+
+    // switches screen and remembers it for the next start
+    fn set_mode(&mut self, mode: DisplayMode) {
+        self.switch_mode(mode, Transition::Instant);
+    }
+
+    // same, the next frame goes out with transition
+    fn switch_mode(&mut self, mode: DisplayMode, transition: Transition) {
+        self.modes.switch(mode, transition);
+        if let Screen::Mode(previous) = self.screen {
+            if mode == DisplayMode::Playlists && previous != mode {
+                self.playlists.back_to = previous;
+            }
+            if mode == DisplayMode::Settings && previous != mode {
+                self.settings_back_to = previous;
+            }
+        }
+        self.screen = Screen::Mode(mode);
+        if let Err(e) = scheduler::mode_path().and_then(|path| scheduler::save_mode(&path, mode)) {
+            warn!("Failed to save display mode: {}", e);
+        }
+    }
+
+    pub fn run(
+        &mut self,
+        pet: &mut Pet,
+        commands: &mpsc::Receiver<Request>,
+    ) -> Result<(), EpaperError> {
+        let running = ctrl_c_flag();
+
+        // `kill -USR2 <pid>` shows the self test pattern, handy when SSHed in.
+        // signals only exist on unix, elsewhere this is a no-op
+        let test_pattern = Arc::new(AtomicBool::new(false));
+        #[cfg(unix)]
+        signal_hook::flag::register(signal_hook::consts::SIGUSR2, test_pattern.clone())
+            .expect("Error setting SIGUSR2 handler");
+
+        self.clear_screen()?;
+        self.show_splash(&running)?;
+
+        info!("Running. Press Ctrl+C to exit...");
+
+        // the first frame (and after the test pattern) goes out whole,
+        // after that only what changed
+        let mut full_frame = true;
+        let mut pet_saved = Instant::now();
+        let mut frames = RenderLoop::new(self.frame_interval);
+
+        while running.load(Ordering::SeqCst) {
+            self.pick_up_config();
+
+            // the plug gets pulled more often than Ctrl+C gets pressed
+            if pet_saved.elapsed() >= PET_SAVE_EVERY {
+                save_pet(pet);
+                pet_saved = Instant::now();
+            }
+
+            if test_pattern.swap(false, Ordering::SeqCst) {
+                if self.screensaver.activity() {
+                    self.wake()?;
+                }
+                self.show_test_pattern(&running)?;
+                full_frame = true;
+            }
+
+            if self.check_battery() {
+                break;
+            }
+            self.check_temperature();
+
+            if let Some(phase) = self.pomodoro.tick() {
+                info!("Pomodoro on to {}", phase.label());
+                if let Some(buzzer) = &self.buzzer {
+                    buzzer.beep(buzzer::ALARM_HZ, buzzer::ALARM_LENGTH);
+                }
+            }
+
+            // an important notification is worth waking up for
+            if self.screensaver.is_asleep()
+                && self.has_notification(NotificationPriority::High)
+                && self.screensaver.activity()
+            {
+                self.wake()?;
+            }
+
+            // nothing to draw while the screensaver is on, just wait for a button
+            if self.screensaver.is_asleep() {
+                self.wait_for_next_frame(&running, &mut frames, pet, commands)?;
+                self.wind_down()?;
+                continue;
+            }
+
+            self.check_track(pet);
+
+            let now = in_zone(self.clock.now(), self.timezone);
+            if let Some((mode, transition)) = self.scheduler.poll(now) {
+                if self.screen != Screen::Mode(mode) {
+                    info!("Schedule switching to {:?} ({:?})", mode, transition);
+                    self.switch_mode(mode, transition);
+                }
+            }
+
+            self.state.set(self.device_state(pet));
+
+            self.dismiss_progress(Instant::now());
+
+            // whatever the last command drew stays up on Custom
+            match self.screen {
+                Screen::Mode(mode) => {
+                    self.draw_mode(mode, pet, now);
+                    self.draw_status_bar();
+                }
+                Screen::Progress => self.draw_progress(),
+                Screen::Custom => {}
+            }
+
+            // update the display
+            if full_frame {
+                self.update_frame()?;
+                full_frame = false;
+            } else if let Some(transition) = self.modes.take_transition() {
+                self.play_transition(transition)?;
+            } else {
+                self.display_partial_update()?;
+            }
+            self.maybe_full_refresh()?;
+
+            self.ticker_offset = self.ticker_offset.wrapping_add(MARQUEE_STEP) & i32::MAX;
+
+            self.wait_for_next_frame(&running, &mut frames, pet, commands)?;
+            self.wind_down()?;
+        }
+
+        Ok(())
+    }
+
+    // takes the config once for the frame, if it's been swapped since the
+    // last one the settings that can change live are set from the new one
+    fn pick_up_config(&mut self) {
+        let cfg = self.config.load();
+        if Arc::ptr_eq(&cfg, &self.applied) {
+            return;
+        }
+        info!("Applying the new config, pins, spi and the rtc only change on a restart");
+        self.full_refresh_every = cfg.display.full_refresh_every;
+        self.frame_interval = Duration::from_millis(cfg.display.frame_ms);
+        self.timezone = cfg.clock.timezone;
+        self.pet_name = cfg.pet.name.clone();
+        self.step_goal = cfg.steps.goal;
+        self.temperature.cpu_alert = cfg.temperature.cpu_alert_celsius;
+        self.scheduler = TaskRunner::new(&cfg.schedule);
+        // the menu's what changed it while it's up, a new one would lose
+        // the cursor
+        if self.screen != Screen::Mode(DisplayMode::Settings) {
+            self.settings = Menu::new(&cfg);
+        }
+        if let Some(source) = &mut self.spotify {
+            source
+                .tracks
+                .set_ttl(Duration::from_secs(cfg.spotify.track_cache_secs));
+        }
+        self.applied = cfg;
+    }
+
+    // a slideshow for showing it off, goes round durations until Ctrl+C.
+    // buttons and commands don't do anything, and every screen starts with a
+    // full refresh so the last one doesn't ghost through
+    pub fn run_demo(
+        &mut self,
+        pet: &mut Pet,
+        durations: &[(DisplayMode, Duration)],
+    ) -> Result<(), EpaperError> {
+        let running = ctrl_c_flag();
+        let mut frames = RenderLoop::new(self.frame_interval);
+        info!("Running the demo. Press Ctrl+C to exit...");
+
+        for &(mode, duration) in durations.iter().cycle() {
+            if !running.load(Ordering::SeqCst) {
+                break;
+            }
+            self.clear_screen()?;
+            self.screen = Screen::Mode(mode);
+
+            frames.run_for(self, &running, duration, |app| {
+                pet.tick();
+                let now = in_zone(app.clock.now(), app.timezone);
+                app.draw_mode(mode, pet, now);
+                app.draw_status_bar();
+                app.display_partial_update()?;
+                app.maybe_full_refresh()
+            })?;
+        }
+        Ok(())
+    }
+
+    fn draw_mode(&mut self, mode: DisplayMode, pet: &Pet, now: DateTime<FixedOffset>) {
+        match mode {
+            DisplayMode::PetStats => self.draw_pet(pet, &time_string(&now)),
+            DisplayMode::Clock if !is_synced(&now) => self.draw_message(WAITING_FOR_SYNC),
+            DisplayMode::Clock => self.draw_clock(now.time()),
+            DisplayMode::Weather => self.draw_weather(),
+            DisplayMode::StepCount => self.draw_steps(),
+            DisplayMode::SystemInfo => self.draw_system_info(),
+            DisplayMode::Network => self.draw_network(),
+            DisplayMode::Thermometer => self.draw_thermometer(),
+            DisplayMode::Pomodoro => self.draw_pomodoro(),
+            DisplayMode::Playlists => self.draw_playlists(),
+            DisplayMode::Settings => self.draw_settings(),
+            DisplayMode::NowPlaying => self.draw_playing(),
+        }
+    }
+
+    // the pet's face and mood on the left, its stats on the right, the time
+    // and how to exit along the bottom
+    fn draw_pet(&mut self, pet: &Pet, time_str: &str) {
+        self.clear_buffer();
+        face::draw_pet_screen(&mut self.display, pet, &self.pet_name, time_str);
+    }
+
+    // along the top right, over whatever screen is up
+    fn draw_status_bar(&mut self) {
+        let Some(battery) = &self.battery else {
+            return;
+        };
+        let percent = battery.charge_percent();
+        let width = self.display.bounding_box().size.width as i32;
+        let x = width - BATTERY_ICON_WIDTH - 2;
+        // flips every frame while it's nearly empty
+        self.battery_blink = percent < BATTERY_BLINK_PERCENT && !self.battery_blink;
+        draw_battery(&mut self.display, x, 2, percent, self.battery_blink);
+
+        let text = format!("{}%", percent);
+        let text_x = x - text_width(&text, &FONT_6X10) as i32 - 2;
+        draw_text(&mut self.display, &text, text_x, 2);
+    }
+
+    // warns once when the battery gets low, true once it's low enough that
+    // the loop should stop before the pi browns out
+    fn check_battery(&mut self) -> bool {
+        let Some(battery) = &self.battery else {
+            return false;
+        };
+        let percent = battery.charge_percent();
+
+        if percent < CRITICAL_BATTERY_PERCENT {
+            warn!("Battery at {}%, shutting down", percent);
+            return true;
+        }
+
+        if percent >= LOW_BATTERY_PERCENT {
+            self.battery_warned = false;
+        } else if !self.battery_warned {
+            self.battery_warned = true;
+            warn!("Battery low, {}%", percent);
+            if let Ok(mut queue) = self.notifications.lock() {
+                queue.push(Notification::new(
+                    "Battery low",
+                    format!("{}% left, plug in the charger", percent),
+                    NotificationPriority::High,
+                ));
+            }
+        }
+        false
+    }
+
+    // a popup the first time the cpu goes over [temperature]
+    // cpu_alert_celsius, again only once it's cooled off
+    fn check_temperature(&mut self) {
+        let Some(cpu) = self.temperature.check_alert() else {
+            return;
+        };
+        warn!("CPU at {:.1}\u{b0}C", cpu);
+        if let Ok(mut queue) = self.notifications.lock() {
+            queue.push(Notification::new(
+                "CPU hot",
+                format!(
+                    "{:.0}\u{b0}C, over {:.0}\u{b0}C",
+                    cpu, self.temperature.cpu_alert
+                ),
+                NotificationPriority::High,
+            ));
+        }
+    }
+
+    // what's playing, through the track cache. once it's settled on a new
+    // track a ticker says so, and websocket clients get told. they're told
+    // when it stops as well
+    fn check_track(&mut self, pet: &Pet) {
+        let Some(source) = &mut self.spotify else {
+            return;
+        };
+        let was_playing = source.track.is_some();
+        match source
+            .tracks
+            .get_or_fetch(&source.client, &mut source.token)
+        {
+            Ok(track) => source.track = track,
+            Err(e) => {
+                warn!("Failed to fetch now playing: {}", e);
+                return;
+            }
+        }
+        let Some(np) = source.changes.observe(source.track.as_ref()) else {
+            // clients watching get told it's stopped too
+            if was_playing && source.track.is_none() {
+                self.state.publish(self.device_state(pet));
+            }
+            return;
+        };
+        info!("Now playing {} by {}", np.title, np.artist);
+        if let Ok(mut queue) = self.notifications.lock() {
+            queue.push(Notification::new(
+                "Now playing",
+                format!("{} by {}", np.title, np.artist),
+                NotificationPriority::Low,
+            ));
+        }
+        self.state.publish(self.device_state(pet));
+    }
+
+    // what check_track last got from spotify, the full layout with its cover
+    // or the marquee
+    fn draw_playing(&mut self) {
+        let Some(np) = self
+            .spotify
+            .as_ref()
+            .and_then(|source| source.track.clone())
+        else {
+            self.draw_message("No track");
+            return;
+        };
+        if self.applied.spotify.marquee {
+            self.draw_now_playing(&np);
+            return;
+        }
+        // out of source for the draw, the cover borrows it
+        let Some(source) = &mut self.spotify else {
+            return;
+        };
+        let mut arts = std::mem::take(&mut source.arts);
+        let art = arts.get(&np).unwrap_or_else(|e| {
+            warn!("Failed to fetch the album art: {}", e);
+            None
+        });
+        self.display_now_playing(&np, art);
+        if let Some(source) = &mut self.spotify {
+            source.arts = arts;
+        }
+    }
+
+    fn draw_weather(&mut self) {
+        self.clear_buffer();
+
+        let Some(source) = &mut self.weather else {
+            self.draw_message("no [weather] location set");
+            return;
+        };
+        if source
+            .checked_at
+            .is_none_or(|at| at.elapsed() >= WEATHER_RETRY)
+        {
+            source.checked_at = Some(Instant::now());
+            match source.client.fetch(source.latitude, source.longitude) {
+                Ok(data) => source.last = Some(data),
+                Err(e) => warn!("Failed to fetch weather: {}", e),
+            }
+        }
+
+        match source.last {
+            Some(data) => {
+                let bounds = self.content_area();
+                self.draw_widget(&WeatherWidget::new(data), bounds);
+            }
+            None => self.draw_message("no weather yet"),
+        }
+    }
+
+    fn draw_steps(&mut self) {
+        self.clear_buffer();
+
+        let Some(steps) = &self.steps else {
+            self.draw_message("no step counter");
+            return;
+        };
+        let widget = StepWidget::new(steps.steps()).with_goal(self.step_goal);
+        let bounds = self.content_area();
+        self.draw_widget(&widget, bounds);
+    }
+
+    // the screen less a margin and the status bar
+    fn content_area(&self) -> Rectangle {
+        let size = self.display.bounding_box().size;
+        Rectangle::new(
+            Point::new(CONTENT_MARGIN, STATUS_BAR_HEIGHT),
+            Size::new(
+                size.width - 2 * CONTENT_MARGIN as u32,
+                size.height - 2 * STATUS_BAR_HEIGHT as u32,
+            ),
+        )
+    }
+
+    // widgets draw on a PanelDisplay, like Layout does it's a scratch one
+    // and only bounds is copied over
+    fn draw_widget(&mut self, widget: &impl Widget, bounds: Rectangle) {
+        let mut scratch = PanelDisplay::default();
+        scratch.set_rotation(self.display.rotation());
+        let Ok(()) = scratch.clear(Color::White);
+        if let Err(e) = widget.render(&mut scratch, bounds) {
+            warn!("Failed to draw widget: {}", e);
+            return;
+        }
+        let _ = self.display.draw_iter(
+            bounds
+                .points()
+                .filter_map(|p| region::get_pixel(&scratch, p).map(|color| Pixel(p, color))),
+        );
+    }
+
+    // characters of font that fit across the display, less reserved pixels.
+    // wrapping goes by this so it follows the rotation
+    fn chars_across(&self, font: &MonoFont, reserved: u32) -> usize {
+        let width = self
+            .display
+            .bounding_box()
+            .size
+            .width
+            .saturating_sub(reserved);
+        (width / font.character_size.width).max(1) as usize
+    }
+
+    // as many lines as text needs, the lot in the middle of a blank screen
+    fn draw_wrapped_message(&mut self, text: &str) {
+        self.clear_buffer();
+        let lines = wrap_text(text, self.chars_across(&FONT_6X10, 0));
+        let line = text_height(&FONT_6X10) as i32;
+        let height = self.display.bounding_box().size.height as i32;
+        let top = (height - lines.len() as i32 * line) / 2;
+        for (i, text) in lines.iter().enumerate() {
+            let x = aligned_x(&self.display, text, Alignment::Center, &FONT_6X10);
+            draw_text(&mut self.display, text, x, top + i as i32 * line);
+        }
+    }
+
+    fn draw_progress(&mut self) {
+        self.clear_buffer();
+        if let Some(progress) = &mut self.progress {
+            progress::draw_progress(&mut self.display, progress);
+        }
+    }
+
+    // a finished bar goes back to whatever was up before it. a Custom screen
+    // is gone by then, it comes back blank
+    fn dismiss_progress(&mut self, now: Instant) {
+        if self.screen != Screen::Progress
+            || !self.progress.as_ref().is_some_and(|p| p.dismiss_due(now))
+        {
+            return;
+        }
+        self.progress = None;
+        self.screen = self.progress_back_to;
+        if self.screen == Screen::Custom {
+            self.clear_buffer();
+        }
+    }
+
+    // one line in the middle of an otherwise blank screen
+    fn draw_message(&mut self, text: &str) {
+        self.clear_buffer();
+        let x = aligned_x(&self.display, text, Alignment::Center, &FONT_6X10);
+        let y = (self.display.bounding_box().size.height - text_height(&FONT_6X10)) as i32 / 2;
+        draw_text(&mut self.display, text, x, y);
+    }
+
+    // the face on the left of the pet screen, picked from its stats
+    pub fn draw_pet_face(&mut self, pet: &Pet) {
+        face::draw_pet_face(&mut self.display, pet);
+    }
+
+    fn draw_system_info(&mut self) {
+        self.clear_buffer();
+
+        let Some((snapshot, usage)) = self.system.poll() else {
+            self.draw_message("no system info");
+            return;
+        };
+        let widget = SystemInfoWidget::new(snapshot.clone(), usage);
+        let bounds = self.content_area();
+        self.draw_widget(&widget, bounds);
+    }
+
+    fn draw_thermometer(&mut self) {
+        self.clear_buffer();
+
+        let temperatures = self.temperature.poll();
+        let hot = temperatures
+            .cpu
+            .is_some_and(|cpu| cpu >= self.temperature.cpu_alert);
+        let widget = ThermometerWidget::new(temperatures).with_cpu_hot(hot);
+        let bounds = self.content_area();
+        self.draw_widget(&widget, bounds);
+    }
+
+    fn draw_network(&mut self) {
+        self.clear_buffer();
+
+        let Some(info) = self.network.poll() else {
+            self.draw_message("no network info");
+            return;
+        };
+        let widget = NetworkStatusWidget::new(info.clone());
+        let bounds = self.content_area();
+        self.draw_widget(&widget, bounds);
+    }
+
+    // the phase over a big MM:SS in the middle, how many sessions are done
+    // under it
+    fn draw_pomodoro(&mut self) {
+        self.clear_buffer();
+
+        let timer = &self.pomodoro;
+        let mut label = timer.phase().label().to_string();
+        if !timer.is_running() {
+            label.push_str(" (paused)");
+        }
+        let time = timer.format_remaining();
+        let sessions = format!("{}/{} sessions", timer.sessions_done(), timer.session_count);
+
+        let big = text_height(&FONT_10X20) as i32;
+        let small = text_height(&FONT_6X10) as i32;
+        let top = (self.display.bounding_box().size.height as i32 - big - 2 * small - 8) / 2;
+        for (text, y, font) in [
+            (label.as_str(), top, &FONT_6X10),
+            (time.as_str(), top + small + 4, &FONT_10X20),
+            (sessions.as_str(), top + small + big + 8, &FONT_6X10),
+        ] {
+            let x = aligned_x(&self.display, text, Alignment::Center, font);
+            draw_text_styled(
+                &mut self.display,
+                text,
+                x,
+                y,
+                font,
+                Color::Black,
+                Color::White,
+            );
+        }
+    }
+
+    // the menu under the status bar
+    fn draw_settings(&mut self) {
+        self.clear_buffer();
+        self.settings.draw(&mut self.display, STATUS_BAR_HEIGHT);
+    }
+
+    // rest on the settings screen. on Back it leaves, anywhere else the
+    // value changes and takes effect straight away
+    fn select_setting(&mut self) {
+        if self.settings.on_back() {
+            let changes = self.settings.changes();
+            if !changes.is_empty() {
+                info!("Settings changed: {:?}", changes);
+            }
+            // opens fresh next time
+            self.settings = Menu::new(&self.config.load());
+            self.set_mode(self.settings_back_to);
+            return;
+        }
+        let Some(setting) = self.settings.select() else {
+            return;
+        };
+        // pick_up_config puts it into effect at the start of the next frame
+        let mut cfg = DisplayConfig::clone(&self.config.load());
+        setting.apply(&mut cfg);
+        self.config.store(cfg);
+        if let Err(e) = menu::settings_path().and_then(|path| menu::save_setting(&path, &setting)) {
+            warn!("Failed to save the setting: {}", e);
+        }
+    }
+
+    // the playlist's name big, how many tracks and where it is in the list
+    // under it, then the first few tracks
+    fn draw_playlists(&mut self) {
+        self.clear_buffer();
+
+        let Some(source) = &mut self.spotify else {
+            self.draw_message("spotify isn't logged in");
+            return;
+        };
+        if let Err(e) = self.playlists.load(&source.client, &mut source.token) {
+            warn!("Failed to fetch playlists: {}", e);
+        }
+        let Some(playlist) = self.playlists.current() else {
+            self.draw_message(if self.playlists.is_loaded() {
+                "no playlists"
+            } else {
+                "no playlists yet"
+            });
+            return;
+        };
+
+        let margin = CONTENT_MARGIN;
+        let big = text_height(&FONT_10X20) as i32;
+        let small = text_height(&FONT_6X10) as i32;
+        let mut lines = wrap_text(
+            &playlist.name,
+            self.chars_across(&FONT_10X20, 2 * margin as u32),
+        );
+        lines.truncate(2);
+        let (at, of) = self.playlists.position();
+        let count = format!("{} tracks, {}/{}", playlist.track_count, at, of);
+        let wide = self.chars_across(&FONT_6X10, 2 * margin as u32);
+        let tracks: Vec<String> = playlist
+            .tracks
+            .iter()
+            .map(|t| truncate_text(&format!("{} - {}", t.name, t.artist), wide))
+            .collect();
+
+        let mut y = STATUS_BAR_HEIGHT + 2;
+        for line in &lines {
+            draw_text_styled(
+                &mut self.display,
+                line,
+                margin,
+                y,
+                &FONT_10X20,
+                Color::Black,
+                Color::White,
+            );
+            y += big;
+        }
+        y += 2;
+        for line in std::iter::once(&count).chain(&tracks) {
+            draw_text_styled(
+                &mut self.display,
+                line,
+                margin,
+                y,
+                &FONT_6X10,
+                Color::Black,
+                Color::White,
+            );
+            y += small;
+        }
+    }
+
+    fn next_playlist(&mut self) {
+        self.playlists.next();
+        if let Some(source) = &mut self.spotify {
+            if let Err(e) = self
+                .playlists
+                .load_tracks(&source.client, &mut source.token)
+            {
+                warn!("Failed to fetch playlist tracks: {}", e);
+            }
+        }
+    }
+
+    // a toast says how it went, spotify can take a second or so to answer
+    fn playback(&mut self, action: Playback) -> Result<(), EpaperError> {
+        let Some(source) = &mut self.spotify else {
+            return self.show_toast("Spotify isn't logged in", TOAST_DURATION);
+        };
+        let (client, token) = (&source.client, &mut source.token);
+        let result = match action {
+            Playback::Previous => client.previous(token).map(|()| "Previous track"),
+            Playback::Next => client.next(token).map(|()| "Track skipped"),
+            // nothing playing at all counts as paused
+            Playback::PlayPause => match source.tracks.get_or_fetch(client, token) {
+                Ok(Some(np)) if np.is_playing => client.pause(token).map(|()| "Paused"),
+                Ok(_) => client.play(token).map(|()| "Playing"),
+                Err(e) => Err(e),
+            },
+        };
+        // whatever was cached isn't what's playing any more
+        source.tracks.invalidate();
+        let toast = match result {
+            Ok(toast) => toast,
+            Err(SpotifyError::NoActiveDevice) => "No active device",
+            Err(e) => {
+                warn!("Spotify {:?} failed: {}", action, e);
+                "Spotify failed"
+            }
+        };
+        self.show_toast(toast, TOAST_DURATION)
+    }
+
+    // starts the one on screen, a toast says how that went
+    fn play_playlist(&mut self) -> Result<(), EpaperError> {
+        let (Some(source), Some(playlist)) = (&mut self.spotify, self.playlists.current()) else {
+            return Ok(());
+        };
+        let toast = match source.client.play_playlist(&mut source.token, &playlist.id) {
+            Ok(()) => format!("Playing {}", playlist.name),
+            Err(SpotifyError::NoActiveDevice) => "No active device".to_string(),
+            Err(e) => {
+                warn!("Failed to play playlist: {}", e);
+                "Couldn't play it".to_string()
+            }
+        };
+        self.show_toast(&toast, TOAST_DURATION)
+    }
+
+    fn draw_clock(&mut self, time: NaiveTime) {
+        self.clear_buffer();
+
+        let size = self.display.bounding_box().size;
+        let center = Point::new(size.width as i32 / 2, size.height as i32 / 2);
+        draw_analog_clock(&mut self.display, center, size.height / 2 - 4, time);
+    }
+
+    // handles commands and button presses until it's time for the next
+    // frame, whatever's left of the frame's beat. a press that wakes the
+    // screen up doesn't count for anything else
+    fn wait_for_next_frame(
+        &mut self,
+        running: &AtomicBool,
+        frames: &mut RenderLoop,
+        pet: &mut Pet,
+        commands: &mpsc::Receiver<Request>,
+    ) -> Result<(), EpaperError> {
+        let (interval, poll) = if self.panel_asleep {
+            (IDLE_FRAME_INTERVAL, IDLE_BUTTON_POLL)
+        } else {
+            (self.frame_interval, BUTTON_POLL)
+        };
+        frames.set_interval(interval);
+        let next_frame = frames.end_frame();
+        while running.load(Ordering::SeqCst) && Instant::now() < next_frame {
+            // commands go first, someone is waiting on the other end
+            while let Ok(request) = commands.try_recv() {
+                if self.screensaver.activity() {
+                    self.wake()?;
+                }
+                match self.handle_command(request.command, pet) {
+                    Ok(()) => {
+                        let _ = request.reply.send(Ok(()));
+                    }
+                    Err(EpaperError::Command(e)) => {
+                        let _ = request.reply.send(Err(e));
+                    }
+                    Err(e) => {
+                        let _ = request.reply.send(Err(e.to_string()));
+                        return Err(e);
+                    }
+                }
+            }
+
+            for event in self.buttons.button_events()? {
+                if self.screensaver.activity() {
+                    self.wake()?;
+                    continue;
+                }
+                // any button takes down a popup, and does nothing else
+                if self.dismiss_notification()? {
+                    continue;
+                }
+
+                // on the pomodoro screen feed is start/pause and play skips
+                match (event, self.screen) {
+                    (ButtonEvent::Feed, Screen::Mode(DisplayMode::Pomodoro)) => {
+                        self.pomodoro.toggle();
+                        continue;
+                    }
+                    (ButtonEvent::Play, Screen::Mode(DisplayMode::Pomodoro)) => {
+                        self.pomodoro.skip();
+                        continue;
+                    }
+                    // and on the playlists, play is the next one, feed plays it
+                    // and rest goes back
+                    (ButtonEvent::Play, Screen::Mode(DisplayMode::Playlists)) => {
+                        self.next_playlist();
+                        continue;
+                    }
+                    (ButtonEvent::Feed, Screen::Mode(DisplayMode::Playlists)) => {
+                        self.play_playlist()?;
+                        continue;
+                    }
+                    (ButtonEvent::Rest, Screen::Mode(DisplayMode::Playlists)) => {
+                        self.set_mode(self.playlists.back_to);
+                        continue;
+                    }
+                    // on the settings screen feed and play move the cursor,
+                    // rest changes what's under it
+                    (ButtonEvent::Feed, Screen::Mode(DisplayMode::Settings)) => {
+                        self.settings.up();
+                        continue;
+                    }
+                    (ButtonEvent::Play, Screen::Mode(DisplayMode::Settings)) => {
+                        self.settings.down();
+                        continue;
+                    }
+                    (ButtonEvent::Rest, Screen::Mode(DisplayMode::Settings)) => {
+                        self.select_setting();
+                        continue;
+                    }
+                    (ButtonEvent::Feed, _) => {
+                        pet.feed();
+                        self.show_toast("Fed!", TOAST_DURATION)?;
+                    }
+                    (ButtonEvent::Play, _) => {
+                        pet.play();
+                        self.show_toast("Played!", TOAST_DURATION)?;
+                    }
+                    (ButtonEvent::Rest, _) => {
+                        pet.rest();
+                        self.show_toast("Resting", TOAST_DURATION)?;
+                    }
+                    // held down they're a spotify remote. rest is only next
+                    // track on the spotify screens, anywhere else it clears
+                    // the ghosting
+                    (ButtonEvent::FeedHeld, _) => {
+                        self.playback(Playback::Previous)?;
+                        continue;
+                    }
+                    (ButtonEvent::PlayHeld, _) => {
+                        self.playback(Playback::PlayPause)?;
+                        continue;
+                    }
+                    (
+                        ButtonEvent::RestHeld,
+                        Screen::Mode(DisplayMode::NowPlaying | DisplayMode::Playlists),
+                    ) => {
+                        self.playback(Playback::Next)?;
+                        continue;
+                    }
+                    (ButtonEvent::RestHeld, _) => {
+                        self.clear_and_redraw(pet)?;
+                        continue;
+                    }
+                }
+                self.state.publish(self.device_state(pet));
+            }
+            // woken up, back to the normal pace
+            if !self.panel_asleep && interval == IDLE_FRAME_INTERVAL {
+                break;
+            }
+            thread::sleep(poll);
+        }
+        pet.tick();
+        Ok(())
+    }
+
+    // text, images and clears stay on screen until another screen is picked
+    fn handle_command(
+        &mut self,
+        command: DisplayCommand,
+        pet: &mut Pet,
+    ) -> Result<(), EpaperError> {
+        match command {
+            DisplayCommand::DrawText { text, x, y, size } => {
+                if self.screen != Screen::Custom {
+                    self.clear_buffer();
+                    self.screen = Screen::Custom;
+                }
+                draw_text_sized(
+                    &mut self.display,
+                    &text,
+                    x,
+                    y,
+                    size,
+                    Color::Black,
+                    Color::White,
+                );
+                self.display_partial_update()
+            }
+            DisplayCommand::DrawImage { bitmap } => {
+                let size = self.display.bounding_box().size;
+                let expected = (size.width.div_ceil(8) * size.height) as usize;
+                if bitmap.len() != expected {
+                    return Err(EpaperError::Command(format!(
+                        "image has to be {}x{}, {} bytes, got {}",
+                        size.width,
+                        size.height,
+                        expected,
+                        bitmap.len()
+                    )));
+                }
+
+                self.clear_buffer();
+                self.draw_image_1bit(&bitmap, 0, 0, size.width, size.height);
+                self.screen = Screen::Custom;
+                self.display_partial_update()
+            }
+            DisplayCommand::DrawQr { data, module_size } => {
+                let size = qr::qr_size(&data, module_size)? as i32;
+                let screen = self.display.bounding_box().size;
+                self.clear_buffer();
+                qr::draw_qr_code(
+                    &mut self.display,
+                    &data,
+                    (screen.width as i32 - size) / 2,
+                    (screen.height as i32 - size) / 2,
+                    module_size,
+                )?;
+                self.screen = Screen::Custom;
+                self.display_partial_update()
+            }
+            DisplayCommand::Clear => {
+                self.clear_buffer();
+                self.screen = Screen::Custom;
+                self.display_partial_update()
+            }
+            DisplayCommand::Refresh => self.full_refresh(),
+            DisplayCommand::ClearScreen => self.clear_and_redraw(pet),
+            DisplayCommand::Progress {
+                label,
+                current,
+                total,
+            } => {
+                if self.screen != Screen::Progress {
+                    self.progress_back_to = self.screen;
+                    self.screen = Screen::Progress;
+                }
+                self.progress = Some(Progress::new(label, current, total));
+                self.draw_progress();
+                self.display_partial_update()
+            }
+            DisplayCommand::ShowSpotify => {
+                if self.spotify.is_none() {
+                    return Err(EpaperError::Command(
+                        "spotify isn't logged in, there's no token".to_string(),
+                    ));
+                }
+                self.set_mode(DisplayMode::NowPlaying);
+                Ok(())
+            }
+            DisplayCommand::ShowPlaylists => {
+                if self.spotify.is_none() {
+                    return Err(EpaperError::Command(
+                        "spotify isn't logged in, there's no token".to_string(),
+                    ));
+                }
+                self.set_mode(DisplayMode::Playlists);
+                Ok(())
+            }
+            DisplayCommand::ShowSettings => {
+                self.set_mode(DisplayMode::Settings);
+                Ok(())
+            }
+            DisplayCommand::ShowPet => {
+                self.set_mode(DisplayMode::PetStats);
+                Ok(())
+            }
+            DisplayCommand::ShowClock => {
+                self.set_mode(DisplayMode::Clock);
+                Ok(())
+            }
+            DisplayCommand::FeedPet => {
+                pet.feed();
+                Ok(())
+            }
+            DisplayCommand::PlayWithPet => {
+                pet.play();
+                Ok(())
+            }
+            DisplayCommand::PomodoroStart => {
+                self.pomodoro.start();
+                self.set_mode(DisplayMode::Pomodoro);
+                Ok(())
+            }
+            DisplayCommand::PomodoroPause => {
+                self.pomodoro.pause();
+                Ok(())
+            }
+            DisplayCommand::PomodoroSkip => {
+                self.pomodoro.skip();
+                Ok(())
+            }
+        }
+    }
+
+    // idles or blanks the panel when the screensaver's timeouts run out
+    fn wind_down(&mut self) -> Result<(), EpaperError> {
+        match self.screensaver.due() {
+            Some(Stage::Idle) => {
+                info!("Nothing happening, display going to sleep");
+                self.enter_sleep()
+            }
+            Some(Stage::Blank) => self.blank(),
+            Some(Stage::Awake) | None => Ok(()),
+        }
+    }
+
+    // puts the panel to sleep as it is, the frame stays on it. no spi until
+    // wake(), and the loop slows right down meanwhile
+    pub fn enter_sleep(&mut self) -> Result<(), EpaperError> {
+        if self.panel_asleep {
+            return Ok(());
+        }
+        self.epd.sleep()?;
+        self.panel_asleep = true;
+        Ok(())
+    }
+
+    // screensaver kicked in, one full refresh to white (or [display]
+    // idle_image) to clear any ghosting then the panel sleeps. the buffer
+    // keeps the last frame for wake()
+    fn blank(&mut self) -> Result<(), EpaperError> {
+        if self.panel_asleep {
+            self.epd.wake_up()?;
+            self.panel_asleep = false;
+        }
+        self.set_refresh(RefreshLut::Full)?;
+        match &self.idle_image {
+            Some(picture) => {
+                let mut scratch = PanelDisplay::default();
+                scratch.set_rotation(self.display.rotation());
+                let Ok(()) = scratch.clear(Color::White);
+                draw_sprite(&mut scratch, &picture.sprite(), 0, 0);
+                self.epd.update_and_display_frame(scratch.buffer())?;
+            }
+            None => {
+                self.epd.clear_frame()?;
+                self.epd.display_frame()?;
+            }
+        }
+        self.front.invalidate();
+        self.enter_sleep()
+    }
+
+    // back from sleep or the screensaver with a full refresh of whatever
+    // was on screen before it
+    pub fn wake(&mut self) -> Result<(), EpaperError> {
+        if self.panel_asleep {
+            self.epd.wake_up()?;
+            self.panel_asleep = false;
+        }
+        self.full_refresh()
+    }
+
+    // clear to white with a full refresh to get rid of ghosting, then go back
+    // to quick refreshes. the next frame redraws whatever was on screen
+    pub fn clear_screen(&mut self) -> Result<(), EpaperError> {
+        self.set_refresh(RefreshLut::Full)?;
+        self.clear_buffer();
+        self.epd.update_and_display_frame(self.display.buffer())?;
+        self.front.commit(self.display.buffer());
+        self.display.take_dirty();
+        self.partial_refreshes = 0;
+        self.set_refresh(RefreshLut::Quick)
+    }
+
+    // clear_screen's full refresh, then whatever's up drawn over the blank
+    // and sent. a custom screen was only what a command drew, it stays blank
+    fn clear_and_redraw(&mut self, pet: &Pet) -> Result<(), EpaperError> {
+        self.clear_screen()?;
+        match self.screen {
+            Screen::Mode(mode) => {
+                let now = in_zone(self.clock.now(), self.timezone);
+                self.draw_mode(mode, pet, now);
+                self.draw_status_bar();
+            }
+            Screen::Progress => self.draw_progress(),
+            Screen::Custom => return Ok(()),
+        }
+        self.update_frame()
+    }
+
+    // once enough partial refreshes have piled up, redraw the current frame
+    // with the full LUT to clear the ghosting they leave behind
+    pub fn maybe_full_refresh(&mut self) -> Result<(), EpaperError> {
+        if self.partial_refreshes < self.full_refresh_every {
+            return Ok(());
+        }
+        self.full_refresh()
+    }
+
+    // redraw the current frame with the full LUT then go back to quick
+    fn full_refresh(&mut self) -> Result<(), EpaperError> {
+        self.with_overlay(Self::send_full_refresh)
+    }
+
+    fn send_full_refresh(&mut self) -> Result<(), EpaperError> {
+        self.set_refresh(RefreshLut::Full)?;
+        let started = Instant::now();
+        self.epd.clear_frame()?;
+        self.epd.update_and_display_frame(self.display.buffer())?;
+        let took = started.elapsed();
+        logger::record_refresh(took);
+        debug!("Full refresh took {:?}", took);
+        self.front.commit(self.display.buffer());
+        self.display.take_dirty();
+        self.partial_refreshes = 0;
+        self.set_refresh(RefreshLut::Quick)
+    }
+
+    fn set_refresh(&mut self, refresh: RefreshLut) -> Result<(), EpaperError> {
+        self.epd.set_refresh(refresh)?;
+        self.refresh = refresh;
+        Ok(())
+    }
+
+    // push only `area` (display coordinates) of the buffer to the panel.
+    // anything drawn outside it since the last update isn't shown, and
+    // neither are notifications, display_partial_update adds those
+    pub fn update_region(&mut self, area: Rectangle) -> Result<(), EpaperError> {
+        // a full LUT refresh redraws the whole panel anyway
+        if self.refresh != RefreshLut::Quick {
+            return self.send_frame();
+        }
+
+        let Some(window) = region::native_window(&area, self.display.rotation()) else {
+            return Ok(());
+        };
+        self.update_window(&window)
+    }
+
+    fn update_window(&mut self, window: &Window) -> Result<(), EpaperError> {
+        let started = Instant::now();
+        self.epd.update_window(self.display.buffer(), window)?;
+        let took = started.elapsed();
+        logger::record_refresh(took);
+        debug!("Partial refresh of {:?} took {:?}", window, took);
+        // the controller's base buffer is the whole frame now, not just the
+        // window, so that's what the panel is diffed against from here
+        self.front.commit(self.display.buffer());
+        self.display.take_dirty();
+
+        self.partial_refreshes += 1;
+        Ok(())
+    }
+
+    // push whatever changed since the last refresh, nothing if nothing did
+    pub fn display_partial_update(&mut self) -> Result<(), EpaperError> {
+        self.with_overlay(Self::send_changed)
+    }
+
+    fn send_changed(&mut self) -> Result<(), EpaperError> {
+        // a full LUT refresh redraws the whole panel anyway
+        if self.refresh != RefreshLut::Quick {
+            return self.send_frame();
+        }
+        // drawing that ended up the same as what's showing sends nothing
+        match self.front.changed(self.display.buffer()) {
+            Some(window)
+                if window.width * window.height * 100 > WIDTH * HEIGHT * WHOLE_FRAME_PERCENT =>
+            {
+                self.send_frame()
+            }
+            Some(window) => self.update_window(&window),
+            None => {
+                self.display.take_dirty();
+                Ok(())
+            }
+        }
+    }
+
+    // the frame that's been drawn, a bit at a time, see transition::Transition
+    fn play_transition(&mut self, transition: Transition) -> Result<(), EpaperError> {
+        self.with_overlay(|app| match transition {
+            Transition::Instant => app.send_changed(),
+            Transition::Wipe(direction) => app.send_wipe(direction),
+            Transition::Dissolve => app.send_dissolve(),
+        })
+    }
+
+    // one window refresh per strip. the buffer's all the new frame, only
+    // the strip of it gets shown each time
+    fn send_wipe(&mut self, direction: Direction) -> Result<(), EpaperError> {
+        if self.refresh != RefreshLut::Quick {
+            return self.send_frame();
+        }
+        let screen = self.display.bounding_box();
+        for step in 1..=transition::STEPS {
+            let strip = transition::wipe_strip(screen, direction, step);
+            if let Some(window) = region::native_window(&strip, self.display.rotation()) {
+                self.update_window(&window)?;
+            }
+        }
+        Ok(())
+    }
+
+    // the pixels the old and new frames disagree on (their XOR) go over in
+    // STEPS partial refreshes, the buffer put back to the old frame for the
+    // ones that haven't dissolved yet. the last step is the new frame exactly
+    fn send_dissolve(&mut self) -> Result<(), EpaperError> {
+        let old = self.front.front().to_vec();
+        let new = self.display.buffer().to_vec();
+        // nothing known on the panel to dissolve from
+        if old.len() != new.len() || self.refresh != RefreshLut::Quick {
+            return self.send_changed();
+        }
+
+        let rotation = self.display.rotation();
+        let changed: Vec<(Point, Color, Color)> = self
+            .display
+            .bounding_box()
+            .points()
+            .filter_map(|p| {
+                let before = region::pixel_at(&old, rotation, p)?;
+                let after = region::pixel_at(&new, rotation, p)?;
+                (before != after).then_some((p, before, after))
+            })
+            .collect();
+        for step in 1..=transition::STEPS {
+            let pixels = changed.iter().map(|&(p, before, after)| {
+                Pixel(
+                    p,
+                    if transition::dissolved(p, step) {
+                        after
+                    } else {
+                        before
+                    },
+                )
+            });
+            let Ok(()) = self.display.draw_iter(pixels);
+            self.send_changed()?;
+        }
+        Ok(())
+    }
+
+    // push the whole buffer to the panel
+    pub fn update_frame(&mut self) -> Result<(), EpaperError> {
+        self.with_overlay(Self::send_frame)
+    }
+
+    fn send_frame(&mut self) -> Result<(), EpaperError> {
+        let started = Instant::now();
+        self.epd.update_and_display_frame(self.display.buffer())?;
+        let took = started.elapsed();
+        logger::record_refresh(took);
+        debug!("Frame ({:?} LUT) took {:?}", self.refresh, took);
+        self.front.commit(self.display.buffer());
+        self.display.take_dirty();
+        if self.refresh == RefreshLut::Quick {
+            self.partial_refreshes += 1;
+        }
+        Ok(())
+    }
+
+    pub fn partial_refreshes(&self) -> u32 {
+        self.partial_refreshes
+    }
+
+    // white all over. clearing the buffer can't fail, there's no error to
+    // log, the pattern says so
+    fn clear_buffer(&mut self) {
+        let Ok(()) = self.display.clear(Color::White);
+    }
+
+    // clone it into anything that wants to put up alerts
+    pub fn notifications(&self) -> SharedQueue {
+        self.notifications.clone()
+    }
+
+    // clone it into anything that wants to swap the config
+    pub fn config(&self) -> SharedConfig {
+        self.config.clone()
+    }
+
+    // clone it into anything that wants to watch the pet and the battery
+    pub fn state(&self) -> SharedState {
+        self.state.clone()
+    }
+
+    fn device_state(&self, pet: &Pet) -> DeviceState {
+        DeviceState {
+            pet: PetState::from(pet),
+            now_playing: self
+                .spotify
+                .as_ref()
+                .and_then(|source| source.track.clone()),
+            battery: self.battery.as_ref().map(|b| b.charge_percent()),
+        }
+    }
+
+    fn has_notification(&self, priority: NotificationPriority) -> bool {
+        self.notifications
+            .lock()
+            .is_ok_and(|queue| queue.current(priority).is_some())
+    }
+
+    // msg in a box near the top for duration, then it's gone by the next
+    // frame. only the box is refreshed, either way. one already up finishes
+    // first, this one waits its turn
+    pub fn show_toast(&mut self, msg: &str, duration: Duration) -> Result<(), EpaperError> {
+        if let Ok(mut queue) = self.notifications.lock() {
+            queue.push(Notification::toast(msg, duration));
+        }
+        self.display_partial_update()
+    }
+
+    // true if there was a popup to take down
+    fn dismiss_notification(&mut self) -> Result<bool, EpaperError> {
+        let dismissed = self
+            .notifications
+            .lock()
+            .is_ok_and(|mut queue| queue.dismiss());
+        if dismissed {
+            self.display_partial_update()?;
+        }
+        Ok(dismissed)
+    }
+
+    // notifications only ever go to the panel, they never stay in the
+    // buffer. they're drawn just before a push and what was under them is
+    // put back straight after, so the screens don't need to know about them
+    fn with_overlay(
+        &mut self,
+        push: impl FnOnce(&mut Self) -> Result<(), EpaperError>,
+    ) -> Result<(), EpaperError> {
+        let (high, toast, low) = match self.notifications.lock() {
+            Ok(mut queue) => {
+                queue.drop_expired();
+                (
+                    queue.current(NotificationPriority::High).cloned(),
+                    queue.current(NotificationPriority::Toast).cloned(),
+                    queue.current(NotificationPriority::Low).cloned(),
+                )
+            }
+            Err(_) => (None, None, None),
+        };
+
+        // the panel still has the last overlay on it, front knows that so
+        // the next diff covers it whether it's still up or not
+        let mut under = Vec::new();
+        if let Some(n) = &low {
+            let area = self.ticker_area();
+            under.push(self.save_area(area));
+            self.draw_ticker(n, area);
+        }
+        if let Some(n) = &toast {
+            let area = self.toast_area(&n.body);
+            under.push(self.save_area(area));
+            self.draw_toast(n, area);
+        }
+        if let Some(n) = &high {
+            let area = self.popup_area();
+            under.push(self.save_area(area));
+            self.draw_popup(n, area);
+        }
+        let result = push(self);
+
+        for (area, pixels) in under.into_iter().rev() {
+            let _ = self
+                .display
+                .draw_iter(area.points().zip(pixels).map(|(p, c)| Pixel(p, c)));
+        }
+        // putting the screen back isn't a change, the panel is meant to
+        // keep showing the overlay
+        self.display.take_dirty();
+        result
+    }
+
+    fn save_area(&self, area: Rectangle) -> (Rectangle, Vec<Color>) {
+        let pixels = area
+            .points()
+            .map(|p| region::get_pixel(&*self.display, p).unwrap_or(Color::White))
+            .collect();
+        (area, pixels)
+    }
+
+    fn popup_area(&self) -> Rectangle {
+        let size = self.display.bounding_box().size;
+        let popup = Size::new(POPUP_WIDTH.min(size.width), POPUP_HEIGHT.min(size.height));
+        Rectangle::new(
+            Point::new(
+                (size.width - popup.width) as i32 / 2,
+                (size.height - popup.height) as i32 / 2,
+            ),
+            popup,
+        )
+    }
+
+    // bordered box, title in the middle at the top and the body under it
+    fn draw_popup(&mut self, n: &Notification, area: Rectangle) {
+        let _ = area
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(Color::White)
+                    .stroke_color(Color::Black)
+                    .stroke_width(2)
+                    .build(),
+            )
+            .draw(&mut self.display);
+
+        let title_font = FontSize::Medium.font();
+        let x = area.top_left.x
+            + (area.size.width as i32 - text_width(&n.title, title_font) as i32) / 2;
+        let y = area.top_left.y + 5;
+        draw_text_styled(
+            &mut self.display,
+            &n.title,
+            x.max(area.top_left.x + 4),
+            y,
+            title_font,
+            Color::Black,
+            Color::White,
+        );
+
+        let body_font = FontSize::Small.font();
+        let chars = (area.size.width as usize - 8) / body_font.character_size.width as usize;
+        let mut y = y + text_height(title_font) as i32 + 2;
+        for line in wrap_text(&n.body, chars).iter().take(3) {
+            draw_text_styled(
+                &mut self.display,
+                line,
+                area.top_left.x + 4,
+                y,
+                body_font,
+                Color::Black,
+                Color::White,
+            );
+            y += text_height(body_font) as i32;
+        }
+    }
+
+    // as wide as the text, in the middle just under the status bar
+    fn toast_area(&self, text: &str) -> Rectangle {
+        let size = self.display.bounding_box().size;
+        let font = FontSize::Medium.font();
+        let width = (text_width(text, font) + 2 * TOAST_PADDING).min(size.width);
+        let height = text_height(font) + 2 * TOAST_PADDING;
+        Rectangle::new(
+            Point::new((size.width - width) as i32 / 2, STATUS_BAR_HEIGHT + 2),
+            Size::new(width, height),
+        )
+    }
+
+    fn draw_toast(&mut self, n: &Notification, area: Rectangle) {
+        let _ = area
+            .into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(Color::White)
+                    .stroke_color(Color::Black)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(&mut self.display);
+        let offset = TOAST_PADDING as i32;
+        draw_text_styled(
+            &mut self.display,
+            &n.body,
+            area.top_left.x + offset,
+            area.top_left.y + offset,
+            FontSize::Medium.font(),
+            Color::Black,
+            Color::White,
+        );
+    }
+
+    fn ticker_area(&self) -> Rectangle {
+        let size = self.display.bounding_box().size;
+        Rectangle::new(
+            Point::new(0, (size.height - TICKER_HEIGHT) as i32),
+            Size::new(size.width, TICKER_HEIGHT),
+        )
+    }
+
+    // "title: body" along the bottom with a line over it, scrolling when it
+    // doesn't fit
+    fn draw_ticker(&mut self, n: &Notification, area: Rectangle) {
+        let _ = area
+            .into_styled(PrimitiveStyle::with_fill(Color::White))
+            .draw(&mut self.display);
+        let _ = Line::new(
+            area.top_left,
+            area.top_left + Point::new(area.size.width as i32 - 1, 0),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+        .draw(&mut self.display);
+
+        let text = format!("{}: {}", n.title, n.body);
+        let font = FontSize::Small.font();
+        let text_w = text_width(&text, font) as i32;
+        let y = area.top_left.y + 2;
+        if text_w <= area.size.width as i32 {
+            draw_text_styled(
+                &mut self.display,
+                &text,
+                0,
+                y,
+                font,
+                Color::Black,
+                Color::White,
+            );
+            return;
+        }
+
+        // two copies so the start comes round again behind the end
+        let span = text_w + MARQUEE_GAP;
+        let x = -(self.ticker_offset % span);
+        for x in [x, x + span] {
+            draw_text_styled(
+                &mut self.display,
+                &text,
+                x,
+                y,
+                font,
+                Color::Black,
+                Color::White,
+            );
+        }
+    }
+
+    // title on the top half, artist and a progress bar on the bottom half.
+    // episodes get a little podcast icon in front of the show name. art is
+    // the cover from spotify::ArtCache, the title goes to the right of it.
+    // nothing is sent to the panel
+    fn display_now_playing(&mut self, np: &NowPlaying, art: Option<&AlbumArt>) {
+        self.clear_buffer();
+
+        // the artist and album wrap as short as the title does without art
+        let wrap = self.chars_across(&FONT_10X20, 0);
+        let mut title_wrap = wrap;
+        let mut title_x = 0;
+        if let Some(art) = art {
+            draw_album_art(&mut self.display, art, 0, 0);
+            // and a few pixels' gap after the art
+            title_x = spotify::ART_SIZE as i32 + 4;
+            title_wrap = self.chars_across(&FONT_10X20, title_x as u32);
+        }
+
+        // top half, up to 3 lines of title
+        for (n, line) in wrap_text(&np.title, title_wrap).iter().take(3).enumerate() {
+            draw_text_styled(
+                &mut self.display,
+                line,
+                title_x,
+                n as i32 * text_height(&FONT_10X20) as i32,
+                &FONT_10X20,
+                Color::Black,
+                Color::White,
+            );
+        }
+
+        // bottom half, artist (or show) and album (or publisher)
+        let mut x = 0;
+        if np.kind == PlayingKind::Episode {
+            let stroke = PrimitiveStyle::with_stroke(Color::Black, 1);
+            let _ = Circle::new(Point::new(2, 64), 6)
+                .into_styled(PrimitiveStyle::with_fill(Color::Black))
+                .draw(&mut self.display);
+            let _ = Line::new(Point::new(5, 70), Point::new(5, 73))
+                .into_styled(stroke)
+                .draw(&mut self.display);
+            let _ = Line::new(Point::new(2, 73), Point::new(8, 73))
+                .into_styled(stroke)
+                .draw(&mut self.display);
+            x = 12;
+        }
+        for (n, line) in wrap_text(&np.artist, wrap).iter().take(2).enumerate() {
+            draw_text_styled(
+                &mut self.display,
+                line,
+                x,
+                64 + n as i32 * 10,
+                &FONT_6X10,
+                Color::Black,
+                Color::White,
+            );
+        }
+        if let Some(album) = wrap_text(&np.album, wrap).first() {
+            draw_text_styled(
+                &mut self.display,
+                album,
+                0,
+                86,
+                &FONT_6X10,
+                Color::Black,
+                Color::White,
+            );
+        }
+
+        // progress bar along the bottom with the times next to it
+        draw_progress_bar(&mut self.display, 0, 106, 160, 8, np.progress());
+
+        let times = format!(
+            "{} / {}",
+            format_ms(np.progress_ms),
+            format_ms(np.duration_ms)
+        );
+        let x = aligned_x(&self.display, &times, Alignment::Right, &FONT_6X10);
+        draw_text_styled(
+            &mut self.display,
+            &times,
+            x,
+            105,
+            &FONT_6X10,
+            Color::Black,
+            Color::White,
+        );
+    }
+
+    // blit a 1 bit image into the buffer, packed like a Sprite (rows padded
+    // to whole bytes, set bits black). nothing is sent to the panel
+    pub fn draw_image_1bit(&mut self, data: &[u8], x: i32, y: i32, w: u32, h: u32) {
+        let image = Sprite {
+            width: w,
+            height: h,
+            data,
+        };
+        draw_sprite(&mut self.display, &image, x, y);
+    }
+
+    // one line "title - artist" with a note in front, scrolling along a bit
+    // every call when it doesn't fit. paused freezes the scroll and inverts
+    // the progress bar. [spotify] marquee picks it over display_now_playing.
+    // nothing is sent to the panel
+    fn draw_now_playing(&mut self, np: &NowPlaying) {
+        let text = format!("{} - {}", np.title, np.artist);
+        if text != self.marquee_text {
+            self.marquee_text = text.clone();
+            self.marquee_offset = 0;
+        }
+
+        self.clear_buffer();
+
+        let size = self.display.bounding_box().size;
+        let (width, height) = (size.width as i32, size.height as i32);
+        let line_height = text_height(&FONT_10X20) as i32;
+        let y = (height - line_height) / 2;
+
+        let text_w = text_width(&text, &FONT_10X20) as i32;
+        if text_w <= width - MARQUEE_LEFT {
+            draw_text_styled(
+                &mut self.display,
+                &text,
+                MARQUEE_LEFT,
+                y,
+                &FONT_10X20,
+                Color::Black,
+                Color::White,
+            );
+        } else {
+            // two copies so the start comes round again behind the end
+            let span = text_w + MARQUEE_GAP;
+            let x = MARQUEE_LEFT - self.marquee_offset;
+            for x in [x, x + span] {
+                draw_text_styled(
+                    &mut self.display,
+                    &text,
+                    x,
+                    y,
+                    &FONT_10X20,
+                    Color::Black,
+                    Color::White,
+                );
+            }
+            if np.is_playing {
+                self.marquee_offset = (self.marquee_offset + MARQUEE_STEP) % span;
+            }
+
+            // the text scrolls in from under the note
+            let _ = Rectangle::new(
+                Point::new(0, y),
+                Size::new(MARQUEE_LEFT as u32, line_height as u32),
+            )
+            .into_styled(PrimitiveStyle::with_fill(Color::White))
+            .draw(&mut self.display);
+        }
+
+        // note: head, stem and flag
+        let stroke = PrimitiveStyle::with_stroke(Color::Black, 1);
+        let _ = Circle::new(Point::new(1, y + 12), 5)
+            .into_styled(PrimitiveStyle::with_fill(Color::Black))
+            .draw(&mut self.display);
+        let _ = Line::new(Point::new(5, y + 3), Point::new(5, y + 14))
+            .into_styled(stroke)
+            .draw(&mut self.display);
+        let _ = Line::new(Point::new(5, y + 3), Point::new(9, y + 6))
+            .into_styled(stroke)
+            .draw(&mut self.display);
+
+        // thin bar along the bottom, inverted while paused
+        let bar_y = height - 6;
+        if np.is_playing {
+            draw_progress_bar(&mut self.display, 0, bar_y, width as u32, 5, np.progress());
+        } else {
+            draw_progress_bar(&mut self.display, 0, bar_y, width as u32, 5, 1.0);
+            let inner = (width - 2) as f32 * np.progress();
+            let _ = Rectangle::new(Point::new(1, bar_y + 1), Size::new(inner.round() as u32, 3))
+                .into_styled(PrimitiveStyle::with_fill(Color::White))
+                .draw(&mut self.display);
+        }
+    }
+
+    // [display] splash_image or the default, for SPLASH_HOLD
+    fn show_splash(&mut self, running: &AtomicBool) -> Result<(), EpaperError> {
+        self.clear_buffer();
+        splash::draw_splash(&mut self.display, self.splash.as_ref());
+        self.update_frame()?;
+
+        let start = Instant::now();
+        while running.load(Ordering::SeqCst) && start.elapsed() < SPLASH_HOLD {
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    // interrupts whatever is on screen, the next frame of the loop restores it
+    fn show_test_pattern(&mut self, running: &AtomicBool) -> Result<(), EpaperError> {
+        info!("Received SIGUSR2, showing test pattern...");
+        let dead = &self.applied.display.dead_pixels;
+        info!("Self test: {}", diag::stuck_pixel_report(dead));
+        diag::draw_test_pattern(&mut self.display, dead);
+        self.update_frame()?;
+
+        let start = std::time::Instant::now();
+        while running.load(Ordering::SeqCst) && start.elapsed() < TEST_PATTERN_HOLD {
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        Ok(())
+    }
+
+    pub fn shutdown(mut self) -> Result<(), EpaperError> {
+        info!("Shutting down display...");
+        self.release()
+    }
+
+    // sleep the display, shared by shutdown() and drop. only runs once.
+    // the pins clean up after themselves when they drop
+    fn release(&mut self) -> Result<(), EpaperError> {
+        if self.released {
+            return Ok(());
+        }
+        self.released = true;
+
+        // the screensaver may have put it to sleep already
+        self.enter_sleep()
+    }
+}
+
+impl<D: EpdDisplay, E: EpdDriver> Drop for GenericEpaperApp<D, E> {
+    fn drop(&mut self) {
+        if let Err(e) = self.release() {
+            error!("Failed to clean up display: {}", e);
+            logger::record_error(format!("Failed to clean up display: {}", e));
+        }
+    }
+}
+
+// m:ss for track times
+fn format_ms(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+// set to false by Ctrl+C, which every loop then winds down on so the panel
+// gets put to sleep and the pet saved. one handler for the whole process,
+// whichever loop asks first puts it in. a second Ctrl+C is for when the
+// wind down is stuck, it exits there and then
+fn ctrl_c_flag() -> Arc<AtomicBool> {
+    static RUNNING: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    RUNNING
+        .get_or_init(|| {
+            let running = Arc::new(AtomicBool::new(true));
+            let r = running.clone();
+            ctrlc::set_handler(move || {
+                if r.swap(false, Ordering::SeqCst) {
+                    info!("Received Ctrl+C, shutting down... (again to force it)");
+                } else {
+                    warn!("Received Ctrl+C again, exiting without cleaning up");
+                    std::process::exit(130);
+                }
+            })
+            .expect("Error setting Ctrl+C handler");
+            running
+        })
+        .clone()
+}
+
+// a broken save shouldn't keep the display from coming up
+fn load_pet() -> Pet {
+    let loaded = pet::state_path().and_then(|path| Pet::load(&path));
+    match loaded {
+        Ok(Some(pet)) => {
+            info!(
+                "Loaded pet: hunger {}, happiness {}, energy {}",
+                pet.hunger, pet.happiness, pet.energy
+            );
+            pet
+        }
+        Ok(None) => {
+            info!("No saved pet, starting a new one");
+            Pet::new()
+        }
+        Err(e) => {
+            warn!("Failed to load pet, starting a new one: {}", e);
+            logger::record_error(format!("Failed to load pet: {}", e));
+            Pet::new()
+        }
+    }
+}
+
+fn save_pet(pet: &mut Pet) {
+    pet.tick();
+    if let Err(e) = pet::state_path().and_then(|path| pet.save(&path)) {
+        error!("Failed to save pet: {}", e);
+        logger::record_error(format!("Failed to save pet: {}", e));
+    }
+}
+
+// the panel and buttons plus whatever else the config turns on. a battery
+// or step counter that doesn't answer isn't worth stopping for, the app
+// just runs without it
+fn open_app(cfg: &DisplayConfig) -> Result<EpaperApp, EpaperError> {
+    let backend = Backend::new()?;
+    let mut app = open_panel_on(cfg, &backend)?;
+
+    // validate() made sure it fits in a u8
+    if let Some(pin) = cfg.gpio.buzzer_pin {
+        match backend.output_pin(pin as u8, 0) {
+            Ok(pin) => app = app.with_buzzer(Buzzer::new(pin)),
+            Err(e) => {
+                error!("Failed to set up the buzzer: {}", e);
+                logger::record_error(format!("Failed to set up the buzzer: {}", e));
+            }
+        }
+    }
+
+    if cfg.battery.enabled {
+        match BatteryMonitor::start(&cfg.battery) {
+            Ok(battery) => app = app.with_battery(battery),
+            Err(e) => {
+                error!("Failed to start battery monitor: {}", e);
+                logger::record_error(format!("Failed to start battery monitor: {}", e));
+            }
+        }
+    }
+
+    if cfg.steps.enabled {
+        match StepCounter::start(&cfg.steps.i2c_path) {
+            Ok(steps) => app = app.with_steps(steps),
+            Err(e) => {
+                error!("Failed to start step counter: {}", e);
+                logger::record_error(format!("Failed to start step counter: {}", e));
+            }
+        }
+    }
+
+    if let (Some(latitude), Some(longitude)) = (cfg.weather.latitude, cfg.weather.longitude) {
+        app = app.with_weather(latitude, longitude);
+    }
+
+    // refreshed now if it's run out, see Client::login for when it's the
+    // browser flow instead
+    let login = spotify::get_client_data().and_then(|client| {
+        let client = client.with_redirect_port(cfg.spotify.redirect_port);
+        let token = client.login(&cfg.spotify.token_path)?;
+        Ok((client, token))
+    });
+    match login {
+        Ok((client, token)) => app = app.with_spotify(client, token),
+        Err(e) => info!("Spotify is off: {}", e),
+    }
+
+    if cfg.display.resume_last_screen {
+        match scheduler::mode_path().and_then(|path| scheduler::load_mode(&path)) {
+            Ok(Some(mode)) => {
+                let mode = app.resumable(mode);
+                app = app.with_mode(mode);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to load display mode: {}", e),
+        }
+    }
+    Ok(app)
+}
+
+// the way out of a loop however it ended: the pet's saved first, it matters
+// most, then the panel sleeps and the pins unexport as the app drops. a
+// refresh that was going when Ctrl+C came has finished by now, the loops
+// only look at the flag between frames. the loop's error wins over one
+// from shutting down
+fn finish(
+    app: EpaperApp,
+    pet: &mut Pet,
+    result: Result<(), EpaperError>,
+    what: &str,
+) -> Result<(), EpaperError> {
+    if let Err(e) = &result {
+        error!("{} failed: {}", what, e);
+        logger::record_error(e.to_string());
+    }
+    save_pet(pet);
+    let shutdown = app.shutdown();
+    if let Err(e) = &shutdown {
+        error!("Failed to shut down display: {}", e);
+        logger::record_error(format!("Failed to shut down display: {}", e));
+    }
+    result.and(shutdown)
+}
+
+pub fn run_epaper_app(cfg: &DisplayConfig) -> Result<(), EpaperError> {
+    let mut pet = load_pet();
+    let mut app = open_app(cfg)?;
+    let commands = ws::spawn(cfg.websocket.port, app.state())?;
+    reload_on_sighup(&app);
+
+    let result = app.run(&mut pet, &commands);
+    finish(app, &mut pet, result, "Display loop")
+}
+
+// not being able to is no reason to stop, it just needs a restart to change
+fn reload_on_sighup(app: &EpaperApp) {
+    if let Err(e) = config::reload_on_sighup(app.config()) {
+        warn!("SIGHUP won't reload the config: {}", e);
+    }
+}
+
+// the app on DEMO_SEQUENCE, no websocket, nobody's going to be sending it
+// anything
+pub fn run_demo_sequence(cfg: &DisplayConfig) -> Result<(), EpaperError> {
+    let mut pet = load_pet();
+
+    let mut app = open_app(cfg)?;
+    let result = app.run_demo(&mut pet, &DEMO_SEQUENCE);
+    finish(app, &mut pet, result, "Demo")
+}
+
+pub fn run_epaper_threaded(cfg: DisplayConfig) -> Result<(), EpaperError> {
+    let handle = thread::spawn(move || -> Result<(), EpaperError> {
+        let mut pet = load_pet();
+        let mut app = open_app(&cfg)?;
+        let commands = ws::spawn(cfg.websocket.port, app.state())?;
+        reload_on_sighup(&app);
+
+        let result = app.run(&mut pet, &commands);
+        finish(app, &mut pet, result, "Display loop")
+    });
+
+    handle.join().map_err(|panic| {
+        let reason = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_default();
+        EpaperError::DisplayInit(format!("display thread panicked: {}", reason))
+    })??;
+    Ok(())
+}
+
+// the panel on its own, for the one-shot commands. nothing else the config
+// turns on is worth starting to draw one screen
+fn open_panel(cfg: &DisplayConfig) -> Result<EpaperApp, EpaperError> {
+    open_panel_on(cfg, &Backend::new()?)
+}
+
+// tried [display] init_attempts times, the spi device isn't always ready
+// straight after boot
+fn open_panel_on(cfg: &DisplayConfig, backend: &Backend) -> Result<EpaperApp, EpaperError> {
+    let backoff = Duration::from_millis(cfg.display.init_backoff_ms);
+    driver::retry(cfg.display.init_attempts, backoff, || {
+        EpaperApp::with_config(cfg, backend)
+    })
+}
+
+// the self test pattern, left on the panel
+pub fn test_display(cfg: &DisplayConfig) -> Result<(), EpaperError> {
+    let mut app = open_panel(cfg)?;
+    app.clear_screen()?;
+    info!(
+        "Self test: {}",
+        diag::stuck_pixel_report(&cfg.display.dead_pixels)
+    );
+    diag::draw_test_pattern(&mut app.display, &cfg.display.dead_pixels);
+    app.full_refresh()?;
+    app.shutdown()
+}
+
+// text in the middle of the screen, wrapped, left on the panel
+pub fn show_text(cfg: &DisplayConfig, text: &str) -> Result<(), EpaperError> {
+    let mut app = open_panel(cfg)?;
+    app.clear_screen()?;
+    app.draw_wrapped_message(text);
+    app.full_refresh()?;
+    app.shutdown()
+}
+
+// a blank white panel
+pub fn clear_display(cfg: &DisplayConfig) -> Result<(), EpaperError> {
+    let mut app = open_panel(cfg)?;
+    app.clear_screen()?;
+    app.shutdown()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpio::GpioInput;
+    use crate::mock::{DisplayCall, EpdCall, MockBackend, MockDisplay, MockEpd, MockPin, MockSpi};
+
+    // buttons that are never pressed
+    fn app() -> GenericEpaperApp<MockDisplay, MockEpd> {
+        let up = || GpioInput::new(MockPin::new(true));
+        GenericEpaperApp::from_parts(
+            &DisplayConfig::default(),
+            MockDisplay::new(),
+            MockEpd::new(),
+            ButtonManager::new(up(), up(), up()),
+        )
+    }
+
+    #[test]
+    fn resumes_a_screen_that_is_still_on() {
+        let app = app();
+        assert_eq!(app.resumable(DisplayMode::Clock), DisplayMode::Clock);
+        assert_eq!(app.resumable(DisplayMode::Pomodoro), DisplayMode::Pomodoro);
+    }
+
+    #[test]
+    fn falls_back_when_the_saved_screen_is_off() {
+        let app = app();
+        for mode in [
+            DisplayMode::Weather,
+            DisplayMode::StepCount,
+            DisplayMode::NowPlaying,
+            DisplayMode::Playlists,
+        ] {
+            assert_eq!(app.resumable(mode), DisplayMode::PetStats);
+        }
+        let app = app.with_weather(51.5, -0.12);
+        assert_eq!(app.resumable(DisplayMode::Weather), DisplayMode::Weather);
+    }
+
+    #[test]
+    fn a_new_app_takes_its_pins_off_the_backend() {
+        let cfg = DisplayConfig::default();
+        let backend = MockBackend::default();
+        let epd = driver::Panel::on_backend(MockSpi::new(), &cfg, &backend).unwrap();
+        let app = GenericEpaperApp::with_panel(&cfg, epd, &backend).unwrap();
+        assert_eq!(app.screen, Screen::Mode(DisplayMode::default()));
+        // buttons are pulled up, high is not pressed
+        for pin in [cfg.gpio.feed_pin, cfg.gpio.play_pin, cfg.gpio.sleep_pin] {
+            backend.pin(pin as u8).unwrap().assert_state(true);
+        }
+    }
+
+    #[test]
+    fn a_bad_config_is_turned_down_before_any_pins_are_touched() {
+        let mut cfg = DisplayConfig::default();
+        cfg.gpio.dc_pin = cfg.gpio.rst_pin;
+        let backend = MockBackend::default();
+        assert!(matches!(
+            EpaperApp::with_config(&cfg, &backend),
+            Err(EpaperError::InvalidConfig(_))
+        ));
+        assert!((0..=u8::MAX).all(|pin| backend.pin(pin).is_none()));
+    }
+
+    #[test]
+    fn dropping_the_app_puts_the_panel_to_sleep() {
+        let app = app();
+        let epd = app.epd.clone();
+        drop(app);
+        epd.assert_calls(&[EpdCall::Sleep]);
+    }
+
+    #[test]
+    fn shutdown_then_drop_only_sleeps_once() {
+        let app = app();
+        let epd = app.epd.clone();
+        app.shutdown().unwrap();
+        epd.assert_calls(&[EpdCall::Sleep]);
+    }
+
+    #[test]
+    fn a_panel_the_screensaver_put_to_sleep_isnt_slept_again() {
+        let mut app = app();
+        let epd = app.epd.clone();
+        app.enter_sleep().unwrap();
+        drop(app);
+        epd.assert_calls(&[EpdCall::Sleep]);
+    }
+
+    #[test]
+    fn clearing_then_dropping_goes_full_quick_then_sleeps() {
+        let mut app = app();
+        let epd = app.epd.clone();
+        app.clear_screen().unwrap();
+        // DirtyDisplay fills rather than clears, so the buffer says it
+        app.display.assert_calls(&[DisplayCall::SetRotation(270)]);
+        let rotation = app.display.rotation();
+        assert!(app
+            .display
+            .bounding_box()
+            .points()
+            .all(|p| region::pixel_at(app.display.buffer(), rotation, p) == Some(Color::White)));
+        epd.assert_calls(&[
+            EpdCall::SetRefresh(RefreshLut::Full),
+            EpdCall::UpdateAndDisplayFrame,
+            EpdCall::SetRefresh(RefreshLut::Quick),
+        ]);
+
+        drop(app);
+        assert_eq!(epd.calls().last(), Some(&EpdCall::Sleep));
+        assert_eq!(epd.calls().len(), 4);
+    }
+
+    #[test]
+    fn clear_screen_blanks_with_a_full_refresh_then_draws_the_screen_again() {
+        let mut app = app();
+        let mut epd = app.epd.clone();
+        app.handle_command(DisplayCommand::ClearScreen, &mut Pet::default())
+            .unwrap();
+        epd.assert_calls(&[
+            EpdCall::SetRefresh(RefreshLut::Full),
+            EpdCall::UpdateAndDisplayFrame,
+            EpdCall::SetRefresh(RefreshLut::Quick),
+            EpdCall::UpdateAndDisplayFrame,
+        ]);
+        let rotation = app.display.rotation();
+        assert!(app
+            .display
+            .bounding_box()
+            .points()
+            .any(|p| region::pixel_at(app.display.buffer(), rotation, p) == Some(Color::Black)));
+
+        // nothing to draw again on a custom screen
+        epd.clear_calls();
+        app.screen = Screen::Custom;
+        app.handle_command(DisplayCommand::ClearScreen, &mut Pet::default())
+            .unwrap();
+        assert_eq!(epd.calls().len(), 3);
+    }
+
+    #[test]
+    fn a_little_text_only_refreshes_its_window_before_the_sleep() {
+        let mut app = app();
+        let mut epd = app.epd.clone();
+        app.clear_screen().unwrap();
+        epd.clear_calls();
+
+        let text = DisplayCommand::DrawText {
+            text: "hi".to_string(),
+            x: 10,
+            y: 20,
+            size: FontSize::Small,
+        };
+        app.handle_command(text, &mut Pet::default()).unwrap();
+        drop(app);
+
+        let calls = epd.calls();
+        assert!(
+            matches!(calls[..], [EpdCall::UpdateWindow(_), EpdCall::Sleep]),
+            "{:?}",
+            calls
+        );
+    }
+
+    fn progress(current: u32, total: u32) -> DisplayCommand {
+        DisplayCommand::Progress {
+            label: "updating".to_string(),
+            current,
+            total,
+        }
+    }
+
+    #[test]
+    fn progress_goes_back_to_the_last_screen_once_done() {
+        let mut app = app().with_mode(DisplayMode::Clock);
+        let mut pet = Pet::default();
+        app.handle_command(progress(3, 10), &mut pet).unwrap();
+        assert_eq!(app.screen, Screen::Progress);
+
+        let later = Instant::now() + progress::PROGRESS_HOLD;
+        app.dismiss_progress(later);
+        assert_eq!(app.screen, Screen::Progress);
+
+        // past the total is clamped and counts as done
+        app.handle_command(progress(12, 10), &mut pet).unwrap();
+        app.dismiss_progress(Instant::now());
+        assert_eq!(app.screen, Screen::Progress);
+        app.dismiss_progress(Instant::now() + progress::PROGRESS_HOLD);
+        assert_eq!(app.screen, Screen::Mode(DisplayMode::Clock));
+        assert!(app.progress.is_none());
+    }
+
+    #[test]
+    fn show_spotify_needs_a_token() {
+        let mut app = app().with_mode(DisplayMode::Clock);
+        let shown = app.handle_command(DisplayCommand::ShowSpotify, &mut Pet::default());
+        assert!(matches!(shown, Err(EpaperError::Command(_))));
+        assert_eq!(app.screen, Screen::Mode(DisplayMode::Clock));
+    }
+
+    #[test]
+    fn progress_with_no_total_stays_up() {
+        let mut app = app();
+        app.handle_command(progress(0, 0), &mut Pet::default())
+            .unwrap();
+        app.dismiss_progress(Instant::now() + progress::PROGRESS_HOLD * 10);
+        assert_eq!(app.screen, Screen::Progress);
+    }
+}
@@ -0,0 +1,35 @@
+// floyd-steinberg down to 1 bit. gray is width*height bytes, 0 black to 255
+// white. comes back packed like a Sprite: rows padded to whole bytes, msb
+// first, set bits are black
+pub fn floyd_steinberg(gray: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let row_bytes = width.div_ceil(8);
+    let mut bits = vec![0u8; row_bytes * height];
+    // i16 so the spread error can go past 0 and 255
+    let mut levels: Vec<i16> = gray.iter().map(|&g| g as i16).collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = levels[y * width + x];
+            let black = old < 128;
+            let error = if black { old } else { old - 255 };
+            if black {
+                bits[y * row_bytes + x / 8] |= 0x80 >> (x % 8);
+            }
+
+            // 7/16 right, 3/16 down left, 5/16 down, 1/16 down right
+            let mut spread = |dx: isize, dy: usize, weight: i16| {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    return;
+                }
+                levels[ny * width + nx as usize] += error * weight / 16;
+            };
+            spread(1, 0, 7);
+            spread(-1, 1, 3);
+            spread(0, 1, 5);
+            spread(1, 1, 1);
+        }
+    }
+    bits
+}
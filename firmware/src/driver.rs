@@ -0,0 +1,551 @@
+use core::convert::Infallible;
+use embedded_graphics::prelude::*;
+use embedded_hal::spi::{self, Operation, SpiDevice};
+use epd_waveshare::{color::Color, graphics::DisplayRotation, prelude::*};
+use linux_embedded_hal::{
+    spidev::{self, SpidevOptions},
+    Delay, SPIError, SpidevDevice,
+};
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::config::DisplayConfig;
+use crate::gpio::{GpioInput, GpioOutput, HardwareBackend};
+use crate::logger;
+use crate::panel::{self, Epd, PanelDisplay};
+use crate::region::{self, Window};
+use crate::EpaperError;
+
+// a loaded pi sometimes drops an spi transfer, these are tried again
+pub const SPI_RETRIES: u8 = 3;
+const FIRST_BACKOFF: Duration = Duration::from_millis(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+// runs f, and again up to max_retries times while it fails, waiting 10ms
+// then twice as long each time up to 1s. the last error is what comes back,
+// callers wrap it in whatever they were doing
+pub fn retry_spi<T, F>(mut f: F, max_retries: u8) -> Result<T, SPIError>
+where
+    F: FnMut() -> Result<T, SPIError>,
+{
+    let mut backoff = FIRST_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < max_retries => {
+                attempt += 1;
+                warn!(
+                    "SPI transfer failed ({}), retry {}/{} in {:?}",
+                    e, attempt, max_retries, backoff
+                );
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// for opening the panel, which can lose a race with the spi driver on a
+// cold boot. f is tried up to attempts times, waiting backoff then twice as
+// long each time in between. bad config won't get any better, that comes
+// straight back
+pub fn retry<T, F>(attempts: u32, backoff: Duration, mut f: F) -> Result<T, EpaperError>
+where
+    F: FnMut() -> Result<T, EpaperError>,
+{
+    let mut backoff = backoff;
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e @ (EpaperError::Config(_) | EpaperError::InvalidConfig(_))) => return Err(e),
+            Err(e) if attempt < attempts => {
+                warn!(
+                    "Opening the display failed ({}), attempt {}/{}, trying again in {:?}",
+                    e, attempt, attempts, backoff
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// the HAT doesn't wire up MISO, so the panel RAM can't be read back to
+// check a frame. instead write runs twice and the crcs of what each pass
+// sent are compared, a mismatch is counted in the metrics and both go again
+// up to max_retries times
+pub fn write_verified<F>(mut write: F, max_retries: u8) -> Result<(), EpaperError>
+where
+    F: FnMut() -> Result<u32, EpaperError>,
+{
+    for attempt in 0..=max_retries {
+        let (first, second) = (write()?, write()?);
+        if first == second {
+            return Ok(());
+        }
+        logger::record_write_mismatch();
+        warn!(
+            "Frame write didn't match ({:08x} then {:08x}), try {}/{}",
+            first,
+            second,
+            attempt + 1,
+            max_retries + 1
+        );
+    }
+    Err(EpaperError::WriteVerify(max_retries + 1))
+}
+
+// crc-32, the zip/ethernet one, a bit at a time. a frame's 4KB so it's
+// quick enough
+pub fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// passes everything through to spi, keeping a crc of the bytes written
+pub struct CrcSpi<S> {
+    inner: S,
+    crc: u32,
+}
+
+impl<S> CrcSpi<S> {
+    fn new(inner: S) -> Self {
+        CrcSpi { inner, crc: 0 }
+    }
+}
+
+impl<S: SpiDevice> spi::ErrorType for CrcSpi<S> {
+    type Error = S::Error;
+}
+
+impl<S: SpiDevice> SpiDevice for CrcSpi<S> {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        for op in operations.iter() {
+            match op {
+                Operation::Write(words) | Operation::Transfer(_, words) => {
+                    self.crc = crc32(self.crc, words);
+                }
+                Operation::TransferInPlace(words) => self.crc = crc32(self.crc, words),
+                Operation::Read(_) | Operation::DelayNs(_) => {}
+            }
+        }
+        self.inner.transaction(operations)
+    }
+}
+
+// what EpaperApp needs from the panel. the real one owns the spi bus and
+// pins so none of that leaks into the app, a mock can just write it down
+pub trait EpdDriver {
+    fn update_and_display_frame(&mut self, buffer: &[u8]) -> Result<(), EpaperError>;
+    fn set_refresh(&mut self, refresh: RefreshLut) -> Result<(), EpaperError>;
+    fn clear_frame(&mut self) -> Result<(), EpaperError>;
+    fn display_frame(&mut self) -> Result<(), EpaperError>;
+    fn sleep(&mut self) -> Result<(), EpaperError>;
+    fn wake_up(&mut self) -> Result<(), EpaperError>;
+    // quick refresh of just the window of `buffer`, which also becomes the
+    // base the next quick refresh is diffed against
+    fn update_window(&mut self, buffer: &[u8], window: &Window) -> Result<(), EpaperError>;
+}
+
+// what EpaperApp needs from the frame buffer besides drawing on it. clear
+// comes with DrawTarget. the buffer has to be laid out like PanelDisplay's,
+// region reads pixels back out of it
+pub trait EpdDisplay: DrawTarget<Color = Color, Error = Infallible> {
+    fn buffer(&self) -> &[u8];
+    fn set_rotation(&mut self, rotation: DisplayRotation);
+    fn rotation(&self) -> DisplayRotation;
+}
+
+impl EpdDisplay for PanelDisplay {
+    fn buffer(&self) -> &[u8] {
+        PanelDisplay::buffer(self)
+    }
+
+    fn set_rotation(&mut self, rotation: DisplayRotation) {
+        PanelDisplay::set_rotation(self, rotation)
+    }
+
+    fn rotation(&self) -> DisplayRotation {
+        PanelDisplay::rotation(self)
+    }
+}
+
+// whichever panel the build is for, on the pi's spi bus. the bus is only
+// generic so the tests can put a MockSpi there
+pub struct Panel<S: SpiDevice<Error = SPIError> = SpidevDevice> {
+    spi: CrcSpi<S>,
+    epd: Epd<CrcSpi<S>, GpioInput, GpioOutput, GpioOutput, Delay>,
+    delay: Delay,
+    // [display] verify_writes
+    verify_writes: bool,
+    // not driven, spidev does chip select, but keep it claimed
+    _cs: GpioOutput,
+    // shared with the driver, the partial window writes need them too
+    dc: GpioOutput,
+    busy: GpioInput,
+}
+
+impl Panel<SpidevDevice> {
+    // cfg has to be validated already, the pins are cast to u8
+    pub fn open(cfg: &DisplayConfig, backend: &impl HardwareBackend) -> Result<Self, EpaperError> {
+        Panel::on_backend(open_spi(cfg)?, cfg, backend)
+    }
+}
+
+// the spidev the panel's on, set up for it
+pub fn open_spi(cfg: &DisplayConfig) -> Result<SpidevDevice, EpaperError> {
+    info!("Opening the {} panel", panel::MODEL);
+
+    // configure SPI setup
+    let mut spi =
+        SpidevDevice::open(&cfg.display.spi_path).map_err(|source| EpaperError::SpiOpen {
+            path: cfg.display.spi_path.clone(),
+            source,
+        })?;
+
+    let options = SpidevOptions::new()
+        .bits_per_word(8)
+        .max_speed_hz(cfg.display.max_speed_hz)
+        .mode(spidev::SpiModeFlags::SPI_MODE_0)
+        .build();
+
+    spi.configure(&options)
+        .map_err(|e| EpaperError::SpiConfigure(e.into()))?;
+    Ok(spi)
+}
+
+impl<S: SpiDevice<Error = SPIError>> Panel<S> {
+    // the rest of open once spi is there, the pins come from the backend.
+    // cfg has to be validated already
+    pub fn on_backend(
+        spi: S,
+        cfg: &DisplayConfig,
+        backend: &impl HardwareBackend,
+    ) -> Result<Self, EpaperError> {
+        // setup GPIO pins with proper timing idk
+        let cs = backend.output_pin(cfg.gpio.cs_pin as u8, 1)?;
+        let busy = backend.busy_pin(cfg.gpio.busy_pin as u8)?;
+        let dc = backend.output_pin(cfg.gpio.dc_pin as u8, 1)?;
+        let rst = backend.output_pin(cfg.gpio.rst_pin as u8, 1)?;
+
+        Ok(Panel::new(spi, cs, busy, dc, rst)?.with_verify_writes(cfg.display.verify_writes))
+    }
+
+    // resets and inits the panel over spi, which has to be set up already
+    pub fn new(
+        spi: S,
+        cs: GpioOutput,
+        busy: GpioInput,
+        dc: GpioOutput,
+        rst: GpioOutput,
+    ) -> Result<Self, EpaperError> {
+        let mut spi = CrcSpi::new(spi);
+        let mut delay = Delay {};
+
+        // init e-paper display
+        let epd = Epd::new(&mut spi, busy.clone(), dc.clone(), rst, &mut delay, None)
+            .map_err(|e| EpaperError::EpdInit(e.to_string()))?;
+
+        Ok(Panel {
+            spi,
+            epd,
+            delay,
+            verify_writes: false,
+            _cs: cs,
+            dc,
+            busy,
+        })
+    }
+
+    pub fn with_verify_writes(mut self, verify: bool) -> Self {
+        self.verify_writes = verify;
+        self
+    }
+
+    // one pass of the frame into the panel RAM, the crc of everything it
+    // sent. a retried transfer starts the crc over
+    fn write_frame(&mut self, buffer: &[u8]) -> Result<u32, EpaperError> {
+        retry_spi(
+            || {
+                self.spi.crc = 0;
+                self.epd
+                    .update_frame(&mut self.spi, buffer, &mut self.delay)
+            },
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Draw)?;
+        Ok(self.spi.crc)
+    }
+}
+
+// the bits of the drivers that aren't in WaveshareDisplay. they only touch
+// the spi bus, the callers retry them
+#[cfg(any(feature = "epd2in13_v2", feature = "epd2in13_v3"))]
+impl<S: SpiDevice<Error = SPIError>> Panel<S> {
+    fn set_lut(&mut self, refresh: RefreshLut) -> Result<(), SPIError> {
+        self.epd
+            .set_refresh(&mut self.spi, &mut self.delay, refresh)
+    }
+
+    // set_refresh notes the new lut before sending it, so after a failed
+    // send it thinks there's nothing left to do. wake_up is the same init
+    // over again with whatever lut it noted
+    fn resend_lut(&mut self, _refresh: RefreshLut) -> Result<(), SPIError> {
+        self.epd.wake_up(&mut self.spi, &mut self.delay)
+    }
+
+    fn write_base(&mut self, buffer: &[u8]) -> Result<(), SPIError> {
+        self.epd
+            .set_partial_base_buffer(&mut self.spi, &mut self.delay, buffer)
+    }
+}
+
+#[cfg(feature = "epd2in9")]
+impl<S: SpiDevice<Error = SPIError>> Panel<S> {
+    fn set_lut(&mut self, refresh: RefreshLut) -> Result<(), SPIError> {
+        self.epd
+            .set_lut(&mut self.spi, &mut self.delay, Some(refresh))
+    }
+
+    fn resend_lut(&mut self, refresh: RefreshLut) -> Result<(), SPIError> {
+        self.set_lut(refresh)
+    }
+
+    // the IL3820 has no separate base RAM to write, it diffs a quick refresh
+    // against whatever was shown last. writing the frame again is what
+    // waveshare's own 2.9" demo does after a partial update
+    fn write_base(&mut self, buffer: &[u8]) -> Result<(), SPIError> {
+        self.epd
+            .update_frame(&mut self.spi, buffer, &mut self.delay)
+    }
+}
+
+impl<S: SpiDevice<Error = SPIError>> EpdDriver for Panel<S> {
+    fn update_and_display_frame(&mut self, buffer: &[u8]) -> Result<(), EpaperError> {
+        if self.verify_writes {
+            write_verified(|| self.write_frame(buffer), SPI_RETRIES)?;
+            return retry_spi(
+                || self.epd.display_frame(&mut self.spi, &mut self.delay),
+                SPI_RETRIES,
+            )
+            .map_err(EpaperError::Draw);
+        }
+        retry_spi(
+            || {
+                self.epd
+                    .update_and_display_frame(&mut self.spi, buffer, &mut self.delay)
+            },
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Draw)
+    }
+
+    fn set_refresh(&mut self, refresh: RefreshLut) -> Result<(), EpaperError> {
+        let mut first = true;
+        retry_spi(
+            || {
+                if std::mem::take(&mut first) {
+                    self.set_lut(refresh)
+                } else {
+                    self.resend_lut(refresh)
+                }
+            },
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Refresh)
+    }
+
+    fn clear_frame(&mut self) -> Result<(), EpaperError> {
+        retry_spi(
+            || self.epd.clear_frame(&mut self.spi, &mut self.delay),
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Draw)
+    }
+
+    fn display_frame(&mut self) -> Result<(), EpaperError> {
+        retry_spi(
+            || self.epd.display_frame(&mut self.spi, &mut self.delay),
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Refresh)
+    }
+
+    fn sleep(&mut self) -> Result<(), EpaperError> {
+        retry_spi(
+            || self.epd.sleep(&mut self.spi, &mut self.delay),
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Sleep)
+    }
+
+    fn wake_up(&mut self) -> Result<(), EpaperError> {
+        retry_spi(
+            || self.epd.wake_up(&mut self.spi, &mut self.delay),
+            SPI_RETRIES,
+        )
+        .map_err(EpaperError::Sleep)
+    }
+
+    // the window write errors can be pin errors too, only the base buffer
+    // write goes through retry_spi
+    fn update_window(&mut self, buffer: &[u8], window: &Window) -> Result<(), EpaperError> {
+        region::write_window(&mut self.spi, &mut self.dc, &mut self.busy, buffer, window)?;
+        retry_spi(|| self.write_base(buffer), SPI_RETRIES).map_err(EpaperError::Draw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockBackend, MockPin, MockSpi};
+    use std::io;
+
+    // a panel over a mock bus that inits fine then drops every transfer.
+    // busy reads low, the controller's never busy
+    fn failing_panel() -> Panel<MockSpi> {
+        let out = || GpioOutput::new(MockPin::new(true));
+        let busy = GpioInput::new(MockPin::new(false));
+        let mut panel = Panel::new(MockSpi::new(), out(), busy, out(), out()).unwrap();
+        assert!(!panel.spi.inner.written.is_empty());
+        panel.spi.inner.fail = Some(io::ErrorKind::BrokenPipe);
+        panel
+    }
+
+    #[test]
+    fn spi_errors_come_back_as_what_was_being_done() {
+        let mut panel = failing_panel();
+        let buffer = PanelDisplay::default().buffer().to_vec();
+
+        assert!(matches!(
+            panel.update_and_display_frame(&buffer),
+            Err(EpaperError::Draw(_))
+        ));
+        assert!(matches!(panel.clear_frame(), Err(EpaperError::Draw(_))));
+        assert!(matches!(
+            panel.set_refresh(RefreshLut::Quick),
+            Err(EpaperError::Refresh(_))
+        ));
+        assert!(matches!(
+            panel.display_frame(),
+            Err(EpaperError::Refresh(_))
+        ));
+        assert!(matches!(panel.sleep(), Err(EpaperError::Sleep(_))));
+        assert!(matches!(panel.wake_up(), Err(EpaperError::Sleep(_))));
+
+        let window = Window {
+            x: 0,
+            y: 0,
+            width: 8,
+            height: 1,
+        };
+        assert!(matches!(
+            panel.update_window(&buffer, &window),
+            Err(EpaperError::Spi(_))
+        ));
+    }
+
+    #[test]
+    fn the_pins_come_from_the_backend() {
+        let cfg = DisplayConfig::default();
+        let backend = MockBackend::default();
+        let panel = Panel::on_backend(MockSpi::new(), &cfg, &backend).unwrap();
+        assert!(!panel.spi.inner.written.is_empty());
+
+        // cs is claimed high and left there, spidev drives the real one.
+        // rst ends up high again once the reset's done
+        let pin = |num: u64| backend.pin(num as u8).unwrap();
+        pin(cfg.gpio.cs_pin).assert_state(true);
+        pin(cfg.gpio.rst_pin).assert_state(true);
+        pin(cfg.gpio.busy_pin).assert_state(false);
+        assert!(backend.pin(cfg.gpio.dc_pin as u8).is_some());
+    }
+
+    #[test]
+    fn a_bus_that_wont_init_is_an_init_error() {
+        let out = || GpioOutput::new(MockPin::new(true));
+        let busy = GpioInput::new(MockPin::new(false));
+        let spi = MockSpi::new().failing(io::ErrorKind::PermissionDenied);
+        assert!(matches!(
+            Panel::new(spi, out(), busy, out(), out()),
+            Err(EpaperError::EpdInit(_))
+        ));
+    }
+
+    #[test]
+    fn a_dropped_transfer_is_tried_again() {
+        let mut calls = 0;
+        let result = retry_spi(
+            || {
+                calls += 1;
+                match calls {
+                    1 => Err(SPIError::from(io::Error::from(io::ErrorKind::BrokenPipe))),
+                    _ => Ok(calls),
+                }
+            },
+            SPI_RETRIES,
+        );
+        assert_eq!(result.unwrap(), 2);
+    }
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        // it carries on across calls
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn a_mismatched_frame_is_written_again() {
+        let before = logger::snapshot().write_mismatches;
+        let mut crcs = [1, 2, 3, 3].into_iter();
+        write_verified(|| Ok(crcs.next().unwrap()), SPI_RETRIES).unwrap();
+        assert_eq!(crcs.next(), None);
+        assert!(logger::snapshot().write_mismatches > before);
+
+        let mut crc = 0;
+        let never = write_verified(
+            || {
+                crc += 1;
+                Ok(crc)
+            },
+            2,
+        );
+        assert!(matches!(never, Err(EpaperError::WriteVerify(3))));
+        assert_eq!(crc, 6);
+    }
+
+    #[test]
+    fn verifying_sends_the_frame_twice() {
+        let sent = |verify: bool| {
+            let out = || GpioOutput::new(MockPin::new(true));
+            let busy = GpioInput::new(MockPin::new(false));
+            let mut panel = Panel::new(MockSpi::new(), out(), busy, out(), out())
+                .unwrap()
+                .with_verify_writes(verify);
+            panel.spi.inner.clear_written();
+            let buffer = PanelDisplay::default().buffer().to_vec();
+            panel.update_and_display_frame(&buffer).unwrap();
+            panel.spi.inner.written.len()
+        };
+        let (once, twice) = (sent(false), sent(true));
+        let frame = PanelDisplay::default().buffer().len();
+        assert!(twice >= once + frame, "{} then {}", once, twice);
+    }
+}
@@ -0,0 +1,288 @@
+use embedded_graphics::{
+    prelude::*,
+    primitives::{Arc, Circle, Ellipse, Line, Polyline, PrimitiveStyle},
+};
+use epd_waveshare::color::Color;
+
+use crate::pet::{AdultForm, Mood, Pet, PetStage};
+use crate::utils::{
+    draw_progress_bar, draw_text, draw_text_aligned, draw_text_styled, text_height, text_width,
+    Alignment, Canvas, FONT_6X10,
+};
+
+// the face fits in a square this size
+pub const FACE_SIZE: u32 = 60;
+
+// below this energy it's asleep on its feet, whatever it's doing
+const TIRED_ENERGY: u8 = 15;
+const SAD_HUNGER: u8 = 70;
+const HAPPY_HAPPINESS: u8 = 60;
+
+// pet screen layout, the face is FACE_SIZE square
+const PET_FACE_X: i32 = 14;
+const PET_FACE_Y: i32 = 20;
+const PET_STATS_X: i32 = 90;
+const PET_STATS_Y: i32 = 24;
+const PET_STAT_SPACING: i32 = 22;
+const PET_STAT_LABEL_WIDTH: i32 = 42;
+const PET_STAT_BAR_WIDTH: u32 = 110;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Happy,
+    Neutral,
+    Sad,
+    Sleeping,
+    Dead,
+}
+
+impl Face {
+    // tiredness wins over hunger, hunger over happiness
+    pub fn for_pet(pet: &Pet) -> Self {
+        match pet.mood() {
+            Mood::Dead => Face::Dead,
+            Mood::Sleeping => Face::Sleeping,
+            _ if pet.energy < TIRED_ENERGY => Face::Sleeping,
+            _ if pet.hunger >= SAD_HUNGER => Face::Sad,
+            _ if pet.happiness >= HAPPY_HAPPINESS => Face::Happy,
+            _ => Face::Neutral,
+        }
+    }
+}
+
+// head, eyes and mouth in the FACE_SIZE square with its top left at origin
+pub fn draw_face(display: &mut impl Canvas, origin: Point, face: Face) {
+    let stroke = PrimitiveStyle::with_stroke(Color::Black, 2);
+    let fill = PrimitiveStyle::with_fill(Color::Black);
+    let at = |x: i32, y: i32| origin + Point::new(x, y);
+
+    let _ = Circle::new(at(1, 1), FACE_SIZE - 2)
+        .into_styled(stroke)
+        .draw(display);
+
+    // eyes
+    for x in [20, 40] {
+        match face {
+            Face::Happy | Face::Neutral => {
+                let _ = Circle::with_center(at(x, 24), 7)
+                    .into_styled(fill)
+                    .draw(display);
+            }
+            // looking down, with the brows up in the middle
+            Face::Sad => {
+                let _ = Circle::with_center(at(x, 26), 6)
+                    .into_styled(fill)
+                    .draw(display);
+                let inner = if x < 30 { 4 } else { -4 };
+                let _ = Line::new(at(x - inner, 16), at(x + inner, 19))
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+            Face::Sleeping => {
+                let _ = Arc::with_center(at(x, 22), 10, 30.0.deg(), 120.0.deg())
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+            Face::Dead => {
+                let _ = Line::new(at(x - 4, 20), at(x + 4, 28))
+                    .into_styled(stroke)
+                    .draw(display);
+                let _ = Line::new(at(x - 4, 28), at(x + 4, 20))
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+        }
+    }
+
+    // mouth. arcs go clockwise from 3 o'clock, 30 to 150 is the bottom
+    match face {
+        Face::Happy => {
+            let _ = Arc::with_center(at(30, 34), 28, 30.0.deg(), 120.0.deg())
+                .into_styled(stroke)
+                .draw(display);
+        }
+        Face::Neutral | Face::Dead => {
+            let _ = Line::new(at(21, 42), at(39, 42))
+                .into_styled(stroke)
+                .draw(display);
+        }
+        Face::Sad => {
+            let _ = Arc::with_center(at(30, 52), 24, 210.0.deg(), 120.0.deg())
+                .into_styled(stroke)
+                .draw(display);
+        }
+        Face::Sleeping => {
+            let _ = Circle::with_center(at(30, 42), 7)
+                .into_styled(stroke)
+                .draw(display);
+            draw_text_styled(
+                display,
+                "z",
+                origin.x + 28,
+                origin.y + 11,
+                &FONT_6X10,
+                Color::Black,
+                Color::White,
+            );
+            draw_text_styled(
+                display,
+                "Z",
+                origin.x + 35,
+                origin.y + 5,
+                &FONT_6X10,
+                Color::Black,
+                Color::White,
+            );
+        }
+    }
+}
+
+// the face on the left of the pet screen, picked from its stats, with
+// whatever it's grown into drawn on. an egg's just an egg
+pub fn draw_pet_face(display: &mut impl Canvas, pet: &Pet) {
+    let origin = Point::new(PET_FACE_X, PET_FACE_Y);
+    match pet.stage() {
+        PetStage::Egg => draw_egg(display, origin),
+        stage => {
+            draw_face(display, origin, Face::for_pet(pet));
+            draw_stage(display, origin, stage);
+        }
+    }
+}
+
+// upright in the FACE_SIZE square with a crack across it
+fn draw_egg(display: &mut impl Canvas, origin: Point) {
+    let stroke = PrimitiveStyle::with_stroke(Color::Black, 2);
+    let at = |x: i32, y: i32| origin + Point::new(x, y);
+    let _ = Ellipse::new(at(10, 1), Size::new(40, FACE_SIZE - 2))
+        .into_styled(stroke)
+        .draw(display);
+    let crack = [
+        at(14, 30),
+        at(21, 25),
+        at(27, 32),
+        at(33, 25),
+        at(39, 32),
+        at(46, 27),
+    ];
+    let _ = Polyline::new(&crack).into_styled(stroke).draw(display);
+}
+
+// what tells the stages apart on top of the face: rosy cheeks on a baby,
+// a fringe on a teen, then a crown or stubble depending on how it was
+// looked after
+fn draw_stage(display: &mut impl Canvas, origin: Point, stage: PetStage) {
+    let stroke = PrimitiveStyle::with_stroke(Color::Black, 1);
+    let at = |x: i32, y: i32| origin + Point::new(x, y);
+    match stage {
+        PetStage::Egg => {}
+        PetStage::Baby => {
+            for x in [12, 48] {
+                let _ = Circle::with_center(at(x, 34), 6)
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+        }
+        PetStage::Teen => {
+            for x in [22, 30, 38] {
+                let _ = Line::new(at(x - 3, 4), at(x + 1, 12))
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+        }
+        PetStage::Adult(AdultForm::Bright) => {
+            let crown = [
+                at(20, 13),
+                at(20, 6),
+                at(25, 10),
+                at(30, 4),
+                at(35, 10),
+                at(40, 6),
+                at(40, 13),
+                at(20, 13),
+            ];
+            let _ = Polyline::new(&crown).into_styled(stroke).draw(display);
+        }
+        PetStage::Adult(AdultForm::Scruffy) => {
+            let fill = PrimitiveStyle::with_fill(Color::Black);
+            for (x, y) in [(22, 49), (27, 51), (33, 51), (38, 49), (30, 47)] {
+                let _ = Circle::with_center(at(x, y), 2)
+                    .into_styled(fill)
+                    .draw(display);
+            }
+        }
+    }
+}
+
+// the whole pet screen: the face with its name (if it has one) over it and
+// its mood under it, a bar for each
+// stat and the time along the bottom. draws over whatever's there, clear
+// it first
+pub fn draw_pet_screen(display: &mut impl Canvas, pet: &Pet, name: &str, time_str: &str) {
+    draw_pet_face(display, pet);
+    if !name.is_empty() {
+        let x = PET_FACE_X + FACE_SIZE as i32 / 2 - text_width(name, &FONT_6X10) as i32 / 2;
+        draw_text(
+            display,
+            name,
+            x,
+            PET_FACE_Y - text_height(&FONT_6X10) as i32 - 4,
+        );
+    }
+    let mood = match pet.stage() {
+        PetStage::Egg => PetStage::Egg.name(),
+        _ => pet.mood().name(),
+    };
+    let x = PET_FACE_X + FACE_SIZE as i32 / 2 - text_width(mood, &FONT_6X10) as i32 / 2;
+    draw_text(display, mood, x, PET_FACE_Y + FACE_SIZE as i32 + 2);
+
+    let stats = [
+        ("hunger", pet.hunger),
+        ("happy", pet.happiness),
+        ("energy", pet.energy),
+    ];
+    for (row, (label, value)) in stats.into_iter().enumerate() {
+        let y = PET_STATS_Y + row as i32 * PET_STAT_SPACING;
+        draw_text(display, label, PET_STATS_X, y);
+        draw_progress_bar(
+            display,
+            PET_STATS_X + PET_STAT_LABEL_WIDTH,
+            y,
+            PET_STAT_BAR_WIDTH,
+            text_height(&FONT_6X10),
+            value as f32 / 100.0,
+        );
+    }
+
+    draw_text(display, "Press Ctrl+C to exit", 0, 112);
+    draw_text_aligned(display, time_str, 112, Alignment::Right, &FONT_6X10);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockScreen;
+    use embedded_graphics::primitives::Rectangle;
+
+    #[test]
+    fn stat_bars_are_as_full_as_the_stats() {
+        let mut pet = Pet::new();
+        pet.hunger = 25;
+        pet.happiness = 50;
+        pet.energy = 100;
+        let mut screen = MockScreen::default();
+        draw_pet_screen(&mut screen, &pet, "", "12:00");
+
+        let inner = PET_STAT_BAR_WIDTH - 2;
+        let bar_x = PET_STATS_X + PET_STAT_LABEL_WIDTH + 1;
+        let widths: Vec<u32> = screen
+            .fills()
+            .into_iter()
+            .map(|(area, _): (Rectangle, _)| area)
+            .filter(|area| area.top_left.x == bar_x && area.size.height > 1)
+            .map(|area| area.size.width)
+            .collect();
+        let expected = |percent: u32| (inner as f32 * percent as f32 / 100.0).round() as u32;
+        assert_eq!(widths, [expected(25), expected(50), expected(100)]);
+    }
+}
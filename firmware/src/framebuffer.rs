@@ -0,0 +1,77 @@
+use crate::panel::HEIGHT;
+
+use crate::region::{Window, LINE_BYTES};
+
+// a copy of the frame as it was last sent to the panel. drawing goes into
+// the display's own buffer (the back one) and nothing reaches the panel until
+// a push commits it here, so comparing the two says exactly what a partial
+// refresh has to cover, however the frame got drawn
+#[derive(Debug, Clone, Default)]
+pub struct FrameBuffer {
+    // empty when the panel shows something else (blanked, or nothing yet)
+    front: Vec<u8>,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // one bit per 8 pixel column of the panel that back changes, and the
+    // first and last row that changed. None if nothing did
+    pub fn diff(&self, back: &[u8]) -> Option<(u32, u32, u32)> {
+        if self.front.len() != back.len() {
+            let all = (1 << LINE_BYTES) - 1;
+            return Some((all, 0, HEIGHT - 1));
+        }
+
+        let mut columns = 0u32;
+        let mut rows: Option<(u32, u32)> = None;
+        let line = LINE_BYTES as usize;
+        for (row, (old, new)) in self.front.chunks(line).zip(back.chunks(line)).enumerate() {
+            let mut changed = false;
+            for (col, (a, b)) in old.iter().zip(new).enumerate() {
+                if a != b {
+                    columns |= 1 << col;
+                    changed = true;
+                }
+            }
+            if changed {
+                let row = row as u32;
+                rows = Some(rows.map_or((row, row), |(first, _)| (first, row)));
+            }
+        }
+        rows.map(|(first, last)| (columns, first, last))
+    }
+
+    // the window a partial refresh needs to send back, from the first to the
+    // last changed column. the controller takes one window per refresh so
+    // the columns in between go too
+    pub fn changed(&self, back: &[u8]) -> Option<Window> {
+        let (columns, first, last) = self.diff(back)?;
+        let x = columns.trailing_zeros();
+        let x_end = u32::BITS - columns.leading_zeros();
+        Some(Window {
+            x: x * 8,
+            y: first,
+            width: (x_end - x) * 8,
+            height: last - first + 1,
+        })
+    }
+
+    // what's on the panel, empty if that isn't known
+    pub fn front(&self) -> &[u8] {
+        &self.front
+    }
+
+    // back is on the panel now
+    pub fn commit(&mut self, back: &[u8]) {
+        self.front.clear();
+        self.front.extend_from_slice(back);
+    }
+
+    // the panel was changed behind our back, the next diff covers it all
+    pub fn invalidate(&mut self) {
+        self.front.clear();
+    }
+}
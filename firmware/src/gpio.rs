@@ -0,0 +1,296 @@
+use embedded_hal::digital::{Error, ErrorKind, ErrorType, InputPin, OutputPin};
+use linux_embedded_hal::{sysfs_gpio::Direction, SysfsPin};
+use rppal::gpio::Trigger;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+use crate::logger;
+use crate::EpaperError;
+
+// where the display pins come from. sysfs is deprecated on newer kernels,
+// rppal goes through /dev/gpiomem instead
+pub trait HardwareBackend {
+    fn output_pin(&self, num: u8, initial_value: u8) -> Result<GpioOutput, EpaperError>;
+    fn input_pin(&self, num: u8) -> Result<GpioInput, EpaperError>;
+
+    // the panel's BUSY line. the driver spins on is_high() until it drops,
+    // a backend that can wait for the edge instead does it here
+    fn busy_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        self.input_pin(num)
+    }
+}
+
+// pins handed out by a backend. clones share the same pin, so the partial
+// window writes can drive DC/BUSY while the epd driver owns them too
+#[derive(Clone)]
+pub struct GpioOutput(Arc<Mutex<dyn OutputPin<Error = ErrorKind> + Send>>);
+
+#[derive(Clone)]
+pub struct GpioInput(Arc<Mutex<dyn InputPin<Error = ErrorKind> + Send>>);
+
+impl GpioOutput {
+    pub(crate) fn new(pin: impl OutputPin + Send + 'static) -> Self {
+        GpioOutput(Arc::new(Mutex::new(Kind(pin))))
+    }
+}
+
+impl GpioInput {
+    pub(crate) fn new(pin: impl InputPin + Send + 'static) -> Self {
+        GpioInput(Arc::new(Mutex::new(Kind(pin))))
+    }
+}
+
+impl ErrorType for GpioOutput {
+    type Error = ErrorKind;
+}
+
+impl OutputPin for GpioOutput {
+    fn set_low(&mut self) -> Result<(), ErrorKind> {
+        self.0.lock().map_err(|_| ErrorKind::Other)?.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), ErrorKind> {
+        self.0.lock().map_err(|_| ErrorKind::Other)?.set_high()
+    }
+}
+
+impl ErrorType for GpioInput {
+    type Error = ErrorKind;
+}
+
+impl InputPin for GpioInput {
+    fn is_high(&mut self) -> Result<bool, ErrorKind> {
+        self.0.lock().map_err(|_| ErrorKind::Other)?.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, ErrorKind> {
+        self.0.lock().map_err(|_| ErrorKind::Other)?.is_low()
+    }
+}
+
+// every backend has its own pin error type, boil them down to ErrorKind
+struct Kind<P>(P);
+
+impl<P: ErrorType> ErrorType for Kind<P> {
+    type Error = ErrorKind;
+}
+
+impl<P: OutputPin> OutputPin for Kind<P> {
+    fn set_low(&mut self) -> Result<(), ErrorKind> {
+        self.0.set_low().map_err(|e| e.kind())
+    }
+
+    fn set_high(&mut self) -> Result<(), ErrorKind> {
+        self.0.set_high().map_err(|e| e.kind())
+    }
+}
+
+impl<P: InputPin> InputPin for Kind<P> {
+    fn is_high(&mut self) -> Result<bool, ErrorKind> {
+        self.0.is_high().map_err(|e| e.kind())
+    }
+
+    fn is_low(&mut self) -> Result<bool, ErrorKind> {
+        self.0.is_low().map_err(|e| e.kind())
+    }
+}
+
+// the old path, main uses rppal but this still works where sysfs exists
+pub struct SysfsBackend;
+
+// sysfs pin that unexports itself once nothing uses it anymore. drop runs on
+// error returns and panics too, so a crashed run doesn't leave the pins
+// exported for the next launch
+struct PinGuard(SysfsPin);
+
+impl Drop for PinGuard {
+    fn drop(&mut self) {
+        if let Err(e) = self.0.unexport() {
+            let message = format!("Failed to unexport pin {}: {}", self.0.get_pin_num(), e);
+            error!("{}", message);
+            logger::record_error(message);
+        }
+    }
+}
+
+impl ErrorType for PinGuard {
+    type Error = <SysfsPin as ErrorType>::Error;
+}
+
+impl OutputPin for PinGuard {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.0.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.0.set_high()
+    }
+}
+
+impl InputPin for PinGuard {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.0.is_high()
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.0.is_low()
+    }
+}
+
+impl SysfsBackend {
+    fn export(pin_num: u8) -> Result<PinGuard, EpaperError> {
+        let pin = SysfsPin::new(pin_num as u64);
+        pin.export()?;
+        let pin = PinGuard(pin);
+        wait_for_export(pin_num, EXPORT_TIMEOUT, || pin.0.is_exported())?;
+        Ok(pin)
+    }
+}
+
+// how long the kernel gets to make the pin's sysfs files (#5)
+const EXPORT_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn wait_for_export(
+    pin_num: u8,
+    timeout: Duration,
+    mut is_exported: impl FnMut() -> bool,
+) -> Result<(), EpaperError> {
+    let start = Instant::now();
+    while !is_exported() {
+        if start.elapsed() > timeout {
+            return Err(EpaperError::PinExportTimeout(pin_num));
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    Ok(())
+}
+
+impl HardwareBackend for SysfsBackend {
+    fn output_pin(&self, num: u8, initial_value: u8) -> Result<GpioOutput, EpaperError> {
+        let pin = Self::export(num)?;
+        pin.0.set_direction(Direction::Out)?;
+        pin.0.set_value(initial_value)?;
+        Ok(GpioOutput::new(pin))
+    }
+
+    fn input_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        let pin = Self::export(num)?;
+        pin.0.set_direction(Direction::In)?;
+        Ok(GpioInput::new(pin))
+    }
+}
+
+// no export step here, the kernel driver hands the pin over straight away
+pub struct RppalBackend {
+    gpio: rppal::gpio::Gpio,
+}
+
+impl RppalBackend {
+    pub fn new() -> Result<Self, EpaperError> {
+        Ok(RppalBackend {
+            gpio: rppal::gpio::Gpio::new()?,
+        })
+    }
+}
+
+impl HardwareBackend for RppalBackend {
+    fn output_pin(&self, num: u8, initial_value: u8) -> Result<GpioOutput, EpaperError> {
+        let pin = self.gpio.get(num)?;
+        let pin = if initial_value == 0 {
+            pin.into_output_low()
+        } else {
+            pin.into_output_high()
+        };
+        Ok(GpioOutput::new(pin))
+    }
+
+    fn input_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        Ok(GpioInput::new(self.gpio.get(num)?.into_input()))
+    }
+}
+
+// a full refresh holds BUSY high for up to 2s and the drivers spin on it the
+// whole time. waiting on the edge each is_high() lets the thread sleep
+// instead, the timeout only comes into it if an edge is missed
+const BUSY_EDGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// RppalBackend with BUSY on an interrupt, everything else is the same
+pub struct GpioInterruptBackend {
+    inner: RppalBackend,
+}
+
+impl GpioInterruptBackend {
+    pub fn new() -> Result<Self, EpaperError> {
+        Ok(GpioInterruptBackend {
+            inner: RppalBackend::new()?,
+        })
+    }
+}
+
+impl HardwareBackend for GpioInterruptBackend {
+    fn output_pin(&self, num: u8, initial_value: u8) -> Result<GpioOutput, EpaperError> {
+        self.inner.output_pin(num, initial_value)
+    }
+
+    fn input_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        self.inner.input_pin(num)
+    }
+
+    fn busy_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        let mut pin = self.inner.gpio.get(num)?.into_input();
+        pin.set_interrupt(Trigger::Both, None)?;
+        Ok(GpioInput::new(InterruptBusy(pin)))
+    }
+}
+
+// reads low straight away, while it's high is_high() first sleeps until it
+// changes (or BUSY_EDGE_TIMEOUT)
+struct InterruptBusy(rppal::gpio::InputPin);
+
+impl ErrorType for InterruptBusy {
+    type Error = ErrorKind;
+}
+
+impl InputPin for InterruptBusy {
+    fn is_high(&mut self) -> Result<bool, ErrorKind> {
+        if self.0.is_low() {
+            return Ok(false);
+        }
+        // edges from before are still queued, those come back at once and
+        // the caller just asks again
+        self.0
+            .poll_interrupt(false, Some(BUSY_EDGE_TIMEOUT))
+            .map_err(|_| ErrorKind::Other)?;
+        Ok(self.0.is_high())
+    }
+
+    fn is_low(&mut self) -> Result<bool, ErrorKind> {
+        Ok(self.0.is_low())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_export_that_never_shows_up_times_out() {
+        let start = Instant::now();
+        let result = wait_for_export(25, Duration::from_millis(20), || false);
+        assert!(matches!(result, Err(EpaperError::PinExportTimeout(25))));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn a_slow_export_is_waited_for() {
+        let mut polls = 0;
+        let result = wait_for_export(25, EXPORT_TIMEOUT, || {
+            polls += 1;
+            polls > 3
+        });
+        assert!(result.is_ok());
+        assert_eq!(polls, 4);
+    }
+}
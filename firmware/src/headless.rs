@@ -0,0 +1,110 @@
+use embedded_graphics::prelude::*;
+use epd_waveshare::{color::Color, graphics::DisplayRotation, prelude::RefreshLut};
+use std::io::{self, Write};
+use tracing::info;
+
+use crate::config::DisplayConfig;
+use crate::driver::EpdDriver;
+use crate::gpio::HardwareBackend;
+use crate::mock::MockEpd;
+use crate::region::{self, Window};
+use crate::EpaperError;
+
+// the panel printed to the terminal, for working on layouts without a pi or
+// SDL. build with `--features headless`. each character is 2x2 pixels, so
+// the 2.13" comes out 125 columns by 61 rows
+
+// indexed by the four pixels as bits, top left 1, top right 2, bottom left
+// 4, bottom right 8. a set bit is black
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+pub struct TermPanel {
+    // does the writing down and the [DISPLAY] logging
+    epd: MockEpd,
+    rotation: DisplayRotation,
+}
+
+impl TermPanel {
+    // takes the same as driver::Panel so either fits in EpaperApp
+    pub fn open(cfg: &DisplayConfig, _backend: &impl HardwareBackend) -> Result<Self, EpaperError> {
+        info!("Printing the panel to the terminal");
+        // clears the terminal once, frames after that draw over the last
+        print!("\x1b[2J");
+        Ok(TermPanel {
+            epd: MockEpd::new(),
+            rotation: cfg.display.rotation,
+        })
+    }
+
+    // the whole frame from the top left corner of the terminal, the right
+    // way up
+    fn show(&self, buffer: &[u8]) {
+        let frame = render(buffer, self.rotation);
+        let mut out = io::stdout().lock();
+        let _ = write!(out, "\x1b[H{}", frame).and_then(|_| out.flush());
+    }
+}
+
+// the frame as lines of block characters
+pub fn render(buffer: &[u8], rotation: DisplayRotation) -> String {
+    let size = region::rotated_size(rotation);
+    let (columns, rows) = (
+        size.width.div_ceil(2) as i32,
+        size.height.div_ceil(2) as i32,
+    );
+    let black =
+        |x: i32, y: i32| region::pixel_at(buffer, rotation, Point::new(x, y)) == Some(Color::Black);
+
+    let mut text = String::with_capacity(((columns + 1) * rows * 3) as usize);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (column * 2, row * 2);
+            let index = usize::from(black(x, y))
+                | usize::from(black(x + 1, y)) << 1
+                | usize::from(black(x, y + 1)) << 2
+                | usize::from(black(x + 1, y + 1)) << 3;
+            text.push(QUADRANTS[index]);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+// every refresh prints the whole frame, there's no busy line or LUTs
+impl EpdDriver for TermPanel {
+    fn update_and_display_frame(&mut self, buffer: &[u8]) -> Result<(), EpaperError> {
+        self.epd.update_and_display_frame(buffer)?;
+        self.show(buffer);
+        Ok(())
+    }
+
+    fn set_refresh(&mut self, refresh: RefreshLut) -> Result<(), EpaperError> {
+        self.epd.set_refresh(refresh)
+    }
+
+    fn clear_frame(&mut self) -> Result<(), EpaperError> {
+        self.epd.clear_frame()
+    }
+
+    fn display_frame(&mut self) -> Result<(), EpaperError> {
+        self.epd.display_frame()?;
+        self.show(&self.epd.frame);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), EpaperError> {
+        self.epd.sleep()
+    }
+
+    fn wake_up(&mut self) -> Result<(), EpaperError> {
+        self.epd.wake_up()
+    }
+
+    fn update_window(&mut self, buffer: &[u8], window: &Window) -> Result<(), EpaperError> {
+        self.epd.update_window(buffer, window)?;
+        self.show(buffer);
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::{i2cdev::linux::LinuxI2CError, I2CError, I2cdev};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::logger;
+
+// MAX30102 pulse oximeter on the pi's i2c header, its address is fixed
+pub const DEFAULT_I2C_PATH: &str = "/dev/i2c-1";
+const ADDRESS: u8 = 0x57;
+const PART_ID: u8 = 0x15;
+
+// registers, see the MAX30102 datasheet
+const FIFO_WR_PTR: u8 = 0x04;
+const OVF_COUNTER: u8 = 0x05;
+const FIFO_RD_PTR: u8 = 0x06;
+const FIFO_DATA: u8 = 0x07;
+const FIFO_CONFIG: u8 = 0x08;
+const MODE_CONFIG: u8 = 0x09;
+const SPO2_CONFIG: u8 = 0x0A;
+const LED1_PA: u8 = 0x0C;
+const LED2_PA: u8 = 0x0D;
+const REG_PART_ID: u8 = 0xFF;
+
+// no averaging, the fifo rolls over if we fall behind
+const FIFO_SETTINGS: u8 = 0x10;
+const MODE_RESET: u8 = 0x40;
+// spo2 mode, red and ir both run. only ir gets used, it sees the pulse best
+const MODE_SPO2: u8 = 0x03;
+// 4096nA range, 100 samples a second, 411us pulses for 18 bit samples
+const SPO2_SETTINGS: u8 = 0x27;
+// about 7mA for each led
+const LED_CURRENT: u8 = 0x24;
+
+pub const SAMPLE_RATE_HZ: u32 = 100;
+// the fifo is 32 deep, at 100Hz that's 320ms, this leaves plenty of room
+const POLL_EVERY: Duration = Duration::from_millis(100);
+const FIFO_DEPTH: u8 = 32;
+// red and ir, 3 bytes each
+const SAMPLE_BYTES: usize = 6;
+
+// bpm is worked out over this many samples, 4 seconds
+const WINDOW: usize = 4 * SAMPLE_RATE_HZ as usize;
+// the waveform the widget plots
+pub const WAVEFORM_SAMPLES: usize = 60;
+
+// ir with nothing on the sensor sits far below this
+const FINGER_THRESHOLD: u32 = 50_000;
+// 200bpm is a beat every 30 samples, a peak closer than that is noise
+const MIN_PEAK_GAP: usize = (SAMPLE_RATE_HZ as usize * 60) / 200;
+// peaks smaller than this much of the biggest swing don't count
+const PEAK_FRACTION: f32 = 0.5;
+// beats further apart than this from the average make the reading too
+// noisy to show
+const MAX_INTERVAL_SPREAD: f32 = 0.3;
+const MIN_BPM: f32 = 30.0;
+const MAX_BPM: f32 = 220.0;
+
+#[derive(Error, Debug)]
+pub enum HeartRateError {
+    #[error("Failed to open the i2c bus: {0}")]
+    Open(#[from] LinuxI2CError),
+    #[error("I2C error: {0}")]
+    Bus(#[from] I2CError),
+    #[error("No MAX30102 on the bus, part id was {0:#04x}")]
+    WrongPart(u8),
+}
+
+// what the sensor saw last, for the widget
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PulseReading {
+    // None when the signal's too poor to trust
+    pub bpm: Option<u8>,
+    pub finger: bool,
+    // the latest WAVEFORM_SAMPLES ir samples, oldest first
+    pub waveform: Vec<u32>,
+}
+
+// the sensor is read in a thread of its own, this is just a handle on the
+// last reading
+pub struct Max30102 {
+    reading: Arc<Mutex<PulseReading>>,
+}
+
+impl Max30102 {
+    // checks it's a MAX30102 on the bus at i2c_path, sets it up and starts
+    // reading in the background
+    pub fn start(i2c_path: &str) -> Result<Self, HeartRateError> {
+        let mut i2c = I2cdev::new(i2c_path)?;
+
+        let mut part = [0u8];
+        i2c.write_read(ADDRESS, &[REG_PART_ID], &mut part)?;
+        if part[0] != PART_ID {
+            return Err(HeartRateError::WrongPart(part[0]));
+        }
+
+        // the reset bit clears itself once it's done
+        i2c.write(ADDRESS, &[MODE_CONFIG, MODE_RESET])?;
+        let mut mode = [MODE_RESET];
+        while mode[0] & MODE_RESET != 0 {
+            thread::sleep(Duration::from_millis(1));
+            i2c.write_read(ADDRESS, &[MODE_CONFIG], &mut mode)?;
+        }
+
+        for (register, value) in [
+            (FIFO_CONFIG, FIFO_SETTINGS),
+            (SPO2_CONFIG, SPO2_SETTINGS),
+            (LED1_PA, LED_CURRENT),
+            (LED2_PA, LED_CURRENT),
+            (FIFO_WR_PTR, 0),
+            (OVF_COUNTER, 0),
+            (FIFO_RD_PTR, 0),
+            (MODE_CONFIG, MODE_SPO2),
+        ] {
+            i2c.write(ADDRESS, &[register, value])?;
+        }
+
+        let reading = Arc::new(Mutex::new(PulseReading::default()));
+        let shared = reading.clone();
+        thread::spawn(move || watch(i2c, shared));
+
+        info!("Heart rate monitor started");
+        Ok(Max30102 { reading })
+    }
+
+    pub fn bpm(&self) -> Option<u8> {
+        self.reading.lock().unwrap().bpm
+    }
+
+    pub fn reading(&self) -> PulseReading {
+        self.reading.lock().unwrap().clone()
+    }
+}
+
+// everything in the fifo since the last read, ir only
+fn read_fifo(i2c: &mut I2cdev) -> Result<Vec<u32>, HeartRateError> {
+    let mut pointers = [0u8; 3];
+    i2c.write_read(ADDRESS, &[FIFO_WR_PTR], &mut pointers)?;
+    let (write, read) = (pointers[0], pointers[2]);
+    let waiting = (write.wrapping_sub(read) % FIFO_DEPTH) as usize;
+    if waiting == 0 {
+        return Ok(Vec::new());
+    }
+
+    // reading FIFO_DATA over and over walks the read pointer along
+    let mut raw = vec![0u8; waiting * SAMPLE_BYTES];
+    i2c.write_read(ADDRESS, &[FIFO_DATA], &mut raw)?;
+    Ok(raw
+        .chunks(SAMPLE_BYTES)
+        .map(|s| u32::from_be_bytes([0, s[3], s[4], s[5]]) & 0x3FFFF)
+        .collect())
+}
+
+// beats per minute over the samples, taken SAMPLE_RATE_HZ apart. the pulse
+// is what's left of the ir after taking the average off, a beat is a peak
+// of that at least PEAK_FRACTION as high as the highest. None if there's
+// not enough beats or they're too uneven to be a pulse
+pub fn bpm_from_samples(samples: &[u32]) -> Option<u8> {
+    if samples.len() < SAMPLE_RATE_HZ as usize {
+        return None;
+    }
+
+    // a little smoothing so the noise on top of a peak isn't counted as
+    // several of them
+    let smoothed: Vec<f32> = samples
+        .windows(4)
+        .map(|w| w.iter().sum::<u32>() as f32 / 4.0)
+        .collect();
+    let mean = smoothed.iter().sum::<f32>() / smoothed.len() as f32;
+    let pulse: Vec<f32> = smoothed.iter().map(|s| s - mean).collect();
+    let highest = pulse.iter().cloned().fold(0.0, f32::max);
+    if highest <= 0.0 {
+        return None;
+    }
+
+    let mut peaks: Vec<usize> = Vec::new();
+    for i in 1..pulse.len() - 1 {
+        let is_peak = pulse[i] > pulse[i - 1] && pulse[i] >= pulse[i + 1];
+        if !is_peak || pulse[i] < highest * PEAK_FRACTION {
+            continue;
+        }
+        match peaks.last_mut() {
+            // too close to the last one, keep whichever is higher
+            Some(last) if i - *last < MIN_PEAK_GAP => {
+                if pulse[i] > pulse[*last] {
+                    *last = i;
+                }
+            }
+            _ => peaks.push(i),
+        }
+    }
+    if peaks.len() < 3 {
+        return None;
+    }
+
+    let intervals: Vec<f32> = peaks.windows(2).map(|p| (p[1] - p[0]) as f32).collect();
+    let average = intervals.iter().sum::<f32>() / intervals.len() as f32;
+    if intervals
+        .iter()
+        .any(|i| (i - average).abs() > average * MAX_INTERVAL_SPREAD)
+    {
+        return None;
+    }
+
+    let bpm = 60.0 * SAMPLE_RATE_HZ as f32 / average;
+    (MIN_BPM..=MAX_BPM)
+        .contains(&bpm)
+        .then_some(bpm.round() as u8)
+}
+
+// runs until the bus goes away
+fn watch(mut i2c: I2cdev, reading: Arc<Mutex<PulseReading>>) {
+    let mut window: VecDeque<u32> = VecDeque::with_capacity(WINDOW);
+
+    loop {
+        thread::sleep(POLL_EVERY);
+        let samples = match read_fifo(&mut i2c) {
+            Ok(samples) => samples,
+            Err(e) => {
+                error!("Heart rate monitor stopped: {}", e);
+                logger::record_error(format!("Heart rate monitor stopped: {}", e));
+                return;
+            }
+        };
+        if samples.is_empty() {
+            continue;
+        }
+
+        for sample in samples {
+            if window.len() == WINDOW {
+                window.pop_front();
+            }
+            window.push_back(sample);
+        }
+
+        // the last sample is enough to tell, the finger's either on or it isn't
+        let finger = window.back().is_some_and(|&ir| ir >= FINGER_THRESHOLD);
+        if !finger {
+            // start over once it's back, old samples would only spoil it
+            window.clear();
+        }
+        let samples = window.make_contiguous();
+
+        let mut shared = reading.lock().unwrap();
+        shared.finger = finger;
+        shared.bpm = bpm_from_samples(samples);
+        shared.waveform = samples[samples.len().saturating_sub(WAVEFORM_SAMPLES)..].to_vec();
+    }
+}
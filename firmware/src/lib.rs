@@ -0,0 +1,55 @@
+// everything the firmware is made of. the binaries only pick what to run,
+// the display module has the one EpaperApp they all share
+pub mod accelerometer;
+pub mod battery;
+pub mod button;
+pub mod buzzer;
+pub mod cli;
+pub mod clock;
+pub mod command;
+pub mod config;
+pub mod diag;
+pub mod dirty;
+pub mod display;
+pub mod dither;
+pub mod driver;
+pub mod face;
+pub mod framebuffer;
+pub mod gpio;
+#[cfg(all(feature = "simulator", feature = "headless"))]
+compile_error!("simulator and headless both replace the panel, pick one");
+#[cfg(feature = "headless")]
+pub mod headless;
+pub mod heartrate;
+pub mod logger;
+pub mod menu;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod network;
+pub mod notification;
+pub mod panel;
+pub mod pet;
+pub mod playlist;
+pub mod pomodoro;
+pub mod progress;
+pub mod qr;
+pub mod region;
+pub mod render;
+pub mod rtc;
+pub mod scheduler;
+pub mod screensaver;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+pub mod splash;
+pub mod spotify;
+pub mod sprites;
+pub mod state;
+pub mod sysinfo;
+pub mod temperature;
+pub mod transition;
+pub mod utils;
+pub mod weather;
+pub mod widget;
+pub mod ws;
+
+pub use display::{EpaperApp, EpaperError, GenericEpaperApp};
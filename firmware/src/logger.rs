@@ -0,0 +1,137 @@
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, Level};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::{filter::LevelFilter, fmt, prelude::*, util::TryInitError, EnvFilter};
+
+pub const DEFAULT_LOG_DIR: &str = "/var/log/rpigotchi";
+
+// one file a day, a week of them kept
+const KEEP_DAYS: usize = 7;
+
+#[derive(Error, Debug)]
+pub enum LoggerError {
+    #[error("Failed to open log file: {0}")]
+    File(#[from] InitError),
+    #[error("Logger already set up: {0}")]
+    Init(#[from] TryInitError),
+}
+
+// logging stays on while this is alive, dropping it flushes what's left
+pub struct Logger {
+    _guard: WorkerGuard,
+}
+
+// RUST_LOG if it's set (RUST_LOG=debug shows refresh timings, say), level
+// otherwise
+fn filter(level: Level) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(level).into())
+        .from_env_lossy()
+}
+
+// logs go to a daily file in log_dir and to stderr
+pub fn init(log_dir: &Path, level: Level) -> Result<Logger, LoggerError> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("rpigotchi")
+        .filename_suffix("log")
+        .max_log_files(KEEP_DAYS)
+        .build(log_dir)?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    tracing_subscriber::registry()
+        .with(filter(level))
+        .with(fmt::layer().with_writer(writer).with_ansi(false))
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .try_init()?;
+
+    Ok(Logger { _guard: guard })
+}
+
+// for when the log dir can't be used, stderr is better than nothing
+pub fn init_stderr(level: Level) -> Result<(), LoggerError> {
+    tracing_subscriber::registry()
+        .with(filter(level))
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .try_init()?;
+    Ok(())
+}
+
+// counters for the websocket metrics command, shared by the display loop,
+// spotify and the websocket thread
+struct Metrics {
+    frames: u64,
+    refresh_total: Duration,
+    spotify_calls: u64,
+    write_mismatches: u64,
+    last_error: Option<String>,
+}
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    frames: 0,
+    refresh_total: Duration::ZERO,
+    spotify_calls: 0,
+    write_mismatches: 0,
+    last_error: None,
+});
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub frame_count: u64,
+    pub average_refresh_ms: f64,
+    pub spotify_api_calls: u64,
+    // frames that read back different with [display] verify_writes on
+    pub write_mismatches: u64,
+    pub last_error: Option<String>,
+}
+
+// a panel refresh, whole or partial
+pub fn record_refresh(duration: Duration) {
+    debug!("display refresh took {:?}", duration);
+    if let Ok(mut metrics) = METRICS.lock() {
+        metrics.frames += 1;
+        metrics.refresh_total += duration;
+    }
+}
+
+pub fn record_spotify_call() {
+    if let Ok(mut metrics) = METRICS.lock() {
+        metrics.spotify_calls += 1;
+    }
+}
+
+pub fn record_write_mismatch() {
+    if let Ok(mut metrics) = METRICS.lock() {
+        metrics.write_mismatches += 1;
+    }
+}
+
+// call alongside error!() so the metrics show it too
+pub fn record_error(message: impl Into<String>) {
+    if let Ok(mut metrics) = METRICS.lock() {
+        metrics.last_error = Some(message.into());
+    }
+}
+
+pub fn snapshot() -> MetricsSnapshot {
+    // a poisoned lock still has usable counters
+    let metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    let average_refresh_ms = if metrics.frames == 0 {
+        0.0
+    } else {
+        metrics.refresh_total.as_secs_f64() * 1000.0 / metrics.frames as f64
+    };
+
+    MetricsSnapshot {
+        frame_count: metrics.frames,
+        average_refresh_ms,
+        spotify_api_calls: metrics.spotify_calls,
+        write_mismatches: metrics.write_mismatches,
+        last_error: metrics.last_error.clone(),
+    }
+}
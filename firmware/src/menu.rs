@@ -0,0 +1,355 @@
+use chrono_tz::Tz;
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use epd_waveshare::color::Color;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, mem};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::config::DisplayConfig;
+use crate::utils::{draw_text_styled, text_height, Canvas, FONT_6X10};
+
+// what the refresh setting goes round, partial refreshes between full ones
+pub const FULL_REFRESH_CHOICES: [u32; 4] = [10, 30, 60, 120];
+// the zones the timezone setting goes round after the pi's own, plus the
+// config's if it's none of these
+pub const TIMEZONE_CHOICES: [Tz; 8] = [
+    Tz::UTC,
+    Tz::Europe__London,
+    Tz::Europe__Berlin,
+    Tz::America__New_York,
+    Tz::America__Chicago,
+    Tz::America__Los_Angeles,
+    Tz::Asia__Tokyo,
+    Tz::Australia__Sydney,
+];
+// no keyboard, so names are picked off a list. "" shows none
+pub const PET_NAME_CHOICES: [&str; 6] = ["", "Pika", "Mochi", "Bean", "Pixel", "Tofu"];
+
+const ROW_HEIGHT: i32 = 13;
+const LEFT: i32 = 6;
+// where the values line up, after the longest label
+const VALUE_X: i32 = 110;
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("HOME is not set, don't know where to keep the settings")]
+    NoHome,
+    #[error("Failed to read or write the settings: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Bad settings file: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+// one change the menu made, to apply to the live config. saved as
+// snake_case, {"pet_name": "Mochi"} say
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Setting {
+    FullRefreshEvery(u32),
+    // None is the pi's own
+    Timezone(Option<Tz>),
+    PetName(String),
+}
+
+impl Setting {
+    pub fn apply(&self, cfg: &mut DisplayConfig) {
+        match self {
+            Setting::FullRefreshEvery(every) => cfg.display.full_refresh_every = *every,
+            Setting::Timezone(tz) => cfg.clock.timezone = *tz,
+            Setting::PetName(name) => cfg.pet.name = name.clone(),
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Setting::FullRefreshEvery(_) => "Full refresh",
+            Setting::Timezone(_) => "Timezone",
+            Setting::PetName(_) => "Pet name",
+        }
+    }
+
+    fn value_text(&self) -> String {
+        match self {
+            Setting::FullRefreshEvery(every) => format!("every {}", every),
+            Setting::Timezone(None) => "pi's own".to_string(),
+            Setting::Timezone(Some(tz)) => tz.name().to_string(),
+            Setting::PetName(name) if name.is_empty() => "none".to_string(),
+            Setting::PetName(name) => name.clone(),
+        }
+    }
+}
+
+// ~/.rpigotchi/settings.json
+pub fn settings_path() -> Result<PathBuf, SettingsError> {
+    let home = env::var_os("HOME").ok_or(SettingsError::NoHome)?;
+    Ok(PathBuf::from(home).join(".rpigotchi").join("settings.json"))
+}
+
+// empty if nothing's been changed yet
+pub fn load_settings(path: &Path) -> Result<Vec<Setting>, SettingsError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// in place of whatever was saved for the same setting. a file that won't
+// parse is started over
+pub fn save_setting(path: &Path, setting: &Setting) -> Result<(), SettingsError> {
+    let mut saved = load_settings(path).unwrap_or_default();
+    saved.retain(|s| mem::discriminant(s) != mem::discriminant(setting));
+    saved.push(setting.clone());
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(&saved)?)?;
+    Ok(())
+}
+
+// what's been picked on the settings screen, over the top of the config
+// file's values
+pub fn apply_saved(cfg: &mut DisplayConfig) {
+    match settings_path().and_then(|path| load_settings(&path)) {
+        Ok(saved) => {
+            for setting in &saved {
+                setting.apply(cfg);
+            }
+        }
+        Err(e) => warn!("Ignoring the saved settings: {}", e),
+    }
+}
+
+struct Item {
+    choices: Vec<Setting>,
+    chosen: usize,
+    // what it was when the menu opened
+    started: usize,
+}
+
+impl Item {
+    // the current value first if it isn't one of choices already
+    fn new(current: Setting, mut choices: Vec<Setting>) -> Self {
+        let chosen = match choices.iter().position(|c| *c == current) {
+            Some(i) => i,
+            None => {
+                choices.insert(0, current);
+                0
+            }
+        };
+        Item {
+            choices,
+            chosen,
+            started: chosen,
+        }
+    }
+
+    fn value(&self) -> &Setting {
+        &self.choices[self.chosen]
+    }
+}
+
+// the settings screen: a list with a cursor, up and down go round the
+// ends, select steps the value under the cursor on to its next choice.
+// the last row is "Back", select on it means the menu's done
+pub struct Menu {
+    items: Vec<Item>,
+    // items.len() is on Back
+    cursor: usize,
+    // [display] dead_pixels, a black bar over one stuck white looks broken
+    // so the cursor's row is only outlined there
+    dead_pixels: Vec<Point>,
+}
+
+impl Menu {
+    // the current values out of cfg
+    pub fn new(cfg: &DisplayConfig) -> Self {
+        let items = vec![
+            Item::new(
+                Setting::FullRefreshEvery(cfg.display.full_refresh_every),
+                FULL_REFRESH_CHOICES
+                    .iter()
+                    .map(|&every| Setting::FullRefreshEvery(every))
+                    .collect(),
+            ),
+            Item::new(
+                Setting::Timezone(cfg.clock.timezone),
+                std::iter::once(None)
+                    .chain(TIMEZONE_CHOICES.iter().copied().map(Some))
+                    .map(Setting::Timezone)
+                    .collect(),
+            ),
+            Item::new(
+                Setting::PetName(cfg.pet.name.clone()),
+                PET_NAME_CHOICES
+                    .iter()
+                    .map(|name| Setting::PetName(name.to_string()))
+                    .collect(),
+            ),
+        ];
+        Menu {
+            items,
+            cursor: 0,
+            dead_pixels: cfg
+                .display
+                .dead_pixels
+                .iter()
+                .map(|&(x, y)| Point::new(x as i32, y as i32))
+                .collect(),
+        }
+    }
+
+    fn rows(&self) -> usize {
+        self.items.len() + 1
+    }
+
+    pub fn up(&mut self) {
+        self.cursor = (self.cursor + self.rows() - 1) % self.rows();
+    }
+
+    pub fn down(&mut self) {
+        self.cursor = (self.cursor + 1) % self.rows();
+    }
+
+    pub fn on_back(&self) -> bool {
+        self.cursor == self.items.len()
+    }
+
+    // the setting under the cursor, changed to its next value. None on Back
+    pub fn select(&mut self) -> Option<Setting> {
+        let item = self.items.get_mut(self.cursor)?;
+        item.chosen = (item.chosen + 1) % item.choices.len();
+        Some(item.value().clone())
+    }
+
+    fn is_masked(&self, area: &Rectangle) -> bool {
+        self.dead_pixels.iter().any(|&p| area.contains(p))
+    }
+
+    // everything that's different from when it opened
+    pub fn changes(&self) -> Vec<Setting> {
+        self.items
+            .iter()
+            .filter(|item| item.chosen != item.started)
+            .map(|item| item.value().clone())
+            .collect()
+    }
+
+    // a title then a row per setting, the cursor's row white on a black
+    // bar, or boxed if there's a dead pixel under it. top is under whatever's
+    // along the top of the screen
+    pub fn draw(&self, display: &mut impl Canvas, top: i32) {
+        let width = display.bounding_box().size.width;
+        let font = &FONT_6X10;
+        let text_top = (ROW_HEIGHT - text_height(font) as i32) / 2;
+        draw_text_styled(
+            display,
+            "Settings",
+            LEFT,
+            top,
+            font,
+            Color::Black,
+            Color::White,
+        );
+
+        let rows = self
+            .items
+            .iter()
+            .map(|item| (item.value().label(), item.value().value_text()))
+            .chain(std::iter::once(("Back", String::new())));
+        for (i, (label, value)) in rows.enumerate() {
+            let y = top + text_height(font) as i32 + 2 + i as i32 * ROW_HEIGHT;
+            let row = Rectangle::new(Point::new(0, y), Size::new(width, ROW_HEIGHT as u32));
+            let (fg, bg) = if i == self.cursor && self.is_masked(&row) {
+                let _ = row
+                    .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+                    .draw(display);
+                (Color::Black, Color::White)
+            } else if i == self.cursor {
+                let _ = row
+                    .into_styled(PrimitiveStyle::with_fill(Color::Black))
+                    .draw(display);
+                (Color::White, Color::Black)
+            } else {
+                (Color::Black, Color::White)
+            };
+            draw_text_styled(display, label, LEFT, y + text_top, font, fg, bg);
+            draw_text_styled(display, &value, VALUE_X, y + text_top, font, fg, bg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockScreen;
+
+    // the solid black bar the cursor's row gets, if it got one
+    fn cursor_bar(menu: &Menu) -> Option<Rectangle> {
+        let mut screen = MockScreen::default();
+        menu.draw(&mut screen, 0);
+        let width = screen.bounding_box().size.width;
+        screen
+            .fills()
+            .into_iter()
+            .map(|(area, _)| area)
+            .find(|area| area.size == Size::new(width, ROW_HEIGHT as u32))
+    }
+
+    #[test]
+    fn the_cursor_keeps_its_bar_off_dead_pixels() {
+        let bar = cursor_bar(&Menu::new(&DisplayConfig::default())).unwrap();
+
+        let mut cfg = DisplayConfig::default();
+        cfg.display.dead_pixels = vec![(40, bar.top_left.y as u32 + 3)];
+        let mut menu = Menu::new(&cfg);
+        assert_eq!(cursor_bar(&menu), None);
+
+        // the next row down's clear of it
+        menu.down();
+        assert!(cursor_bar(&menu).is_some());
+    }
+
+    #[test]
+    fn a_saved_setting_replaces_the_last_of_its_kind() {
+        let path = std::env::temp_dir().join(format!("piknife-{}-settings", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(load_settings(&path).unwrap().is_empty());
+
+        save_setting(&path, &Setting::PetName("Bean".into())).unwrap();
+        save_setting(&path, &Setting::Timezone(None)).unwrap();
+        save_setting(&path, &Setting::PetName("Tofu".into())).unwrap();
+        let saved = load_settings(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            saved,
+            [Setting::Timezone(None), Setting::PetName("Tofu".into())]
+        );
+
+        let mut cfg = DisplayConfig::default();
+        cfg.clock.timezone = Some(Tz::UTC);
+        for setting in &saved {
+            setting.apply(&mut cfg);
+        }
+        assert_eq!(cfg.clock.timezone, None);
+        assert_eq!(cfg.pet.name, "Tofu");
+    }
+
+    #[test]
+    fn settings_are_saved_as_snake_case() {
+        let json = serde_json::to_string(&[
+            Setting::FullRefreshEvery(30),
+            Setting::Timezone(Some(Tz::Asia__Tokyo)),
+        ])
+        .unwrap();
+        assert_eq!(
+            json,
+            r#"[{"full_refresh_every":30},{"timezone":"Asia/Tokyo"}]"#
+        );
+    }
+}
@@ -0,0 +1,533 @@
+use core::convert::Infallible;
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use embedded_hal::digital::{self, InputPin, OutputPin};
+use embedded_hal::spi::{self, Operation, SpiBus, SpiDevice};
+use epd_waveshare::{color::Color, graphics::DisplayRotation, prelude::RefreshLut};
+use linux_embedded_hal::SPIError;
+
+use crate::driver::{EpdDisplay, EpdDriver};
+use crate::gpio::{GpioInput, GpioOutput, HardwareBackend};
+use crate::panel::{PanelDisplay, HEIGHT, WIDTH};
+use crate::region::Window;
+use crate::EpaperError;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+// stand-ins for the panel so GenericEpaperApp runs without a pi. they write
+// down what was asked of them, the sequence can be checked afterwards.
+// MockEpd logs each call at debug too, RUST_LOG=debug shows them
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdCall {
+    UpdateAndDisplayFrame,
+    SetRefresh(RefreshLut),
+    ClearFrame,
+    DisplayFrame,
+    Sleep,
+    WakeUp,
+    UpdateWindow(Window),
+}
+
+// clones share the calls like MockPin shares its level, keep one to check
+// what the app did after it's been dropped
+#[derive(Default, Clone)]
+pub struct MockEpd {
+    calls: Arc<Mutex<Vec<EpdCall>>>,
+    // copy of the last buffer sent, what the panel would be showing
+    pub frame: Vec<u8>,
+}
+
+impl MockEpd {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn calls(&self) -> Vec<EpdCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    // panics with both lists if the calls weren't exactly these
+    pub fn assert_calls(&self, expected: &[EpdCall]) {
+        assert_eq!(self.calls(), expected, "unexpected epd calls");
+    }
+
+    pub fn clear_calls(&mut self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    fn record(&self, call: EpdCall) {
+        self.calls.lock().unwrap().push(call);
+    }
+}
+
+impl EpdDriver for MockEpd {
+    fn update_and_display_frame(&mut self, buffer: &[u8]) -> Result<(), EpaperError> {
+        debug!(
+            "[DISPLAY] update_and_display_frame called, {} bytes",
+            buffer.len()
+        );
+        self.record(EpdCall::UpdateAndDisplayFrame);
+        self.frame = buffer.to_vec();
+        Ok(())
+    }
+
+    fn set_refresh(&mut self, refresh: RefreshLut) -> Result<(), EpaperError> {
+        debug!("[DISPLAY] set_refresh called, {:?}", refresh);
+        self.record(EpdCall::SetRefresh(refresh));
+        Ok(())
+    }
+
+    fn clear_frame(&mut self) -> Result<(), EpaperError> {
+        debug!("[DISPLAY] clear_frame called");
+        self.record(EpdCall::ClearFrame);
+        Ok(())
+    }
+
+    fn display_frame(&mut self) -> Result<(), EpaperError> {
+        debug!("[DISPLAY] display_frame called");
+        self.record(EpdCall::DisplayFrame);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), EpaperError> {
+        debug!("[DISPLAY] sleep called");
+        self.record(EpdCall::Sleep);
+        Ok(())
+    }
+
+    fn wake_up(&mut self) -> Result<(), EpaperError> {
+        debug!("[DISPLAY] wake_up called");
+        self.record(EpdCall::WakeUp);
+        Ok(())
+    }
+
+    fn update_window(&mut self, buffer: &[u8], window: &Window) -> Result<(), EpaperError> {
+        debug!(
+            "[DISPLAY] update_window called, {}x{} at ({}, {}), {} bytes",
+            window.width,
+            window.height,
+            window.x,
+            window.y,
+            window.width / 8 * window.height
+        );
+        self.record(EpdCall::UpdateWindow(*window));
+        self.frame = buffer.to_vec();
+        Ok(())
+    }
+}
+
+// DisplayRotation has no PartialEq, so rotations are written down in degrees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayCall {
+    Clear(Color),
+    SetRotation(u16),
+}
+
+// a real PanelDisplay buffer underneath so pixels can be read back
+#[derive(Default)]
+pub struct MockDisplay {
+    display: PanelDisplay,
+    pub calls: Vec<DisplayCall>,
+}
+
+impl MockDisplay {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn assert_calls(&self, expected: &[DisplayCall]) {
+        assert_eq!(self.calls, expected, "unexpected display calls");
+    }
+
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+    }
+}
+
+impl OriginDimensions for MockDisplay {
+    fn size(&self) -> Size {
+        self.display.size()
+    }
+}
+
+impl DrawTarget for MockDisplay {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<Color>>,
+    {
+        self.display.draw_iter(pixels)
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), Infallible> {
+        self.calls.push(DisplayCall::Clear(color));
+        self.display.clear(color)
+    }
+}
+
+impl EpdDisplay for MockDisplay {
+    fn buffer(&self) -> &[u8] {
+        self.display.buffer()
+    }
+
+    fn set_rotation(&mut self, rotation: DisplayRotation) {
+        let degrees = match rotation {
+            DisplayRotation::Rotate0 => 0,
+            DisplayRotation::Rotate90 => 90,
+            DisplayRotation::Rotate180 => 180,
+            DisplayRotation::Rotate270 => 270,
+        };
+        self.calls.push(DisplayCall::SetRotation(degrees));
+        self.display.set_rotation(rotation);
+    }
+
+    fn rotation(&self) -> DisplayRotation {
+        self.display.rotation()
+    }
+}
+
+// what the drawing helpers asked of a MockScreen. a filled or outlined
+// rectangle comes through as FillSolid, text with a background as
+// FillContiguous, one per glyph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawCall {
+    // single pixels, the bounding box of the ones that landed on screen.
+    // None if none did
+    Pixels(Option<Rectangle>),
+    FillContiguous(Rectangle),
+    FillSolid(Rectangle, Color),
+    Clear(Color),
+}
+
+// a Canvas that only writes down what was drawn on it, for checking the
+// helpers' layout without a PanelDisplay. sized like the panel on its side
+pub struct MockScreen {
+    size: Size,
+    pub calls: Vec<DrawCall>,
+}
+
+impl MockScreen {
+    pub fn new(size: Size) -> Self {
+        MockScreen {
+            size,
+            calls: Vec::new(),
+        }
+    }
+
+    pub fn assert_calls(&self, expected: &[DrawCall]) {
+        assert_eq!(self.calls, expected, "unexpected draw calls");
+    }
+
+    pub fn clear_calls(&mut self) {
+        self.calls.clear();
+    }
+
+    // the solid fills in order, what a progress bar or a box is made of
+    pub fn fills(&self) -> Vec<(Rectangle, Color)> {
+        self.calls
+            .iter()
+            .filter_map(|call| match call {
+                DrawCall::FillSolid(area, color) => Some((*area, *color)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Default for MockScreen {
+    fn default() -> Self {
+        Self::new(Size::new(HEIGHT, WIDTH))
+    }
+}
+
+impl OriginDimensions for MockScreen {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for MockScreen {
+    type Color = Color;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Pixel<Color>>,
+    {
+        let screen = self.bounding_box();
+        let mut corners: Option<(Point, Point)> = None;
+        for Pixel(p, _) in pixels {
+            if !screen.contains(p) {
+                continue;
+            }
+            corners = Some(match corners {
+                Some((min, max)) => (min.component_min(p), max.component_max(p)),
+                None => (p, p),
+            });
+        }
+        self.calls.push(DrawCall::Pixels(
+            corners.map(|(min, max)| Rectangle::with_corners(min, max)),
+        ));
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, _colors: I) -> Result<(), Infallible>
+    where
+        I: IntoIterator<Item = Color>,
+    {
+        self.calls.push(DrawCall::FillContiguous(*area));
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Color) -> Result<(), Infallible> {
+        self.calls.push(DrawCall::FillSolid(*area, color));
+        Ok(())
+    }
+
+    fn clear(&mut self, color: Color) -> Result<(), Infallible> {
+        self.calls.push(DrawCall::Clear(color));
+        Ok(())
+    }
+}
+
+// an spi bus, or a device on one, that keeps every byte written. reads are
+// all zeros, the hat has no MISO so nothing ever reads anything real
+#[derive(Debug, Default)]
+pub struct MockSpi {
+    pub written: Vec<u8>,
+    // every call fails with this once it's set, as spidev would
+    pub fail: Option<io::ErrorKind>,
+}
+
+impl MockSpi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn failing(mut self, kind: io::ErrorKind) -> Self {
+        self.fail = Some(kind);
+        self
+    }
+
+    // panics with both if the bytes written so far weren't exactly these
+    pub fn assert_wrote(&self, expected: &[u8]) {
+        assert_eq!(self.written, expected, "unexpected spi writes");
+    }
+
+    pub fn clear_written(&mut self) {
+        self.written.clear();
+    }
+
+    fn check(&self) -> Result<(), SPIError> {
+        self.fail
+            .map_or(Ok(()), |kind| Err(SPIError::from(io::Error::from(kind))))
+    }
+}
+
+impl spi::ErrorType for MockSpi {
+    type Error = SPIError;
+}
+
+impl SpiBus for MockSpi {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        self.check()?;
+        words.fill(0);
+        Ok(())
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.check()?;
+        self.written.extend_from_slice(words);
+        Ok(())
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+        SpiBus::write(self, write)?;
+        SpiBus::read(self, read)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+        SpiBus::write(self, words)?;
+        SpiBus::read(self, words)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.check()
+    }
+}
+
+// chip select isn't modelled, a transaction is just its operations in order
+impl SpiDevice for MockSpi {
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        for op in operations {
+            match op {
+                Operation::Read(words) => SpiBus::read(self, words)?,
+                Operation::Write(words) => SpiBus::write(self, words)?,
+                Operation::Transfer(read, write) => SpiBus::transfer(self, read, write)?,
+                Operation::TransferInPlace(words) => SpiBus::transfer_in_place(self, words)?,
+                Operation::DelayNs(_) => self.check()?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// a pin that's both ends: what an output set it to is what an input reads.
+// clones share the level, so keep one to check or change it while whatever
+// is under test owns another
+#[derive(Debug, Clone)]
+pub struct MockPin {
+    high: Arc<AtomicBool>,
+    // every call fails with this once it's set
+    fail: Option<digital::ErrorKind>,
+}
+
+impl MockPin {
+    pub fn new(high: bool) -> Self {
+        MockPin {
+            high: Arc::new(AtomicBool::new(high)),
+            fail: None,
+        }
+    }
+
+    pub fn failing(mut self, kind: digital::ErrorKind) -> Self {
+        self.fail = Some(kind);
+        self
+    }
+
+    // what the outside world drives it to, for inputs
+    pub fn set_level(&self, high: bool) {
+        self.high.store(high, Ordering::SeqCst);
+    }
+
+    pub fn level(&self) -> bool {
+        self.high.load(Ordering::SeqCst)
+    }
+
+    pub fn assert_state(&self, high: bool) {
+        assert_eq!(self.level(), high, "unexpected pin state");
+    }
+
+    fn check(&self) -> Result<(), digital::ErrorKind> {
+        self.fail.map_or(Ok(()), Err)
+    }
+}
+
+impl digital::ErrorType for MockPin {
+    type Error = digital::ErrorKind;
+}
+
+impl OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.check()?;
+        self.set_level(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.check()?;
+        self.set_level(true);
+        Ok(())
+    }
+}
+
+impl InputPin for MockPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        self.check()?;
+        Ok(self.level())
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+// pins that go nowhere, for the simulator and headless builds. inputs read
+// high, so the buttons are never pressed, drive the pet over the websocket
+// instead. busy reads low, the controller's never busy. it keeps a clone of
+// each pin it hands out so tests can check what was done with them
+#[derive(Default)]
+pub struct MockBackend {
+    pins: Mutex<HashMap<u8, MockPin>>,
+}
+
+impl MockBackend {
+    // can't fail, it's a Result like RppalBackend::new
+    pub fn new() -> Result<Self, EpaperError> {
+        Ok(MockBackend::default())
+    }
+
+    // None if nothing asked for it
+    pub fn pin(&self, num: u8) -> Option<MockPin> {
+        self.pins.lock().unwrap().get(&num).cloned()
+    }
+
+    fn claim(&self, num: u8, high: bool) -> MockPin {
+        let pin = MockPin::new(high);
+        self.pins.lock().unwrap().insert(num, pin.clone());
+        pin
+    }
+}
+
+impl HardwareBackend for MockBackend {
+    fn output_pin(&self, num: u8, initial_value: u8) -> Result<GpioOutput, EpaperError> {
+        Ok(GpioOutput::new(self.claim(num, initial_value != 0)))
+    }
+
+    fn input_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        Ok(GpioInput::new(self.claim(num, true)))
+    }
+
+    fn busy_pin(&self, num: u8) -> Result<GpioInput, EpaperError> {
+        Ok(GpioInput::new(self.claim(num, false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::draw_progress_bar;
+
+    #[test]
+    fn progress_bar_width_is_fraction_of_the_inside() {
+        for fraction in [0.1, 0.25, 0.5, 0.75, 0.9] {
+            let mut screen = MockScreen::default();
+            draw_progress_bar(&mut screen, 0, 0, 202, 10, fraction);
+            // the border's four 1px sides, then the fill
+            let fills = screen.fills();
+            let (fill, color) = fills.last().unwrap();
+            assert_eq!(fill.top_left, Point::new(1, 1));
+            assert_eq!(fill.size, Size::new((fraction * 200.0).round() as u32, 8));
+            assert_eq!(*color, Color::Black);
+        }
+    }
+
+    #[test]
+    fn fills_skip_everything_else() {
+        let mut screen = MockScreen::default();
+        let Ok(()) = screen.clear(Color::White);
+        let _ = Pixel(Point::new(3, 4), Color::Black).draw(&mut screen);
+        let area = Rectangle::new(Point::new(1, 2), Size::new(3, 4));
+        let Ok(()) = screen.fill_solid(&area, Color::Black);
+        assert_eq!(screen.fills(), [(area, Color::Black)]);
+        assert_eq!(screen.calls.len(), 3);
+    }
+
+    #[test]
+    fn output_pins_start_at_the_level_asked_for() {
+        let backend = MockBackend::default();
+        let mut low = backend.output_pin(22, 0).unwrap();
+        backend.output_pin(8, 1).unwrap();
+        backend.pin(22).unwrap().assert_state(false);
+        backend.pin(8).unwrap().assert_state(true);
+        assert!(backend.pin(17).is_none());
+
+        low.set_high().unwrap();
+        backend.pin(22).unwrap().assert_state(true);
+    }
+}
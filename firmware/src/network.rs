@@ -0,0 +1,188 @@
+use std::fs;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// one line per wireless interface after two of headers
+const WIRELESS_PATH: &str = "/proc/net/wireless";
+const IF_INET6_PATH: &str = "/proc/net/if_inet6";
+const FIB_TRIE_PATH: &str = "/proc/net/fib_trie";
+const NET_CLASS_PATH: &str = "/sys/class/net";
+
+// the network screen doesn't need to be fresher than this
+pub const REFRESH_EVERY: Duration = Duration::from_secs(30);
+// what the wireless drivers on a pi count link quality out of
+pub const MAX_LINK_QUALITY: u8 = 70;
+pub const MAX_BARS: u8 = 4;
+
+// the interface the pi is online through, read out of /proc and /sys
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkInfo {
+    pub interface: String,
+    // the first local address that isn't loopback
+    pub ip: Option<Ipv4Addr>,
+    // the interface's global address, for when there's no ipv4 one
+    pub ipv6: Option<Ipv6Addr>,
+    // 0 to MAX_LINK_QUALITY, None on a wired interface
+    pub link_quality: Option<u8>,
+    pub up: bool,
+}
+
+impl NetworkInfo {
+    // the first wireless interface, or the first one that isn't loopback if
+    // there's no wifi
+    pub fn collect() -> Result<Self, io::Error> {
+        let (interface, link_quality) = match fs::read_to_string(WIRELESS_PATH) {
+            Ok(text) => match parse_wireless(&text) {
+                Some((name, quality)) => (name, Some(quality)),
+                None => (wired_interface()?, None),
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (wired_interface()?, None),
+            Err(e) => return Err(e),
+        };
+        let ip = parse_fib_trie(&fs::read_to_string(FIB_TRIE_PATH)?);
+        // no ipv6 at all is fine, the file just isn't there
+        let ipv6 = fs::read_to_string(IF_INET6_PATH)
+            .ok()
+            .and_then(|text| parse_if_inet6(&text, &interface));
+        let up = read_operstate(&interface)?;
+        Ok(NetworkInfo {
+            interface,
+            ip,
+            ipv6,
+            link_quality,
+            up,
+        })
+    }
+
+    // None for a wired interface
+    pub fn rssi_dbm(&self) -> Option<i32> {
+        self.link_quality.map(quality_to_dbm)
+    }
+
+    // 0 while the link is down, all of them for a wired one that's up
+    pub fn bars(&self) -> u8 {
+        match (self.up, self.rssi_dbm()) {
+            (false, _) => 0,
+            (true, None) => MAX_BARS,
+            (true, Some(dbm)) => dbm_to_bars(dbm),
+        }
+    }
+
+    // the ipv4 address, or the ipv6 one, or "no address"
+    pub fn address_text(&self) -> String {
+        match (self.ip, self.ipv6) {
+            (Some(ip), _) => ip.to_string(),
+            (None, Some(ip)) => ip.to_string(),
+            (None, None) => "no address".to_string(),
+        }
+    }
+}
+
+fn bad_data(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+// the drivers work out quality as dBm + 110, clamped to 0..=70
+pub fn quality_to_dbm(quality: u8) -> i32 {
+    i32::from(quality.min(MAX_LINK_QUALITY)) - 110
+}
+
+// the same cutoffs phones use, roughly
+pub fn dbm_to_bars(dbm: i32) -> u8 {
+    match dbm {
+        d if d >= -55 => 4,
+        d if d >= -67 => 3,
+        d if d >= -78 => 2,
+        d if d >= -89 => 1,
+        _ => 0,
+    }
+}
+
+// "wlan0: 0000   54.  -56.  -256  ..." under two header lines, the name and
+// the link quality of the first one
+fn parse_wireless(text: &str) -> Option<(String, u8)> {
+    text.lines().skip(2).find_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        // status, then quality with a trailing "." when it's changed
+        let quality = rest.split_whitespace().nth(1)?.trim_end_matches('.');
+        let quality: f32 = quality.parse().ok()?;
+        Some((name.trim().to_string(), quality.clamp(0.0, 255.0) as u8))
+    })
+}
+
+// the local table lists each address the pi has as a "|-- a.b.c.d" line
+// with "/32 host LOCAL" under it
+fn parse_fib_trie(text: &str) -> Option<Ipv4Addr> {
+    let mut last = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(addr) = line.strip_prefix("|-- ") {
+            last = addr.parse::<Ipv4Addr>().ok();
+        } else if line.starts_with("/32 host LOCAL") {
+            if let Some(ip) = last.filter(|ip| !ip.is_loopback()) {
+                return Some(ip);
+            }
+        }
+    }
+    None
+}
+
+// "address index prefix scope flags name", the address as 32 hex digits.
+// scope 00 is global, link local ones are no use off the local network
+fn parse_if_inet6(text: &str, interface: &str) -> Option<Ipv6Addr> {
+    text.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 6 || fields[5] != interface || fields[3] != "00" {
+            return None;
+        }
+        u128::from_str_radix(fields[0], 16).ok().map(Ipv6Addr::from)
+    })
+}
+
+// the first interface that isn't loopback, by name so it's the same every time
+fn wired_interface() -> Result<String, io::Error> {
+    let mut names: Vec<String> = fs::read_dir(NET_CLASS_PATH)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name != "lo")
+        .collect();
+    names.sort();
+    names
+        .into_iter()
+        .next()
+        .ok_or_else(|| bad_data("no network interfaces"))
+}
+
+// "up", "down", "dormant" and so on. a few drivers never set it and say
+// "unknown", those count as up
+fn read_operstate(interface: &str) -> Result<bool, io::Error> {
+    let path = format!("{}/{}/operstate", NET_CLASS_PATH, interface);
+    let state = fs::read_to_string(path)?;
+    Ok(matches!(state.trim(), "up" | "unknown"))
+}
+
+// the latest NetworkInfo, read again once it's REFRESH_EVERY old
+#[derive(Default)]
+pub struct NetworkMonitor {
+    last: Option<NetworkInfo>,
+    taken_at: Option<Instant>,
+}
+
+impl NetworkMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // None only if nothing could be read yet
+    pub fn poll(&mut self) -> Option<&NetworkInfo> {
+        if self.taken_at.is_none_or(|at| at.elapsed() >= REFRESH_EVERY) {
+            self.taken_at = Some(Instant::now());
+            match NetworkInfo::collect() {
+                Ok(info) => self.last = Some(info),
+                Err(e) => warn!("Failed to read network info: {}", e),
+            }
+        }
+        self.last.as_ref()
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// how long a notification stays up unless it's given its own time
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(10);
+// long enough to read "Fed!"
+pub const TOAST_DURATION: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationPriority {
+    // a one line ticker along the bottom
+    Low,
+    // a box over the middle of the screen, a button press dismisses it
+    High,
+    // a few words in a box near the top, just the body. they take turns, one
+    // pushed while another is up starts once that one's gone
+    Toast,
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub title: String,
+    pub body: String,
+    pub priority: NotificationPriority,
+    pub expires_at: Instant,
+}
+
+impl Notification {
+    pub fn new(
+        title: impl Into<String>,
+        body: impl Into<String>,
+        priority: NotificationPriority,
+    ) -> Self {
+        Notification {
+            title: title.into(),
+            body: body.into(),
+            priority,
+            expires_at: Instant::now() + DEFAULT_DURATION,
+        }
+    }
+
+    pub fn toast(text: impl Into<String>, duration: Duration) -> Self {
+        Self::new("", text, NotificationPriority::Toast).with_duration(duration)
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.expires_at = Instant::now() + duration;
+        self
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires_at
+    }
+}
+
+pub type SharedQueue = Arc<Mutex<NotificationQueue>>;
+
+// alerts waiting to be shown, oldest first. the display loop shows the
+// first one of each priority at the same time
+#[derive(Debug, Default)]
+pub struct NotificationQueue {
+    queue: VecDeque<Notification>,
+}
+
+impl NotificationQueue {
+    // the one EpaperApp reads from, clone it into whatever pushes alerts
+    pub fn shared() -> SharedQueue {
+        Arc::new(Mutex::new(Self::default()))
+    }
+
+    pub fn push(&mut self, mut n: Notification) {
+        // queued behind the last toast, for as long as it was going to be up
+        if n.priority == NotificationPriority::Toast {
+            let last = self
+                .queue
+                .iter()
+                .rev()
+                .find(|q| q.priority == NotificationPriority::Toast && !q.is_expired());
+            if let Some(last) = last {
+                let duration = n.expires_at.saturating_duration_since(Instant::now());
+                n.expires_at = last.expires_at + duration;
+            }
+        }
+        self.queue.push_back(n);
+    }
+
+    pub fn drop_expired(&mut self) {
+        self.queue.retain(|n| !n.is_expired());
+    }
+
+    pub fn current(&self, priority: NotificationPriority) -> Option<&Notification> {
+        self.queue
+            .iter()
+            .find(|n| n.priority == priority && !n.is_expired())
+    }
+
+    // takes down the high priority one on screen, false if there wasn't one
+    pub fn dismiss(&mut self) -> bool {
+        match self
+            .queue
+            .iter()
+            .position(|n| n.priority == NotificationPriority::High && !n.is_expired())
+        {
+            Some(i) => {
+                self.queue.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -0,0 +1,40 @@
+// which waveshare panel the firmware is built for, picked with a cargo
+// feature (one of them, see the README). everything else takes the display
+// type and size from here so it draws the same on any of them
+
+#[cfg(any(
+    all(feature = "epd2in13_v2", feature = "epd2in13_v3"),
+    all(
+        feature = "epd2in9",
+        any(feature = "epd2in13_v2", feature = "epd2in13_v3")
+    )
+))]
+compile_error!("more than one panel feature is on, build with --no-default-features and pick one");
+
+#[cfg(not(any(feature = "epd2in13_v2", feature = "epd2in13_v3", feature = "epd2in9")))]
+compile_error!("no panel feature is on, pick one of epd2in13_v2, epd2in13_v3 or epd2in9");
+
+// the v2 and v3 share a driver, epd_waveshare's own feature picks the LUTs
+#[cfg(any(feature = "epd2in13_v2", feature = "epd2in13_v3"))]
+pub use epd_waveshare::epd2in13_v2::{
+    Display2in13 as PanelDisplay, Epd2in13 as Epd, HEIGHT, WIDTH,
+};
+
+#[cfg(feature = "epd2in9")]
+pub use epd_waveshare::epd2in9::{Display2in9 as PanelDisplay, Epd2in9 as Epd, HEIGHT, WIDTH};
+
+#[cfg(feature = "epd2in13_v2")]
+pub const MODEL: &str = "2.13\" V2";
+#[cfg(feature = "epd2in13_v3")]
+pub const MODEL: &str = "2.13\" V3";
+#[cfg(feature = "epd2in9")]
+pub const MODEL: &str = "2.9\"";
+
+// what region writes to DISPLAY_UPDATE_CONTROL_2 for a quick refresh of a
+// window. the SSD1675/SSD1680 on the 2.13" keep the clock and analog on
+// between quick refreshes so it's just the "display" bit, the 2.9"'s IL3820
+// wants them switched on every time
+#[cfg(any(feature = "epd2in13_v2", feature = "epd2in13_v3"))]
+pub const QUICK_UPDATE: u8 = 0x04;
+#[cfg(feature = "epd2in9")]
+pub const QUICK_UPDATE: u8 = 0xC4;
@@ -0,0 +1,413 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+
+// stats move one step per period of real time, including while the pi is off
+const TICK_PERIOD: Duration = Duration::from_secs(5 * 60);
+// how much sleeping gets back per period
+const SLEEP_RECOVERY: u8 = 3;
+
+const MAX_STAT: u8 = 100;
+
+// how old it is when it moves on to each stage
+pub const BABY_AT: Duration = Duration::from_secs(60 * 60);
+pub const TEEN_AT: Duration = Duration::from_secs(24 * 60 * 60);
+pub const ADULT_AT: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+// looked after for at least this share of growing up and it turns out well
+const WELL_CARED: f32 = 0.6;
+
+#[derive(Error, Debug)]
+pub enum PetError {
+    #[error("HOME is not set, don't know where to keep the pet")]
+    NoHome,
+    #[error("Failed to read or write pet state: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Bad pet state: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mood {
+    Happy,
+    Neutral,
+    Hungry,
+    Sleeping,
+    Dead,
+}
+
+impl Mood {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mood::Happy => "happy",
+            Mood::Neutral => "ok",
+            Mood::Hungry => "hungry",
+            Mood::Sleeping => "asleep",
+            Mood::Dead => "dead",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum AdultForm {
+    Bright,
+    Scruffy,
+}
+
+// what it's grown into, from its age and how well it was looked after on
+// the way. never saved, it's worked out again from born and the care
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PetStage {
+    Egg,
+    Baby,
+    Teen,
+    Adult(AdultForm),
+}
+
+impl PetStage {
+    // care is the share of time it was happy or asleep, 0 to 1
+    pub fn for_age(age_secs: u64, care: f32) -> Self {
+        if age_secs < BABY_AT.as_secs() {
+            PetStage::Egg
+        } else if age_secs < TEEN_AT.as_secs() {
+            PetStage::Baby
+        } else if age_secs < ADULT_AT.as_secs() {
+            PetStage::Teen
+        } else if care >= WELL_CARED {
+            PetStage::Adult(AdultForm::Bright)
+        } else {
+            PetStage::Adult(AdultForm::Scruffy)
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PetStage::Egg => "egg",
+            PetStage::Baby => "baby",
+            PetStage::Teen => "teen",
+            PetStage::Adult(AdultForm::Bright) => "adult",
+            PetStage::Adult(AdultForm::Scruffy) => "scruffy",
+        }
+    }
+
+    // age the next stage starts at, None once grown up
+    fn next_at(self) -> Option<Duration> {
+        match self {
+            PetStage::Egg => Some(BABY_AT),
+            PetStage::Baby => Some(TEEN_AT),
+            PetStage::Teen => Some(ADULT_AT),
+            PetStage::Adult(_) => None,
+        }
+    }
+
+    // (hunger, happiness, energy) a period costs. an egg just sits there,
+    // babies get hungry fast and teens sulk
+    fn decay(self) -> (u32, u32, u32) {
+        match self {
+            PetStage::Egg => (0, 0, 0),
+            PetStage::Baby => (2, 1, 1),
+            PetStage::Teen => (1, 2, 1),
+            PetStage::Adult(_) => (1, 1, 1),
+        }
+    }
+}
+
+// periods spent growing up and how many of them it was happy or asleep for
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct Care {
+    good: u32,
+    periods: u32,
+}
+
+impl Care {
+    // nothing to go on counts as looked after
+    fn quality(self) -> f32 {
+        if self.periods == 0 {
+            return 1.0;
+        }
+        self.good as f32 / self.periods as f32
+    }
+}
+
+// the pigotchi itself. stats are all 0-100, hunger goes up over time,
+// happiness and energy go down
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pet {
+    pub hunger: u8,
+    pub happiness: u8,
+    pub energy: u8,
+    pub last_tick: SystemTime,
+    pub mood: Mood,
+    asleep: bool,
+    // a save from before pets had an age hatches again, its stats carry on
+    #[serde(default = "SystemTime::now")]
+    pub born: SystemTime,
+    #[serde(default)]
+    care: Care,
+}
+
+impl Default for Pet {
+    fn default() -> Self {
+        let now = SystemTime::now();
+        Pet {
+            hunger: 0,
+            happiness: MAX_STAT,
+            energy: MAX_STAT,
+            last_tick: now,
+            mood: Mood::Happy,
+            asleep: false,
+            born: now,
+            care: Care::default(),
+        }
+    }
+}
+
+fn add(stat: u8, amount: u32) -> u8 {
+    (stat as u32 + amount).min(MAX_STAT as u32) as u8
+}
+
+fn sub(stat: u8, amount: u32) -> u8 {
+    (stat as u32).saturating_sub(amount) as u8
+}
+
+// ~/.rpigotchi/state.json
+pub fn state_path() -> Result<PathBuf, PetError> {
+    let home = env::var_os("HOME").ok_or(PetError::NoHome)?;
+    Ok(PathBuf::from(home).join(".rpigotchi").join("state.json"))
+}
+
+impl Pet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // applies however many whole periods passed since the last tick. the
+    // leftover time carries over so ticking often doesn't slow the pet down.
+    // after the pi's been off it's caught up a stage at a time, each with
+    // its own decay
+    pub fn tick(&mut self) {
+        let elapsed = SystemTime::now()
+            .duration_since(self.last_tick)
+            .unwrap_or_default();
+        let mut periods = (elapsed.as_secs() / TICK_PERIOD.as_secs()) as u32;
+        if periods == 0 || self.mood == Mood::Dead {
+            return;
+        }
+        while periods > 0 {
+            let age = self.age_at(self.last_tick);
+            let stage = self.stage_at(age);
+            let chunk = match stage.next_at() {
+                Some(next) => {
+                    periods.min((next.as_secs() - age).div_ceil(TICK_PERIOD.as_secs()) as u32)
+                }
+                None => periods,
+            };
+            self.last_tick += TICK_PERIOD * chunk;
+            self.decay(chunk, stage);
+            periods -= chunk;
+        }
+        self.mood = self.mood();
+    }
+
+    fn decay(&mut self, periods: u32, stage: PetStage) {
+        let (hunger, happiness, energy) = stage.decay();
+        self.hunger = add(self.hunger, periods * hunger);
+        self.happiness = sub(self.happiness, periods * happiness);
+        // wakes up by itself once rested, and is awake for the rest of it.
+        // matters after the pi's been off for a while
+        let mut awake = periods;
+        if self.asleep {
+            let to_full = ((MAX_STAT - self.energy) as u32).div_ceil(SLEEP_RECOVERY as u32);
+            let slept = periods.min(to_full);
+            self.energy = add(self.energy, slept * SLEEP_RECOVERY as u32);
+            if self.energy == MAX_STAT {
+                self.asleep = false;
+            }
+            awake = periods - slept;
+        }
+        self.energy = sub(self.energy, awake * energy);
+
+        // only growing up counts towards what it turns into
+        if !matches!(stage, PetStage::Adult(_)) {
+            self.care.periods += periods;
+            if matches!(self.mood(), Mood::Happy | Mood::Sleeping) {
+                self.care.good += periods;
+            }
+        }
+    }
+
+    // how long since it hatched, well, since it was laid
+    pub fn age_secs(&self) -> u64 {
+        self.age_at(SystemTime::now())
+    }
+
+    fn age_at(&self, when: SystemTime) -> u64 {
+        when.duration_since(self.born).unwrap_or_default().as_secs()
+    }
+
+    pub fn stage(&self) -> PetStage {
+        self.stage_at(self.age_secs())
+    }
+
+    fn stage_at(&self, age_secs: u64) -> PetStage {
+        PetStage::for_age(age_secs, self.care.quality())
+    }
+
+    pub fn feed(&mut self) {
+        if self.mood == Mood::Dead {
+            return;
+        }
+        self.hunger = sub(self.hunger, 30);
+        self.mood = self.mood();
+    }
+
+    // playing wakes it up and makes it hungry
+    pub fn play(&mut self) {
+        if self.mood == Mood::Dead {
+            return;
+        }
+        self.asleep = false;
+        self.happiness = add(self.happiness, 20);
+        self.energy = sub(self.energy, 10);
+        self.hunger = add(self.hunger, 5);
+        self.mood = self.mood();
+    }
+
+    // puts it to sleep, it gets energy back until it's full again
+    pub fn rest(&mut self) {
+        if self.mood == Mood::Dead {
+            return;
+        }
+        self.asleep = true;
+        self.mood = self.mood();
+    }
+
+    pub fn mood(&self) -> Mood {
+        if self.hunger >= MAX_STAT {
+            Mood::Dead
+        } else if self.asleep {
+            Mood::Sleeping
+        } else if self.hunger >= 70 {
+            Mood::Hungry
+        } else if self.happiness >= 60 && self.energy >= 30 {
+            Mood::Happy
+        } else {
+            Mood::Neutral
+        }
+    }
+
+    // None if there's no saved pet yet. a loaded one has already been
+    // ticked for however long it was saved for
+    pub fn load(path: &Path) -> Result<Option<Self>, PetError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut pet: Pet = serde_json::from_str(&text)?;
+        pet.tick();
+        Ok(Some(pet))
+    }
+
+    // written next to path first then moved over it, so losing power half
+    // way through leaves the old save rather than a broken one
+    pub fn save(&self, path: &Path) -> Result<(), PetError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 60 * 60;
+
+    #[test]
+    fn it_grows_up_at_each_threshold() {
+        let at = |age: Duration| PetStage::for_age(age.as_secs(), 1.0);
+        assert_eq!(PetStage::for_age(0, 1.0), PetStage::Egg);
+        assert_eq!(at(BABY_AT - Duration::from_secs(1)), PetStage::Egg);
+        assert_eq!(at(BABY_AT), PetStage::Baby);
+        assert_eq!(at(TEEN_AT - Duration::from_secs(1)), PetStage::Baby);
+        assert_eq!(at(TEEN_AT), PetStage::Teen);
+        assert_eq!(at(ADULT_AT - Duration::from_secs(1)), PetStage::Teen);
+        assert_eq!(at(ADULT_AT), PetStage::Adult(AdultForm::Bright));
+    }
+
+    #[test]
+    fn care_only_matters_for_the_adult() {
+        assert_eq!(PetStage::for_age(HOUR, 0.0), PetStage::Baby);
+        let adult = ADULT_AT.as_secs();
+        assert_eq!(
+            PetStage::for_age(adult, WELL_CARED),
+            PetStage::Adult(AdultForm::Bright)
+        );
+        assert_eq!(
+            PetStage::for_age(adult, 0.2),
+            PetStage::Adult(AdultForm::Scruffy)
+        );
+    }
+
+    // grows it up a period at a time, looked after or left sulking
+    fn raise(looked_after: bool) -> Pet {
+        let mut pet = Pet::new();
+        for _ in 0..ADULT_AT.as_secs() / TICK_PERIOD.as_secs() {
+            pet.born -= TICK_PERIOD;
+            pet.last_tick -= TICK_PERIOD;
+            pet.hunger = 0;
+            pet.energy = MAX_STAT;
+            pet.happiness = if looked_after { MAX_STAT } else { 10 };
+            pet.tick();
+        }
+        pet
+    }
+
+    #[test]
+    fn a_well_looked_after_pet_grows_up_better() {
+        assert_eq!(raise(true).stage(), PetStage::Adult(AdultForm::Bright));
+        assert_eq!(raise(false).stage(), PetStage::Adult(AdultForm::Scruffy));
+    }
+
+    #[test]
+    fn each_stage_gets_hungry_at_its_own_pace() {
+        let hunger_after = |age: Duration| {
+            let mut pet = Pet::new();
+            pet.born -= age + TICK_PERIOD * 4;
+            pet.last_tick -= TICK_PERIOD * 4;
+            pet.tick();
+            pet.hunger
+        };
+        assert_eq!(hunger_after(Duration::ZERO), 0);
+        assert_eq!(hunger_after(BABY_AT), 8);
+        assert_eq!(hunger_after(TEEN_AT), 4);
+    }
+
+    #[test]
+    fn catching_up_splits_the_decay_at_the_stage() {
+        // an egg with two periods to go, then two as a baby
+        let mut pet = Pet::new();
+        pet.born -= BABY_AT + TICK_PERIOD * 2;
+        pet.last_tick -= TICK_PERIOD * 4;
+        pet.tick();
+        assert_eq!(pet.hunger, 4);
+        assert_eq!(pet.stage(), PetStage::Baby);
+    }
+
+    #[test]
+    fn an_old_save_without_an_age_hatches_again() {
+        let mut pet: serde_json::Value = serde_json::to_value(Pet::new()).unwrap();
+        let fields = pet.as_object_mut().unwrap();
+        fields.remove("born");
+        fields.remove("care");
+        let pet: Pet = serde_json::from_value(pet).unwrap();
+        assert_eq!(pet.stage(), PetStage::Egg);
+    }
+}
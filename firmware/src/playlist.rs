@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+use crate::scheduler::DisplayMode;
+use crate::spotify::{Client, Playlist, SpotifyError, Token};
+
+// tracks of the playlist on screen that get fetched to show under its name
+pub const PREVIEW_TRACKS: u32 = 3;
+// after a failed get_playlists, like the weather it isn't asked every frame
+const LOAD_RETRY: Duration = Duration::from_secs(60);
+
+// the playlist browser screen, one playlist at a time. play goes on to the
+// next one, feed plays it and rest goes back to the screen it came from.
+// nothing is saved, it starts at the first playlist every run
+pub struct PlaylistBrowserMode {
+    playlists: Vec<Playlist>,
+    index: usize,
+    // get_playlists has answered
+    loaded: bool,
+    tried_at: Option<Instant>,
+    // where rest goes back to
+    pub back_to: DisplayMode,
+}
+
+impl Default for PlaylistBrowserMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlaylistBrowserMode {
+    pub fn new() -> Self {
+        PlaylistBrowserMode {
+            playlists: Vec::new(),
+            index: 0,
+            loaded: false,
+            tried_at: None,
+            back_to: DisplayMode::PetStats,
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.loaded
+    }
+
+    // fetches the list the first time round, and again a minute after it
+    // failed. the first playlist's tracks come with it
+    pub fn load(&mut self, client: &Client, token: &mut Token) -> Result<(), SpotifyError> {
+        if self.is_loaded() || self.tried_at.is_some_and(|at| at.elapsed() < LOAD_RETRY) {
+            return Ok(());
+        }
+        self.tried_at = Some(Instant::now());
+        self.playlists = client.get_playlists(token)?;
+        self.index = 0;
+        self.loaded = true;
+        self.load_tracks(client, token)
+    }
+
+    // the preview of the playlist on screen, if it hasn't got one yet
+    pub fn load_tracks(&mut self, client: &Client, token: &mut Token) -> Result<(), SpotifyError> {
+        let Some(playlist) = self.playlists.get_mut(self.index) else {
+            return Ok(());
+        };
+        if playlist.track_count > 0 && playlist.tracks.is_empty() {
+            playlist.tracks = client.playlist_tracks(token, &playlist.id, PREVIEW_TRACKS)?;
+        }
+        Ok(())
+    }
+
+    pub fn current(&self) -> Option<&Playlist> {
+        self.playlists.get(self.index)
+    }
+
+    // round to the first after the last
+    pub fn next(&mut self) {
+        if !self.playlists.is_empty() {
+            self.index = (self.index + 1) % self.playlists.len();
+        }
+    }
+
+    // (1 based position, how many), for the "3/12" in the corner
+    pub fn position(&self) -> (usize, usize) {
+        (self.index + 1, self.playlists.len())
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::{Duration, Instant};
+
+// the long break after the last work session is this many short ones
+const LONG_BREAK_FACTOR: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Working,
+    BreakShort,
+    // after session_count work sessions, then it's done
+    BreakLong,
+    Done,
+}
+
+impl Phase {
+    pub fn label(self) -> &'static str {
+        match self {
+            Phase::Working => "work",
+            Phase::BreakShort => "short break",
+            Phase::BreakLong => "long break",
+            Phase::Done => "done",
+        }
+    }
+}
+
+// work, short break, work, ... until session_count work sessions are done,
+// then a long break. it starts paused on the first work session
+#[derive(Debug, Clone)]
+pub struct PomodoroTimer {
+    pub work_duration: Duration,
+    pub break_duration: Duration,
+    pub session_count: u8,
+    phase: Phase,
+    remaining: Duration,
+    // work sessions finished so far
+    sessions_done: u8,
+    // None while paused
+    ticked_at: Option<Instant>,
+}
+
+impl PomodoroTimer {
+    pub fn new(work_duration: Duration, break_duration: Duration, session_count: u8) -> Self {
+        PomodoroTimer {
+            work_duration,
+            break_duration,
+            session_count: session_count.max(1),
+            phase: Phase::Working,
+            remaining: work_duration,
+            sessions_done: 0,
+            ticked_at: None,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+
+    pub fn sessions_done(&self) -> u8 {
+        self.sessions_done
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.ticked_at.is_some()
+    }
+
+    // carries on where it was paused. once it's done it starts over
+    pub fn start(&mut self) {
+        if self.phase == Phase::Done {
+            *self = Self::new(self.work_duration, self.break_duration, self.session_count);
+        }
+        self.ticked_at.get_or_insert_with(Instant::now);
+    }
+
+    pub fn pause(&mut self) {
+        self.tick();
+        self.ticked_at = None;
+    }
+
+    pub fn toggle(&mut self) {
+        if self.is_running() {
+            self.pause();
+        } else {
+            self.start();
+        }
+    }
+
+    // straight on to the next phase, running or not
+    pub fn skip(&mut self) -> Phase {
+        self.advance()
+    }
+
+    // counts remaining down by the time since the last tick. the phase
+    // it's moved on to when remaining runs out, None otherwise
+    pub fn tick(&mut self) -> Option<Phase> {
+        let at = self.ticked_at?;
+        let now = Instant::now();
+        self.ticked_at = Some(now);
+        self.remaining = self.remaining.saturating_sub(now - at);
+        if !self.remaining.is_zero() {
+            return None;
+        }
+        Some(self.advance())
+    }
+
+    fn advance(&mut self) -> Phase {
+        self.phase = match self.phase {
+            Phase::Working => {
+                self.sessions_done += 1;
+                if self.sessions_done >= self.session_count {
+                    Phase::BreakLong
+                } else {
+                    Phase::BreakShort
+                }
+            }
+            Phase::BreakShort => Phase::Working,
+            Phase::BreakLong | Phase::Done => Phase::Done,
+        };
+        self.remaining = match self.phase {
+            Phase::Working => self.work_duration,
+            Phase::BreakShort => self.break_duration,
+            Phase::BreakLong => self.break_duration * LONG_BREAK_FACTOR,
+            Phase::Done => Duration::ZERO,
+        };
+        if self.phase == Phase::Done {
+            self.ticked_at = None;
+        }
+        self.phase
+    }
+
+    // "MM:SS", rounded up so it only shows 00:00 once it's run out
+    pub fn format_remaining(&self) -> String {
+        let secs = self.remaining.as_secs() + u64::from(self.remaining.subsec_nanos() > 0);
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
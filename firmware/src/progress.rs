@@ -0,0 +1,180 @@
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::{Circle, PrimitiveStyle};
+use epd_waveshare::color::Color;
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+use crate::utils::{
+    aligned_x, draw_progress_bar, draw_text_styled, text_height, Alignment, Canvas, FONT_6X10,
+    FONT_9X18,
+};
+
+// how long a finished bar stays up before the screen goes back
+pub const PROGRESS_HOLD: Duration = Duration::from_secs(1);
+
+// dots round the spinner, one of them filled in a frame
+const SPINNER_DOTS: u32 = 8;
+const SPINNER_RADIUS: i32 = 12;
+const SPINNER_DOT: u32 = 5;
+
+const BAR_HEIGHT: u32 = 12;
+const BAR_MARGIN: i32 = 10;
+
+// something a remote client is doing, an update or a download say. a total
+// of 0 is "don't know how long", that gets a spinner instead of a bar
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub label: String,
+    current: u32,
+    total: u32,
+    // when current got to total
+    finished_at: Option<Instant>,
+    // which spinner dot is filled, moves on a dot every draw
+    spin: u32,
+}
+
+impl Progress {
+    // current past total is taken as done
+    pub fn new(label: String, current: u32, total: u32) -> Self {
+        let current = current.min(total);
+        Progress {
+            label,
+            current,
+            total,
+            finished_at: (total > 0 && current == total).then(Instant::now),
+            spin: 0,
+        }
+    }
+
+    // 0 to 1, None for a spinner
+    pub fn fraction(&self) -> Option<f32> {
+        (self.total > 0).then(|| self.current as f32 / self.total as f32)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished_at.is_some()
+    }
+
+    // finished and shown full for long enough
+    pub fn dismiss_due(&self, now: Instant) -> bool {
+        self.finished_at
+            .is_some_and(|done| now.saturating_duration_since(done) >= PROGRESS_HOLD)
+    }
+}
+
+// label over a bar across the middle with "current/total" under it, or the
+// spinner in the bar's place. draws over whatever's there, clear it first
+pub fn draw_progress(display: &mut impl Canvas, progress: &mut Progress) {
+    let size = display.bounding_box().size;
+    let middle = size.height as i32 / 2;
+
+    let label_y = middle - SPINNER_RADIUS - 4 - text_height(&FONT_9X18) as i32;
+    let x = aligned_x(display, &progress.label, Alignment::Center, &FONT_9X18);
+    draw_text_styled(
+        display,
+        &progress.label,
+        x,
+        label_y,
+        &FONT_9X18,
+        Color::Black,
+        Color::White,
+    );
+
+    let Some(fraction) = progress.fraction() else {
+        draw_spinner(
+            display,
+            Point::new(size.width as i32 / 2, middle),
+            progress.spin,
+        );
+        progress.spin = (progress.spin + 1) % SPINNER_DOTS;
+        return;
+    };
+
+    let width = (size.width as i32 - 2 * BAR_MARGIN).max(0) as u32;
+    let bar_y = middle - BAR_HEIGHT as i32 / 2;
+    draw_progress_bar(display, BAR_MARGIN, bar_y, width, BAR_HEIGHT, fraction);
+
+    let count = format!("{}/{}", progress.current, progress.total);
+    let x = aligned_x(display, &count, Alignment::Center, &FONT_6X10);
+    draw_text_styled(
+        display,
+        &count,
+        x,
+        bar_y + BAR_HEIGHT as i32 + 4,
+        &FONT_6X10,
+        Color::Black,
+        Color::White,
+    );
+}
+
+// a ring of dots round center with the one at step filled
+fn draw_spinner(display: &mut impl Canvas, center: Point, step: u32) {
+    for dot in 0..SPINNER_DOTS {
+        let angle = dot as f32 / SPINNER_DOTS as f32 * TAU;
+        let at = center
+            + Point::new(
+                (angle.sin() * SPINNER_RADIUS as f32).round() as i32,
+                (-angle.cos() * SPINNER_RADIUS as f32).round() as i32,
+            );
+        let style = if dot == step {
+            PrimitiveStyle::with_fill(Color::Black)
+        } else {
+            PrimitiveStyle::with_stroke(Color::Black, 1)
+        };
+        let _ = Circle::with_center(at, SPINNER_DOT)
+            .into_styled(style)
+            .draw(display);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockScreen;
+
+    #[test]
+    fn current_is_clamped_to_total() {
+        let progress = Progress::new("flashing".into(), 12, 10);
+        assert_eq!(progress.current, 10);
+        assert_eq!(progress.fraction(), Some(1.0));
+        assert!(progress.is_done());
+    }
+
+    #[test]
+    fn only_a_finished_bar_is_dismissed() {
+        let half = Progress::new("half".into(), 5, 10);
+        let done = Progress::new("all".into(), 10, 10);
+        let later = Instant::now() + PROGRESS_HOLD;
+        assert!(!half.dismiss_due(later));
+        assert!(done.dismiss_due(later));
+        assert!(!done.dismiss_due(done.finished_at.unwrap()));
+    }
+
+    #[test]
+    fn no_total_is_a_spinner_that_never_finishes() {
+        let mut progress = Progress::new("waiting".into(), 3, 0);
+        assert_eq!(progress.fraction(), None);
+        assert!(!progress.is_done());
+        assert!(!progress.dismiss_due(Instant::now() + PROGRESS_HOLD * 10));
+
+        let mut screen = MockScreen::default();
+        draw_progress(&mut screen, &mut progress);
+        draw_progress(&mut screen, &mut progress);
+        assert_eq!(progress.spin, 2);
+    }
+
+    #[test]
+    fn the_bar_is_filled_by_how_far_along_it_is() {
+        let mut screen = MockScreen::new(Size::new(250, 122));
+        draw_progress(&mut screen, &mut Progress::new("copying".into(), 1, 4));
+        let inner = 250 - 2 * BAR_MARGIN as u32 - 2;
+        let fill = screen
+            .fills()
+            .into_iter()
+            .find(|(rect, _)| {
+                rect.top_left.x == BAR_MARGIN + 1 && rect.size.height == BAR_HEIGHT - 2
+            })
+            .map(|(rect, _)| rect.size.width);
+        assert_eq!(fill, Some((inner as f32 / 4.0).round() as u32));
+    }
+}
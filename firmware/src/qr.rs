@@ -0,0 +1,73 @@
+use embedded_graphics::{
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use epd_waveshare::color::Color;
+use qrcode::QrCode;
+
+use crate::utils::Canvas;
+use crate::EpaperError;
+
+// 2px a module still scans off the panel from a phone held close, and most
+// urls fit at it
+pub const DEFAULT_MODULE_SIZE: u8 = 2;
+// white modules around the code, the spec wants 4 but phones manage with 2
+const QUIET_ZONE: i32 = 2;
+
+fn encode(data: &str) -> Result<QrCode, EpaperError> {
+    QrCode::new(data.as_bytes())
+        .map_err(|e| EpaperError::QrCode(format!("can't encode {} bytes: {}", data.len(), e)))
+}
+
+// how many px across (and down) the code for data comes out at, without
+// the quiet zone
+pub fn qr_size(data: &str, module_size: u8) -> Result<u32, EpaperError> {
+    Ok(encode(data)?.width() as u32 * u32::from(module_size.max(1)))
+}
+
+// draws data as a QR code with its top left at (x, y), module_size px a
+// module, on white with a quiet zone around it as far as the display goes.
+// errors instead of drawing a code that'd be cut off
+pub fn draw_qr_code(
+    display: &mut impl Canvas,
+    data: &str,
+    x: i32,
+    y: i32,
+    module_size: u8,
+) -> Result<(), EpaperError> {
+    let code = encode(data)?;
+    let module = u32::from(module_size.max(1));
+    let modules = code.width() as u32;
+    let size = modules * module;
+
+    let area = Rectangle::new(Point::new(x, y), Size::new(size, size));
+    let screen = display.bounding_box();
+    if area.intersection(&screen) != area {
+        return Err(EpaperError::QrCode(format!(
+            "{}x{} modules at {}px needs {}x{} at ({}, {}), the display is {}x{}",
+            modules, modules, module, size, size, x, y, screen.size.width, screen.size.height
+        )));
+    }
+
+    let quiet = QUIET_ZONE * module as i32;
+    let _ = area
+        .offset(quiet)
+        .intersection(&screen)
+        .into_styled(PrimitiveStyle::with_fill(Color::White))
+        .draw(display);
+
+    let dark = PrimitiveStyle::with_fill(Color::Black);
+    let colors = code.to_colors();
+    for (i, color) in colors.iter().enumerate() {
+        if *color != qrcode::Color::Dark {
+            continue;
+        }
+        let col = i as u32 % modules;
+        let row = i as u32 / modules;
+        let top_left = Point::new(x + (col * module) as i32, y + (row * module) as i32);
+        let _ = Rectangle::new(top_left, Size::new(module, module))
+            .into_styled(dark)
+            .draw(display);
+    }
+    Ok(())
+}
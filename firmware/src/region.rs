@@ -0,0 +1,250 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use embedded_hal::{
+    digital::{InputPin, OutputPin},
+    spi::SpiDevice,
+};
+use epd_waveshare::{color::Color, graphics::DisplayRotation};
+use linux_embedded_hal::SPIError;
+use std::thread;
+use std::time::Duration;
+
+use crate::driver::EpdDisplay;
+use crate::gpio::{GpioInput, GpioOutput};
+use crate::panel::{self, HEIGHT, WIDTH};
+use crate::EpaperError;
+
+// epd_waveshare only lets you write a window of the panel with the full LUT
+// (it asserts otherwise) and its window end is one byte/row too big, so the
+// partial window writes talk to the controller directly. the 2.13" and
+// 2.9" controllers share this much of their command set
+
+// controller commands, see the 2.13" v2 datasheet
+const DISPLAY_UPDATE_CONTROL_2: u8 = 0x22;
+const MASTER_ACTIVATION: u8 = 0x20;
+const WRITE_RAM: u8 = 0x24;
+const SET_RAM_X_START_END: u8 = 0x44;
+const SET_RAM_Y_START_END: u8 = 0x45;
+const SET_RAM_X_COUNTER: u8 = 0x4E;
+const SET_RAM_Y_COUNTER: u8 = 0x4F;
+// bytes per row of the panel buffer, the width rounded up to whole bytes
+pub const LINE_BYTES: u32 = WIDTH.div_ceil(8);
+
+// area of the panel in its own (unrotated) coordinates. x and width are
+// multiples of 8 since the controller addresses RAM a byte at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// the panel's size the way it's drawn on, 250x122 on its side and 122x250
+// upright for the 2.13"
+pub fn rotated_size(rotation: DisplayRotation) -> Size {
+    match rotation {
+        DisplayRotation::Rotate0 | DisplayRotation::Rotate180 => Size::new(WIDTH, HEIGHT),
+        DisplayRotation::Rotate90 | DisplayRotation::Rotate270 => Size::new(HEIGHT, WIDTH),
+    }
+}
+
+// smallest rectangle holding both
+pub fn union(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let (Some(a_br), Some(b_br)) = (a.bottom_right(), b.bottom_right()) else {
+        return if a.is_zero_sized() { *b } else { *a };
+    };
+
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = a_br.component_max(b_br);
+    Rectangle::with_corners(top_left, bottom_right)
+}
+
+// maps a point in display (rotated) coordinates onto the panel, same mapping
+// epd_waveshare uses when setting pixels
+pub fn native_point(p: Point, rotation: DisplayRotation) -> (i32, i32) {
+    let (w, h) = (WIDTH as i32, HEIGHT as i32);
+
+    match rotation {
+        DisplayRotation::Rotate0 => (p.x, p.y),
+        DisplayRotation::Rotate90 => (w - 1 - p.y, p.x),
+        DisplayRotation::Rotate180 => (w - 1 - p.x, h - 1 - p.y),
+        DisplayRotation::Rotate270 => (p.y, h - 1 - p.x),
+    }
+}
+
+// reads a pixel back out of the buffer, epd_waveshare only goes the other
+// way. None if it's off screen
+pub fn get_pixel(display: &impl EpdDisplay, p: Point) -> Option<Color> {
+    pixel_at(display.buffer(), display.rotation(), p)
+}
+
+// same, for a buffer that's been handed over without its display
+pub fn pixel_at(buffer: &[u8], rotation: DisplayRotation, p: Point) -> Option<Color> {
+    let (x, y) = native_point(p, rotation);
+    if x < 0 || y < 0 || x >= WIDTH as i32 || y >= HEIGHT as i32 {
+        return None;
+    }
+
+    let byte = buffer[(y as u32 * LINE_BYTES + x as u32 / 8) as usize];
+    // a set bit is white
+    if byte & (0x80 >> (x % 8)) != 0 {
+        Some(Color::White)
+    } else {
+        Some(Color::Black)
+    }
+}
+
+// maps a rectangle in display (rotated) coordinates onto the panel. None if
+// it's off screen
+pub fn native_window(area: &Rectangle, rotation: DisplayRotation) -> Option<Window> {
+    let bottom_right = area.bottom_right()?;
+    let (w, h) = (WIDTH as i32, HEIGHT as i32);
+
+    let (x0, y0) = native_point(area.top_left, rotation);
+    let (x1, y1) = native_point(bottom_right, rotation);
+
+    let (x_min, x_max) = (x0.min(x1).max(0), x0.max(x1).min(w - 1));
+    let (y_min, y_max) = (y0.min(y1).max(0), y0.max(y1).min(h - 1));
+    if x_min > x_max || y_min > y_max {
+        return None;
+    }
+
+    let x = x_min as u32 / 8 * 8;
+    let x_end = (x_max as u32 / 8 + 1) * 8;
+    Some(Window {
+        x,
+        y: y_min as u32,
+        width: x_end - x,
+        height: (y_max - y_min + 1) as u32,
+    })
+}
+
+// the bytes of a full panel buffer inside the window, row by row
+pub fn window_bytes(buffer: &[u8], window: &Window) -> Vec<u8> {
+    let first = (window.x / 8) as usize;
+    let last = first + (window.width / 8) as usize;
+
+    (window.y..window.y + window.height)
+        .flat_map(|row| {
+            let start = (row * LINE_BYTES) as usize;
+            buffer[start + first..start + last].iter().copied()
+        })
+        .collect()
+}
+
+fn command<S: SpiDevice<Error = SPIError>>(
+    spi: &mut S,
+    dc: &mut GpioOutput,
+    cmd: u8,
+    data: &[u8],
+) -> Result<(), EpaperError> {
+    dc.set_low().map_err(EpaperError::Pin)?;
+    spi.write(&[cmd])?;
+    if !data.is_empty() {
+        dc.set_high().map_err(EpaperError::Pin)?;
+        spi.write(data)?;
+    }
+    Ok(())
+}
+
+fn wait_until_idle(busy: &mut GpioInput) -> Result<(), EpaperError> {
+    // busy is high while the controller is working
+    while busy.is_high().map_err(EpaperError::Pin)? {
+        thread::sleep(Duration::from_millis(1));
+    }
+    Ok(())
+}
+
+// writes just the window of `buffer` to the panel RAM and runs a quick
+// refresh. only valid with the quick LUT, the controller diffs against the
+// base buffer so the caller has to keep that in sync afterwards
+pub fn write_window<S: SpiDevice<Error = SPIError>>(
+    spi: &mut S,
+    dc: &mut GpioOutput,
+    busy: &mut GpioInput,
+    buffer: &[u8],
+    window: &Window,
+) -> Result<(), EpaperError> {
+    let x_start = (window.x / 8) as u8;
+    let x_end = ((window.x + window.width) / 8 - 1) as u8;
+    let y_start = window.y;
+    let y_end = window.y + window.height - 1;
+
+    wait_until_idle(busy)?;
+    command(spi, dc, SET_RAM_X_START_END, &[x_start, x_end])?;
+    command(
+        spi,
+        dc,
+        SET_RAM_Y_START_END,
+        &[
+            y_start as u8,
+            (y_start >> 8) as u8,
+            y_end as u8,
+            (y_end >> 8) as u8,
+        ],
+    )?;
+    command(spi, dc, SET_RAM_X_COUNTER, &[x_start])?;
+    command(
+        spi,
+        dc,
+        SET_RAM_Y_COUNTER,
+        &[y_start as u8, (y_start >> 8) as u8],
+    )?;
+    command(spi, dc, WRITE_RAM, &window_bytes(buffer, window))?;
+
+    command(spi, dc, DISPLAY_UPDATE_CONTROL_2, &[panel::QUICK_UPDATE])?;
+    command(spi, dc, MASTER_ACTIVATION, &[])?;
+    wait_until_idle(busy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockPin, MockSpi};
+    use embedded_hal::digital::ErrorKind;
+
+    const CORNER: Window = Window {
+        x: 8,
+        y: 1,
+        width: 8,
+        height: 2,
+    };
+
+    #[test]
+    fn a_window_write_sends_just_its_bytes() {
+        let mut buffer = vec![0xFF; (LINE_BYTES * HEIGHT) as usize];
+        buffer[(LINE_BYTES + 1) as usize] = 0x0F;
+        buffer[(2 * LINE_BYTES + 1) as usize] = 0xF0;
+
+        let mut spi = MockSpi::new();
+        let dc_pin = MockPin::new(true);
+        let mut dc = GpioOutput::new(dc_pin.clone());
+        let mut busy = GpioInput::new(MockPin::new(false));
+        write_window(&mut spi, &mut dc, &mut busy, &buffer, &CORNER).unwrap();
+
+        // a command then its data
+        let expected: [&[u8]; 7] = [
+            &[SET_RAM_X_START_END, 1, 1],
+            &[SET_RAM_Y_START_END, 1, 0, 2, 0],
+            &[SET_RAM_X_COUNTER, 1],
+            &[SET_RAM_Y_COUNTER, 1, 0],
+            &[WRITE_RAM, 0x0F, 0xF0],
+            &[DISPLAY_UPDATE_CONTROL_2, panel::QUICK_UPDATE],
+            &[MASTER_ACTIVATION],
+        ];
+        spi.assert_wrote(&expected.concat());
+        // master activation has no data, dc is left on command
+        dc_pin.assert_state(false);
+    }
+
+    #[test]
+    fn a_stuck_dc_pin_stops_the_write() {
+        let buffer = vec![0xFF; (LINE_BYTES * HEIGHT) as usize];
+        let mut spi = MockSpi::new();
+        let mut dc = GpioOutput::new(MockPin::new(true).failing(ErrorKind::Other));
+        let mut busy = GpioInput::new(MockPin::new(false));
+        let result = write_window(&mut spi, &mut dc, &mut busy, &buffer, &CORNER);
+        assert!(matches!(result, Err(EpaperError::Pin(ErrorKind::Other))));
+        spi.assert_wrote(&[]);
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+use crate::EpaperError;
+
+// anything shorter is a busy loop
+const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+// frames on a fixed beat. the time a frame took comes off the wait for the
+// next one instead of adding to it, so a frame that took 300ms of a 500ms
+// interval waits 200ms. the beats line up with the wall clock's whole
+// intervals, so at 500ms or 1s the clock ticks over on the second
+pub struct RenderLoop {
+    interval: Duration,
+    // when the frame that's going started, on the beat
+    beat: Option<Instant>,
+}
+
+impl RenderLoop {
+    pub fn new(interval: Duration) -> Self {
+        RenderLoop {
+            interval: interval.max(MIN_INTERVAL),
+            beat: None,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // the next beat is lined up to the new interval
+    pub fn set_interval(&mut self, interval: Duration) {
+        let interval = interval.max(MIN_INTERVAL);
+        if interval != self.interval {
+            self.interval = interval;
+            self.beat = None;
+        }
+    }
+
+    // the frame's done, when the next one starts. a frame that ran over its
+    // beat gets the next one straight away, and the beats start again from
+    // the one after it rather than rushing to catch up
+    pub fn end_frame(&mut self) -> Instant {
+        let now = Instant::now();
+        let next = match self.beat {
+            Some(beat) => beat + self.interval,
+            None => now + until_aligned(self.interval),
+        };
+        if next <= now {
+            debug!(
+                "Frame ran {:?} over its {:?} beat",
+                now - next,
+                self.interval
+            );
+            self.beat = None;
+            return now;
+        }
+        self.beat = Some(next);
+        next
+    }
+
+    // frame(app) once a beat until Ctrl+C clears running or frame fails
+    pub fn run<A>(
+        &mut self,
+        app: &mut A,
+        running: &AtomicBool,
+        frame: impl FnMut(&mut A) -> Result<(), EpaperError>,
+    ) -> Result<(), EpaperError> {
+        self.run_until(app, running, None, frame)
+    }
+
+    // same, stopping after length too
+    pub fn run_for<A>(
+        &mut self,
+        app: &mut A,
+        running: &AtomicBool,
+        length: Duration,
+        frame: impl FnMut(&mut A) -> Result<(), EpaperError>,
+    ) -> Result<(), EpaperError> {
+        self.run_until(app, running, Some(Instant::now() + length), frame)
+    }
+
+    fn run_until<A>(
+        &mut self,
+        app: &mut A,
+        running: &AtomicBool,
+        until: Option<Instant>,
+        mut frame: impl FnMut(&mut A) -> Result<(), EpaperError>,
+    ) -> Result<(), EpaperError> {
+        let done = |now: Instant| until.is_some_and(|until| now >= until);
+        while running.load(Ordering::SeqCst) && !done(Instant::now()) {
+            frame(app)?;
+            let mut next = self.end_frame();
+            if let Some(until) = until {
+                next = next.min(until);
+            }
+            thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+        Ok(())
+    }
+}
+
+// from now to the next whole multiple of interval since the epoch
+fn until_aligned(interval: Duration) -> Duration {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let into = since_epoch.as_nanos() % interval.as_nanos();
+    interval - Duration::from_nanos(into as u64)
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use embedded_hal::i2c::I2c;
+use linux_embedded_hal::{I2CError, I2cdev};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::ClockSection;
+
+pub const DEFAULT_I2C_PATH: &str = "/dev/i2c-1";
+const DS3231_ADDR: u8 = 0x68;
+
+const REG_TIME: u8 = 0x00;
+const REG_STATUS: u8 = 0x0F;
+// oscillator stop flag, set when the coin cell died and the time is junk
+const STATUS_OSF: u8 = 0x80;
+
+// anything before this means the pi booted without network and the clock
+// is still counting from 1970
+pub const SYNCED_YEAR: i32 = 2020;
+
+#[derive(Error, Debug)]
+pub enum RtcError {
+    #[error("I2C error: {0}")]
+    I2c(#[from] I2CError),
+    #[error("RTC has no valid time")]
+    InvalidTime,
+}
+
+// DS3231 on the i2c bus, keeps time in UTC
+pub struct Rtc {
+    i2c: I2cdev,
+}
+
+fn from_bcd(v: u8) -> u32 {
+    ((v >> 4) * 10 + (v & 0x0F)) as u32
+}
+
+fn to_bcd(v: u32) -> u8 {
+    (((v / 10) << 4) | (v % 10)) as u8
+}
+
+impl Rtc {
+    pub fn open(i2c_path: &str) -> Result<Self, RtcError> {
+        let i2c = I2cdev::new(i2c_path).map_err(I2CError::from)?;
+        let mut rtc = Rtc { i2c };
+
+        // make sure something actually answers on the address
+        rtc.read_time()?;
+        Ok(rtc)
+    }
+
+    pub fn read_time(&mut self) -> Result<NaiveDateTime, RtcError> {
+        let mut status = [0u8; 1];
+        self.i2c
+            .write_read(DS3231_ADDR, &[REG_STATUS], &mut status)?;
+        if status[0] & STATUS_OSF != 0 {
+            return Err(RtcError::InvalidTime);
+        }
+
+        let mut regs = [0u8; 7];
+        self.i2c.write_read(DS3231_ADDR, &[REG_TIME], &mut regs)?;
+
+        let sec = from_bcd(regs[0] & 0x7F);
+        let min = from_bcd(regs[1] & 0x7F);
+        let hour = if regs[2] & 0x40 != 0 {
+            // 12 hour mode, bit 5 is pm
+            let h = from_bcd(regs[2] & 0x1F) % 12;
+            if regs[2] & 0x20 != 0 {
+                h + 12
+            } else {
+                h
+            }
+        } else {
+            from_bcd(regs[2] & 0x3F)
+        };
+        let day = from_bcd(regs[4] & 0x3F);
+        let month = from_bcd(regs[5] & 0x1F);
+        let century = if regs[5] & 0x80 != 0 { 2100 } else { 2000 };
+        let year = century + from_bcd(regs[6]) as i32;
+
+        NaiveDate::from_ymd_opt(year, month, day)
+            .and_then(|d| d.and_hms_opt(hour, min, sec))
+            .ok_or(RtcError::InvalidTime)
+    }
+
+    pub fn write_time(&mut self, time: NaiveDateTime) -> Result<(), RtcError> {
+        let year = time.year() - 2000;
+        if !(0..200).contains(&year) {
+            return Err(RtcError::InvalidTime);
+        }
+        let century = if year >= 100 { 0x80 } else { 0 };
+
+        self.i2c.write(
+            DS3231_ADDR,
+            &[
+                REG_TIME,
+                to_bcd(time.second()),
+                to_bcd(time.minute()),
+                to_bcd(time.hour()),
+                to_bcd(time.weekday().number_from_sunday()),
+                to_bcd(time.day()),
+                to_bcd(time.month()) | century,
+                to_bcd(year as u32 % 100),
+            ],
+        )?;
+
+        // time is valid again, clear the oscillator stop flag
+        let mut status = [0u8; 1];
+        self.i2c
+            .write_read(DS3231_ADDR, &[REG_STATUS], &mut status)?;
+        self.i2c
+            .write(DS3231_ADDR, &[REG_STATUS, status[0] & !STATUS_OSF])?;
+        Ok(())
+    }
+}
+
+pub fn system_time_synced() -> bool {
+    Local::now().year() >= SYNCED_YEAR
+}
+
+// picks the best time we have, system time once it looks synced and the
+// RTC before that. falls back to system time if there's no RTC
+pub struct Clock {
+    rtc: Option<Rtc>,
+    synced: bool,
+    // reads have been failing since the last one that worked, so the log
+    // only says so once a streak and not every frame
+    failing: bool,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    // just the system time
+    pub fn new() -> Self {
+        Clock {
+            rtc: None,
+            synced: system_time_synced(),
+            failing: false,
+        }
+    }
+
+    // the RTC on [clock] i2c_path when use_rtc is on, the system time
+    // otherwise or if nothing answers there
+    pub fn from_config(cfg: &ClockSection) -> Self {
+        let mut clock = Self::new();
+        if !cfg.use_rtc {
+            return clock;
+        }
+        match Rtc::open(&cfg.i2c_path) {
+            Ok(rtc) => clock.rtc = Some(rtc),
+            Err(e) => warn!("No RTC on {} ({}), using system time", cfg.i2c_path, e),
+        }
+        clock
+    }
+
+    pub fn now(&mut self) -> DateTime<Local> {
+        if system_time_synced() {
+            // system time just got synced (NTP), save it for the next boot
+            if !self.synced {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    if let Err(e) = rtc.write_time(Utc::now().naive_utc()) {
+                        warn!("Failed to write RTC: {}", e);
+                    }
+                }
+                self.synced = true;
+            }
+            return Local::now();
+        }
+
+        match self.rtc.as_mut().map(|rtc| rtc.read_time()) {
+            Some(Ok(time)) => {
+                if std::mem::take(&mut self.failing) {
+                    info!("RTC reads are working again");
+                }
+                Utc.from_utc_datetime(&time).with_timezone(&Local)
+            }
+            Some(Err(e)) => {
+                if !self.failing {
+                    warn!(
+                        "Failed to read RTC, using system time until it answers: {}",
+                        e
+                    );
+                    self.failing = true;
+                }
+                Local::now()
+            }
+            None => Local::now(),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeZone, Timelike};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::transition::Transition;
+
+#[derive(Error, Debug)]
+pub enum ScheduleError {
+    #[error("HOME is not set, don't know where to keep the display mode")]
+    NoHome,
+    #[error("Failed to read or write the display mode: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Bad display mode file: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Bad cron schedule \"{0}\": {1}")]
+    Cron(String, String),
+}
+
+// everything the display loop can be showing, in the config and the saved
+// mode as snake_case
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayMode {
+    Clock,
+    NowPlaying,
+    Weather,
+    #[default]
+    PetStats,
+    StepCount,
+    SystemInfo,
+    // the interface, address and signal, see network::NetworkInfo
+    Network,
+    // the cpu's and a DS18B20's, see temperature.rs
+    Thermometer,
+    Pomodoro,
+    // spotify playlists, one at a time, see playlist::PlaylistBrowserMode
+    Playlists,
+    // refresh, timezone and pet name, see menu::Menu
+    Settings,
+}
+
+// when a task fires. in the config it's one of `every_minutes = 15`,
+// `daily = "07:30"` or `cron = "0 22 * * 1-5"`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum Schedule {
+    #[serde(rename = "every_minutes", deserialize_with = "minutes")]
+    Every(Duration),
+    #[serde(rename = "daily")]
+    Daily(NaiveTime),
+    // minute hour day-of-month month day-of-week, local time
+    #[serde(rename = "cron")]
+    Cron(String),
+}
+
+fn minutes<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    match u64::deserialize(d)? {
+        0 => Err(serde::de::Error::custom(
+            "every_minutes has to be at least 1",
+        )),
+        m => Ok(Duration::from_secs(m * 60)),
+    }
+}
+
+// one [[schedule]] entry, switches to mode whenever schedule fires
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ScheduledTask {
+    #[serde(flatten)]
+    pub schedule: Schedule,
+    pub mode: DisplayMode,
+    // how the switch looks, instant if it's left out
+    #[serde(default)]
+    pub transition: Transition,
+}
+
+// ~/.rpigotchi/mode.json
+pub fn mode_path() -> Result<PathBuf, ScheduleError> {
+    let home = env::var_os("HOME").ok_or(ScheduleError::NoHome)?;
+    Ok(PathBuf::from(home).join(".rpigotchi").join("mode.json"))
+}
+
+// None if no mode has been saved yet
+pub fn load_mode(path: &Path) -> Result<Option<DisplayMode>, ScheduleError> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(serde_json::from_str(&text)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_mode(path: &Path, mode: DisplayMode) -> Result<(), ScheduleError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(&mode)?)?;
+    Ok(())
+}
+
+// a parsed cron line, one bit per allowed value in each field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // cron's odd rule: when both day fields are restricted either one will do
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    // five fields, each `*`, a number, a range `a-b`, any of those with a
+    // step `/n`, or a comma separated list of them. sunday is 0 or 7
+    pub fn parse(line: &str) -> Result<Self, ScheduleError> {
+        let bad = |why: &str| ScheduleError::Cron(line.to_string(), why.to_string());
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(bad("needs 5 fields"));
+        };
+
+        let mut weekdays = parse_field(weekday, 0, 7).map_err(|e| bad(&e))?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59).map_err(|e| bad(&e))?,
+            hours: parse_field(hour, 0, 23).map_err(|e| bad(&e))?,
+            days: parse_field(day, 1, 31).map_err(|e| bad(&e))?,
+            months: parse_field(month, 1, 12).map_err(|e| bad(&e))?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    pub fn matches(&self, at: &NaiveDateTime) -> bool {
+        let has = |set: u64, v: u32| set & (1 << v) != 0;
+        let day = has(self.days, at.day());
+        let weekday = has(self.weekdays, at.weekday().num_days_from_sunday());
+        let day_ok = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        has(self.minutes, at.minute())
+            && has(self.hours, at.hour())
+            && has(self.months, at.month())
+            && day_ok
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|&s| s > 0)
+                    .ok_or_else(|| format!("bad step in \"{}\"", part))?,
+            ),
+            None => (part, 1),
+        };
+        let number = |s: &str| {
+            s.parse::<u32>()
+                .ok()
+                .filter(|v| (min..=max).contains(v))
+                .ok_or_else(|| format!("\"{}\" isn't {}-{}", s, min, max))
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((a, b)) => (number(a)?, number(b)?),
+                None => {
+                    let v = number(range)?;
+                    // `5/10` means from 5 to the end, every 10
+                    (v, if step > 1 { max } else { v })
+                }
+            },
+        };
+        if from > to {
+            return Err(format!("\"{}\" goes backwards", part));
+        }
+        for v in (from..=to).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+struct Pending {
+    task: ScheduledTask,
+    cron: Option<Cron>,
+    // for Every, when it's next due
+    next: Instant,
+}
+
+// keeps track of which scheduled tasks have fired. polled every frame
+pub struct TaskRunner {
+    tasks: Vec<Pending>,
+    last_poll: Option<NaiveDateTime>,
+}
+
+impl TaskRunner {
+    // the config checked the cron lines already, one that still doesn't
+    // parse never fires
+    pub fn new(tasks: &[ScheduledTask]) -> Self {
+        let started = Instant::now();
+        let tasks = tasks
+            .iter()
+            .map(|task| {
+                let (cron, next) = match &task.schedule {
+                    Schedule::Every(every) => (None, started + *every),
+                    Schedule::Daily(_) => (None, started),
+                    Schedule::Cron(line) => match Cron::parse(line) {
+                        Ok(cron) => (Some(cron), started),
+                        Err(e) => {
+                            warn!("Skipping scheduled task: {}", e);
+                            (None, started)
+                        }
+                    },
+                };
+                Pending {
+                    task: task.clone(),
+                    cron,
+                    next,
+                }
+            })
+            .collect();
+        TaskRunner {
+            tasks,
+            last_poll: None,
+        }
+    }
+
+    // the mode to switch to, and how, if anything fired since the last poll, the last
+    // one in the config wins if several did. the first poll only starts the
+    // clock, nothing that was due before the app started fires. now is in
+    // the zone the schedule's times are meant in
+    pub fn poll<Tz: TimeZone>(&mut self, now: DateTime<Tz>) -> Option<(DisplayMode, Transition)> {
+        let now = now.naive_local();
+        let last = self.last_poll.replace(now)?;
+        if now <= last {
+            return None;
+        }
+
+        let mut mode = None;
+        for pending in &mut self.tasks {
+            let fired = match &pending.task.schedule {
+                Schedule::Every(every) => {
+                    let fired = Instant::now() >= pending.next;
+                    if fired {
+                        pending.next += *every;
+                        // fell behind (suspended?), don't fire for every missed one
+                        if pending.next < Instant::now() {
+                            pending.next = Instant::now() + *every;
+                        }
+                    }
+                    fired
+                }
+                // yesterday's too, for the poll that goes over midnight
+                Schedule::Daily(time) => [last.date(), now.date()]
+                    .iter()
+                    .map(|date| date.and_time(*time))
+                    .any(|at| last < at && at <= now),
+                // once, in the first poll of a matching minute
+                Schedule::Cron(_) => {
+                    let minute =
+                        |at: NaiveDateTime| at.with_second(0).and_then(|at| at.with_nanosecond(0));
+                    pending
+                        .cron
+                        .is_some_and(|cron| minute(now) != minute(last) && cron.matches(&now))
+                }
+            };
+            if fired {
+                mode = Some((pending.task.mode, pending.task.transition));
+            }
+        }
+        mode
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+// how far the screen has wound down, in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Awake,
+    // the panel sleeps with the last frame still on it, e-paper keeps it
+    // without power. the loop stops drawing until something happens
+    Idle,
+    // e-paper keeps a faint copy of whatever sat on it for days, so after
+    // a long while the panel gets blanked as well
+    Blank,
+}
+
+// goes Awake, Idle then Blank as the timeouts run out without anyone
+// touching it, any activity puts it back to Awake
+pub struct ScreenSaver {
+    idle_after: Duration,
+    blank_after: Duration,
+    last_activity: Instant,
+    stage: Stage,
+}
+
+impl ScreenSaver {
+    // blanks straight away at blank_after if idle_after isn't sooner
+    pub fn new(idle_after: Duration, blank_after: Duration) -> Self {
+        ScreenSaver {
+            idle_after,
+            blank_after,
+            last_activity: Instant::now(),
+            stage: Stage::Awake,
+        }
+    }
+
+    // a button press, a new track, anything worth showing. true if the
+    // screen was asleep and has to be woken up
+    pub fn activity(&mut self) -> bool {
+        self.last_activity = Instant::now();
+        std::mem::replace(&mut self.stage, Stage::Awake) != Stage::Awake
+    }
+
+    // the stage it's just gone into, once, when a timeout runs out
+    pub fn due(&mut self) -> Option<Stage> {
+        let quiet = self.last_activity.elapsed();
+        let stage = if quiet >= self.blank_after {
+            Stage::Blank
+        } else if quiet >= self.idle_after {
+            Stage::Idle
+        } else {
+            Stage::Awake
+        };
+        if stage <= self.stage {
+            return None;
+        }
+        self.stage = stage;
+        Some(stage)
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+
+    pub fn is_asleep(&self) -> bool {
+        self.stage != Stage::Awake
+    }
+}
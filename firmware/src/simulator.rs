@@ -0,0 +1,94 @@
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_graphics_simulator::{
+    BinaryColorTheme, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+use epd_waveshare::{color::Color, graphics::DisplayRotation, prelude::RefreshLut};
+use tracing::info;
+
+use crate::config::DisplayConfig;
+use crate::driver::EpdDriver;
+use crate::gpio::HardwareBackend;
+use crate::region::{self, Window as PanelWindow};
+use crate::EpaperError;
+
+// the panel as a window on the desktop, for working on layouts without a pi.
+// needs SDL2, build with `--features simulator`
+const SCALE: u32 = 3;
+
+pub struct SimPanel {
+    window: Window,
+    // what the panel would show, the right way up
+    screen: SimulatorDisplay<BinaryColor>,
+    rotation: DisplayRotation,
+}
+
+impl SimPanel {
+    // takes the same as driver::Panel so either fits in EpaperApp. no spi,
+    // and there's no pins to claim
+    pub fn open(cfg: &DisplayConfig, _backend: &impl HardwareBackend) -> Result<Self, EpaperError> {
+        let rotation = cfg.display.rotation;
+        let settings = OutputSettingsBuilder::new()
+            .scale(SCALE)
+            .theme(BinaryColorTheme::LcdWhite)
+            .build();
+
+        info!("Simulating the panel in a window");
+        Ok(SimPanel {
+            window: Window::new("rpigotchi", &settings),
+            screen: SimulatorDisplay::new(region::rotated_size(rotation)),
+            rotation,
+        })
+    }
+
+    // copies the frame into the window and handles whatever happened to it
+    // since. closing the window is a Ctrl+C, so the pet still gets saved
+    fn show(&mut self, buffer: &[u8]) {
+        let rotation = self.rotation;
+        let pixels = self.screen.bounding_box().points().map(|p| {
+            let black = region::pixel_at(buffer, rotation, p) == Some(Color::Black);
+            Pixel(p, BinaryColor::from(black))
+        });
+        let Ok(()) = self.screen.draw_iter(pixels);
+        self.window.update(&self.screen);
+
+        if self.window.events().any(|e| e == SimulatorEvent::Quit) {
+            info!("Simulator window closed");
+            signal_hook::low_level::raise(signal_hook::consts::SIGINT).ok();
+        }
+    }
+}
+
+// the simulator has no busy line or LUTs, every refresh just redraws it all
+impl EpdDriver for SimPanel {
+    fn update_and_display_frame(&mut self, buffer: &[u8]) -> Result<(), EpaperError> {
+        self.show(buffer);
+        Ok(())
+    }
+
+    fn set_refresh(&mut self, _refresh: RefreshLut) -> Result<(), EpaperError> {
+        Ok(())
+    }
+
+    fn clear_frame(&mut self) -> Result<(), EpaperError> {
+        let Ok(()) = self.screen.clear(BinaryColor::Off);
+        Ok(())
+    }
+
+    fn display_frame(&mut self) -> Result<(), EpaperError> {
+        self.window.update(&self.screen);
+        Ok(())
+    }
+
+    fn sleep(&mut self) -> Result<(), EpaperError> {
+        Ok(())
+    }
+
+    fn wake_up(&mut self) -> Result<(), EpaperError> {
+        Ok(())
+    }
+
+    fn update_window(&mut self, buffer: &[u8], _window: &PanelWindow) -> Result<(), EpaperError> {
+        self.show(buffer);
+        Ok(())
+    }
+}
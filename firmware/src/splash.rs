@@ -0,0 +1,86 @@
+use embedded_graphics::prelude::*;
+use epd_waveshare::color::Color;
+use std::path::Path;
+use std::time::Duration;
+use tracing::warn;
+
+use crate::face::{self, Face, FACE_SIZE};
+use crate::sprites::Sprite;
+use crate::utils::{aligned_x, draw_sprite, draw_text_styled, Alignment, Canvas, FONT_10X20};
+use crate::{dither, EpaperError};
+
+// how long the splash stays up before the first screen
+pub const SPLASH_HOLD: Duration = Duration::from_secs(2);
+
+const SPLASH_TITLE: &str = "rpigotchi";
+
+// someone's own picture, scaled to cover the whole screen with whatever
+// sticks out cropped off the middle, then dithered to 1 bit. packed like a
+// Sprite
+pub struct Picture {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Picture {
+    // width and height are the screen's, the way it's rotated. anything
+    // the image crate reads with png and jpeg turned on will do
+    pub fn load(path: &Path, width: u32, height: u32) -> Result<Self, EpaperError> {
+        let gray = image::open(path)
+            .map_err(|e| EpaperError::ImageDecode(format!("{}: {}", path.display(), e)))?
+            .resize_to_fill(width, height, image::imageops::FilterType::Triangle)
+            .into_luma8();
+        Ok(Picture {
+            width,
+            height,
+            pixels: dither::floyd_steinberg(gray.as_raw(), width as usize, height as usize),
+        })
+    }
+
+    pub fn sprite(&self) -> Sprite<'_> {
+        Sprite {
+            width: self.width,
+            height: self.height,
+            data: &self.pixels,
+        }
+    }
+}
+
+// picture over the whole screen, or the happy face with the name under it
+// when there isn't one. draws over whatever's there, clear it first
+pub fn draw_splash(display: &mut impl Canvas, picture: Option<&Picture>) {
+    if let Some(picture) = picture {
+        draw_sprite(display, &picture.sprite(), 0, 0);
+        return;
+    }
+    let size = display.bounding_box().size;
+    let title_height = FONT_10X20.character_size.height as i32;
+    let top = (size.height as i32 - FACE_SIZE as i32 - title_height - 4) / 2;
+    let x = (size.width as i32 - FACE_SIZE as i32) / 2;
+    face::draw_face(display, Point::new(x, top), Face::Happy);
+
+    let x = aligned_x(display, SPLASH_TITLE, Alignment::Center, &FONT_10X20);
+    draw_text_styled(
+        display,
+        SPLASH_TITLE,
+        x,
+        top + FACE_SIZE as i32 + 4,
+        &FONT_10X20,
+        Color::Black,
+        Color::White,
+    );
+}
+
+// the picture at path if it loads, None with a warning if it doesn't so
+// the default is drawn instead
+pub fn load_or_default(path: Option<&Path>, width: u32, height: u32) -> Option<Picture> {
+    let path = path?;
+    match Picture::load(path, width, height) {
+        Ok(picture) => Some(picture),
+        Err(e) => {
+            warn!("Using the default instead: {}", e);
+            None
+        }
+    }
+}
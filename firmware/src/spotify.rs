@@ -1,25 +1,1049 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use dotenv::dotenv;
+use reqwest::Url;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::dither;
+use crate::logger;
+use crate::sprites::Sprite;
+
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+// episodes are only returned when asked for
+const CURRENTLY_PLAYING_URL: &str =
+    "https://api.spotify.com/v1/me/player/currently-playing?additional_types=track,episode";
+const PLAYLISTS_URL: &str = "https://api.spotify.com/v1/me/playlists?limit=50";
+const PLAY_URL: &str = "https://api.spotify.com/v1/me/player/play";
+const PAUSE_URL: &str = "https://api.spotify.com/v1/me/player/pause";
+const NEXT_URL: &str = "https://api.spotify.com/v1/me/player/next";
+const PREVIOUS_URL: &str = "https://api.spotify.com/v1/me/player/previous";
+// playlist_tracks only needs these, the full track objects are huge
+const PLAYLIST_TRACK_FIELDS: &str = "items(track(name,artists(name)))";
+const SCOPES: &str = "user-read-currently-playing user-read-playback-state \
+                      user-modify-playback-state playlist-read-private";
+
+pub const DEFAULT_REDIRECT_PORT: u16 = 8888;
+
+// album art gets squashed to this many pixels square, the size of spotify's
+// smallest cover. it fills the top half of the now playing screen
+pub const ART_SIZE: u32 = 64;
+// spotify starts answering 429 if it's asked every frame
+pub const DEFAULT_TRACK_TTL: Duration = Duration::from_secs(5);
+
+// refresh a little before spotify actually expires the token
+const EXPIRY_GRACE_SECS: u64 = 60;
+
+#[derive(Error, Debug)]
+pub enum SpotifyError {
+    #[error("CLIENT_ID is not set, put your spotify app's client id in .env")]
+    MissingClientId,
+    #[error("CLIENT_SECRET is not set, put your spotify app's client secret in .env")]
+    MissingClientSecret,
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Bad response from Spotify: {0}")]
+    BadResponse(String),
+    #[error("Redirect server error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to read or write the token file: {0}")]
+    TokenFile(std::io::Error),
+    #[error("Bad token file: {0}")]
+    TokenJson(#[from] serde_json::Error),
+    // the player endpoints answer 404 when no phone or speaker is playing,
+    // or has been lately
+    #[error("No active Spotify device, start playing something on one first")]
+    NoActiveDevice,
+    #[error("Couldn't decode album art: {0}")]
+    Image(#[from] image::ImageError),
+}
 
 pub struct Client {
     client_id: String,
-    client_secret: String
+    client_secret: String,
+    redirect_port: u16,
+    http: reqwest::blocking::Client,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Token {
     access_token: String,
     refresh_token: String,
-    time: u64
+    // unix timestamp (seconds) the access token expires at
+    time: u64,
+    // the file it was loaded from or saved to, refreshes get written back
+    // there so a rotated refresh token survives a restart
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    // spotify may leave this out on refresh, then the old one stays valid
+    refresh_token: Option<String>,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
-pub fn get_client_data() -> Client {
+// random url safe string for the state param
+fn random_string(bytes: usize) -> Result<String, SpotifyError> {
+    let mut buf = vec![0u8; bytes];
+    File::open("/dev/urandom")?.read_exact(&mut buf)?;
+    Ok(URL_SAFE_NO_PAD.encode(buf))
+}
+
+pub fn get_client_data() -> Result<Client, SpotifyError> {
     dotenv().ok();
 
-    let client_id = env::var("CLIENT_ID").unwrap();
-    let client_secret = env::var("CLIENT_SECRET").unwrap();
+    // an empty value is as good as missing
+    let var = |name| env::var(name).ok().filter(|v| !v.is_empty());
+    let client_id = var("CLIENT_ID").ok_or(SpotifyError::MissingClientId)?;
+    let client_secret = var("CLIENT_SECRET").ok_or(SpotifyError::MissingClientSecret)?;
 
-    Client {
+    Ok(Client {
         client_id,
-        client_secret
+        client_secret,
+        redirect_port: DEFAULT_REDIRECT_PORT,
+        http: reqwest::blocking::Client::new(),
+    })
+}
+
+impl Client {
+    pub fn with_redirect_port(mut self, port: u16) -> Self {
+        self.redirect_port = port;
+        self
+    }
+
+    fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}/callback", self.redirect_port)
+    }
+
+    // authorization code flow. prints the url to open and waits on the local
+    // redirect server for spotify to hand back the code, which is swapped for
+    // a token using the client secret
+    pub fn authorize(&self) -> Result<Token, SpotifyError> {
+        let state = random_string(16)?;
+        let redirect_uri = self.redirect_uri();
+
+        let url = Url::parse_with_params(
+            AUTHORIZE_URL,
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("response_type", "code"),
+                ("redirect_uri", redirect_uri.as_str()),
+                ("scope", SCOPES),
+                ("state", state.as_str()),
+            ],
+        )
+        .map_err(|e| SpotifyError::BadResponse(e.to_string()))?;
+
+        let listener = TcpListener::bind(("127.0.0.1", self.redirect_port))?;
+        println!("Open this url to authorize spotify:\n{}", url);
+
+        let code = Self::wait_for_code(&listener, &state)?;
+
+        info!("spotify: exchanging authorization code for a token");
+        logger::record_spotify_call();
+        let response = self
+            .http
+            .post(TOKEN_URL)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code.as_str()),
+                ("redirect_uri", redirect_uri.as_str()),
+            ])
+            .send()?;
+        let response = Self::token_response(response)?;
+
+        let refresh_token = response
+            .refresh_token
+            .ok_or_else(|| SpotifyError::BadResponse("no refresh token in response".to_string()))?;
+
+        Ok(Token {
+            access_token: response.access_token,
+            refresh_token,
+            time: unix_now() + response.expires_in,
+            path: None,
+        })
+    }
+
+    // handles a single request to the redirect uri and pulls the code out of it
+    fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String, SpotifyError> {
+        let (mut stream, _) = listener.accept()?;
+
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+
+        // GET /callback?code=...&state=... HTTP/1.1
+        let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+        let url = Url::parse(&format!("http://127.0.0.1{}", path))
+            .map_err(|e| SpotifyError::BadResponse(e.to_string()))?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+
+        let result = if let Some(error) = param("error") {
+            Err(SpotifyError::BadResponse(format!(
+                "authorization denied: {}",
+                error
+            )))
+        } else if param("state").as_deref() != Some(state) {
+            Err(SpotifyError::BadResponse("state mismatch".to_string()))
+        } else {
+            param("code").ok_or_else(|| SpotifyError::BadResponse("no code".to_string()))
+        };
+
+        let body = match result {
+            Ok(_) => "Authorized, you can close this window.",
+            Err(_) => "Authorization failed, check the terminal.",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+
+        result
+    }
+
+    // the saved token if there is one that still works, refreshed if it had
+    // run out, otherwise the whole browser flow again. that needs someone at
+    // the keyboard, run from a service it's an error instead of waiting for
+    // a redirect that never comes. whatever comes out is saved for next time
+    pub fn login(&self, path: &Path) -> Result<Token, SpotifyError> {
+        let saved = Token::load(path).and_then(|mut token| {
+            self.valid_token(&mut token)?;
+            Ok(token)
+        });
+
+        let mut token = match saved {
+            Ok(token) => token,
+            Err(e) if !io::stdin().is_terminal() => return Err(e),
+            Err(e) => {
+                info!("No usable saved token ({}), authorizing again", e);
+                self.authorize()?
+            }
+        };
+        token.save(path)?;
+        token.path = Some(path.to_path_buf());
+        Ok(token)
     }
-}
\ No newline at end of file
+
+    // swaps the refresh token for a new access token
+    pub fn refresh(&self, token: &mut Token) -> Result<(), SpotifyError> {
+        info!("spotify: refreshing access token");
+        logger::record_spotify_call();
+        let response = self
+            .http
+            .post(TOKEN_URL)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", token.refresh_token.as_str()),
+            ])
+            .send()?;
+        let response = Self::token_response(response)?;
+
+        token.access_token = response.access_token;
+        if let Some(refresh_token) = response.refresh_token {
+            token.refresh_token = refresh_token;
+        }
+        token.time = unix_now() + response.expires_in;
+        // the refresh worked either way, the old one on disk just stays
+        if let Some(path) = &token.path {
+            if let Err(e) = token.save(path) {
+                warn!("Failed to save the refreshed token: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    // the access token to call the api with, refreshed first if it has run out.
+    // anything that talks to the api should go through this
+    pub fn valid_token<'a>(&self, token: &'a mut Token) -> Result<&'a str, SpotifyError> {
+        if token.is_expired() {
+            self.refresh(token)?;
+        }
+        Ok(&token.access_token)
+    }
+
+    // what's playing right now, None when nothing is (spotify answers 204
+    // with no body then)
+    pub fn now_playing(&self, token: &mut Token) -> Result<Option<NowPlaying>, SpotifyError> {
+        let access_token = self.valid_token(token)?;
+        info!("spotify: fetching currently playing");
+        logger::record_spotify_call();
+        let response = self
+            .http
+            .get(CURRENTLY_PLAYING_URL)
+            .bearer_auth(access_token)
+            .send()?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+        let body = response.text()?;
+        if !status.is_success() {
+            return Err(SpotifyError::BadResponse(format!("{}: {}", status, body)));
+        }
+
+        NowPlaying::from_json(&body).map_err(|e| SpotifyError::BadResponse(e.to_string()))
+    }
+
+    // all of the user's playlists, own and followed, in the order spotify
+    // lists them. their tracks are left empty, see playlist_tracks
+    pub fn get_playlists(&self, token: &mut Token) -> Result<Vec<Playlist>, SpotifyError> {
+        let mut playlists = Vec::new();
+        let mut next = Some(PLAYLISTS_URL.to_string());
+        while let Some(url) = next {
+            let page: Page<PlaylistItem> = self.get_json(token, &url)?;
+            playlists.extend(page.items.into_iter().map(|item| Playlist {
+                id: item.id,
+                name: item.name,
+                track_count: item.tracks.total,
+                tracks: Vec::new(),
+            }));
+            next = page.next;
+        }
+        Ok(playlists)
+    }
+
+    // the first limit tracks of a playlist, episodes and tracks that have
+    // gone from spotify are skipped
+    pub fn playlist_tracks(
+        &self,
+        token: &mut Token,
+        id: &str,
+        limit: u32,
+    ) -> Result<Vec<TrackSummary>, SpotifyError> {
+        let url = format!(
+            "https://api.spotify.com/v1/playlists/{}/tracks?limit={}&fields={}",
+            id, limit, PLAYLIST_TRACK_FIELDS
+        );
+        let page: Page<PlaylistTrack> = self.get_json(token, &url)?;
+        Ok(page
+            .items
+            .into_iter()
+            .filter_map(|item| item.track)
+            .map(|track| TrackSummary {
+                name: track.name,
+                artist: track
+                    .artists
+                    .into_iter()
+                    .map(|a| a.name)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            })
+            .collect())
+    }
+
+    // starts the playlist from the top on whichever device is active
+    pub fn play_playlist(&self, token: &mut Token, id: &str) -> Result<(), SpotifyError> {
+        let body = serde_json::json!({ "context_uri": format!("spotify:playlist:{}", id) });
+        self.player(token, reqwest::Method::PUT, PLAY_URL, Some(body))
+    }
+
+    // carries on with whatever was playing on the active device
+    pub fn play(&self, token: &mut Token) -> Result<(), SpotifyError> {
+        self.player(token, reqwest::Method::PUT, PLAY_URL, None)
+    }
+
+    pub fn pause(&self, token: &mut Token) -> Result<(), SpotifyError> {
+        self.player(token, reqwest::Method::PUT, PAUSE_URL, None)
+    }
+
+    pub fn next(&self, token: &mut Token) -> Result<(), SpotifyError> {
+        self.player(token, reqwest::Method::POST, NEXT_URL, None)
+    }
+
+    pub fn previous(&self, token: &mut Token) -> Result<(), SpotifyError> {
+        self.player(token, reqwest::Method::POST, PREVIOUS_URL, None)
+    }
+
+    // one of the /me/player commands. spotify answers 204 when it's done
+    // it, some of them 200
+    fn player(
+        &self,
+        token: &mut Token,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<(), SpotifyError> {
+        let access_token = self.valid_token(token)?;
+        info!("spotify: {} {}", method, url);
+        logger::record_spotify_call();
+        let mut request = self.http.request(method, url).bearer_auth(access_token);
+        request = match body {
+            Some(body) => request.json(&body),
+            // spotify wants a length even with nothing in it
+            None => request.header(reqwest::header::CONTENT_LENGTH, 0),
+        };
+        let response = request.send()?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_FOUND {
+            return Err(SpotifyError::NoActiveDevice);
+        }
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(SpotifyError::BadResponse(format!("{}: {}", status, body)));
+        }
+        Ok(())
+    }
+
+    fn get_json<T: DeserializeOwned>(
+        &self,
+        token: &mut Token,
+        url: &str,
+    ) -> Result<T, SpotifyError> {
+        let access_token = self.valid_token(token)?;
+        info!("spotify: fetching {}", url);
+        logger::record_spotify_call();
+        let response = self.http.get(url).bearer_auth(access_token).send()?;
+
+        let status = response.status();
+        let body = response.text()?;
+        if !status.is_success() {
+            return Err(SpotifyError::BadResponse(format!("{}: {}", status, body)));
+        }
+        serde_json::from_str(&body).map_err(|e| SpotifyError::BadResponse(e.to_string()))
+    }
+
+    fn token_response(
+        response: reqwest::blocking::Response,
+    ) -> Result<TokenResponse, SpotifyError> {
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().unwrap_or_default();
+            return Err(SpotifyError::BadResponse(format!("{}: {}", status, body)));
+        }
+
+        response
+            .json()
+            .map_err(|e| SpotifyError::BadResponse(e.to_string()))
+    }
+}
+
+impl Token {
+    pub fn load(path: &Path) -> Result<Self, SpotifyError> {
+        let text = fs::read_to_string(path).map_err(SpotifyError::TokenFile)?;
+        let mut token: Token = serde_json::from_str(&text)?;
+        token.path = Some(path.to_path_buf());
+        Ok(token)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SpotifyError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(SpotifyError::TokenFile)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?).map_err(SpotifyError::TokenFile)
+    }
+
+    // counts as expired a little early so a request never goes out with a
+    // token that runs out on the way
+    pub fn is_expired(&self) -> bool {
+        unix_now() + EXPIRY_GRACE_SECS >= self.time
+    }
+}
+
+// what the currently-playing endpoint is playing, spotify sends a
+// different item shape for tracks and podcast episodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayingKind {
+    Track,
+    Episode,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NowPlaying {
+    pub kind: PlayingKind,
+    // track name or episode name
+    pub title: String,
+    // artists for a track, show name for an episode
+    pub artist: String,
+    // album for a track, publisher for an episode
+    pub album: String,
+    pub progress_ms: u64,
+    pub duration_ms: u64,
+    pub is_playing: bool,
+    // the album's id for a track, the show's for an episode. the cover goes
+    // with it
+    pub album_id: Option<String>,
+    // album cover for a track, show cover for an episode
+    pub art_url: Option<String>,
+    // the track's or episode's own id, what tells one from the next. local
+    // files don't have one
+    pub id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CurrentlyPlaying {
+    currently_playing_type: String,
+    #[serde(default)]
+    is_playing: bool,
+    progress_ms: Option<u64>,
+    item: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct Image {
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct Album {
+    id: Option<String>,
+    name: String,
+    // widest first
+    #[serde(default)]
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize)]
+struct TrackItem {
+    id: Option<String>,
+    name: String,
+    duration_ms: u64,
+    artists: Vec<Named>,
+    album: Album,
+}
+
+#[derive(Deserialize)]
+struct Show {
+    id: Option<String>,
+    name: String,
+    publisher: String,
+    #[serde(default)]
+    images: Vec<Image>,
+}
+
+#[derive(Deserialize)]
+struct EpisodeItem {
+    id: Option<String>,
+    name: String,
+    duration_ms: u64,
+    show: Show,
+}
+
+impl NowPlaying {
+    // how far through, 0 to 1
+    pub fn progress(&self) -> f32 {
+        if self.duration_ms == 0 {
+            return 0.0;
+        }
+        (self.progress_ms as f32 / self.duration_ms as f32).min(1.0)
+    }
+
+    // parse a currently-playing response body, None when there is no item
+    // or it's something we can't show (ads, unknown)
+    pub fn from_json(body: &str) -> Result<Option<NowPlaying>, serde_json::Error> {
+        let current: CurrentlyPlaying = serde_json::from_str(body)?;
+        let item = match current.item {
+            Some(item) if !item.is_null() => item,
+            _ => return Ok(None),
+        };
+        let progress_ms = current.progress_ms.unwrap_or(0);
+
+        let now_playing = match current.currently_playing_type.as_str() {
+            "track" => {
+                let track: TrackItem = serde_json::from_value(item)?;
+                NowPlaying {
+                    kind: PlayingKind::Track,
+                    title: track.name,
+                    artist: track
+                        .artists
+                        .into_iter()
+                        .map(|a| a.name)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    album: track.album.name,
+                    progress_ms,
+                    duration_ms: track.duration_ms,
+                    is_playing: current.is_playing,
+                    album_id: track.album.id,
+                    // the smallest one (64px) is plenty for ART_SIZE
+                    art_url: track.album.images.into_iter().last().map(|i| i.url),
+                    id: track.id,
+                }
+            }
+            "episode" => {
+                let episode: EpisodeItem = serde_json::from_value(item)?;
+                NowPlaying {
+                    kind: PlayingKind::Episode,
+                    title: episode.name,
+                    artist: episode.show.name,
+                    album: episode.show.publisher,
+                    progress_ms,
+                    duration_ms: episode.duration_ms,
+                    is_playing: current.is_playing,
+                    album_id: episode.show.id,
+                    art_url: episode.show.images.into_iter().last().map(|i| i.url),
+                    id: episode.id,
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        Ok(Some(now_playing))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackSummary {
+    pub name: String,
+    // all of them, comma separated
+    pub artist: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Playlist {
+    pub id: String,
+    pub name: String,
+    // how many spotify says it has, tracks is usually just the first few
+    pub track_count: u32,
+    // empty until filled in from Client::playlist_tracks
+    pub tracks: Vec<TrackSummary>,
+}
+
+// one page of a paged endpoint, next is the url of the one after
+#[derive(Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TrackTotal {
+    total: u32,
+}
+
+#[derive(Deserialize)]
+struct PlaylistItem {
+    id: String,
+    name: String,
+    tracks: TrackTotal,
+}
+
+#[derive(Deserialize)]
+struct PlaylistTrackItem {
+    name: String,
+    artists: Vec<Named>,
+}
+
+#[derive(Deserialize)]
+struct PlaylistTrack {
+    // null for tracks that have been taken off spotify
+    track: Option<PlaylistTrackItem>,
+}
+
+// a cover dithered to ART_SIZE square, 1 bit packed like a Sprite
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlbumArt {
+    pixels: Vec<u8>,
+}
+
+impl AlbumArt {
+    // download a cover and dither it to ART_SIZE square. the image urls
+    // don't need the token
+    pub fn fetch(url: &str) -> Result<AlbumArt, SpotifyError> {
+        let response = reqwest::blocking::get(url)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(SpotifyError::BadResponse(format!("{}: {}", status, url)));
+        }
+        AlbumArt::decode(&response.bytes()?)
+    }
+
+    // a jpeg or png, any size
+    fn decode(bytes: &[u8]) -> Result<AlbumArt, SpotifyError> {
+        let gray = image::load_from_memory(bytes)?
+            .resize_exact(ART_SIZE, ART_SIZE, image::imageops::FilterType::Triangle)
+            .into_luma8();
+        Ok(AlbumArt {
+            pixels: dither::floyd_steinberg(gray.as_raw(), ART_SIZE as usize, ART_SIZE as usize),
+        })
+    }
+
+    pub fn sprite(&self) -> Sprite<'_> {
+        Sprite {
+            width: ART_SIZE,
+            height: ART_SIZE,
+            data: &self.pixels,
+        }
+    }
+}
+
+// the cover of whatever played last, so the same album isn't downloaded
+// again every refresh. goes by the album id, the url if there isn't one. a
+// cover that failed isn't tried again until the album changes
+#[derive(Default)]
+pub struct ArtCache {
+    key: Option<String>,
+    art: Option<AlbumArt>,
+}
+
+impl ArtCache {
+    // None if np has no cover
+    pub fn get(&mut self, np: &NowPlaying) -> Result<Option<&AlbumArt>, SpotifyError> {
+        let Some(url) = &np.art_url else {
+            return Ok(None);
+        };
+        let key = np.album_id.as_ref().unwrap_or(url);
+        if self.key.as_ref() != Some(key) {
+            self.key = Some(key.clone());
+            self.art = None;
+            self.art = Some(AlbumArt::fetch(url)?);
+        }
+        Ok(self.art.as_ref())
+    }
+}
+
+// what has to stay playing before it counts as on, so skipping through a
+// few in a row doesn't put each one up
+pub const TRACK_SETTLE: Duration = Duration::from_secs(8);
+
+// says when what's playing has changed to something new, going by the
+// track id (the title and artist for local files). a track has to be seen
+// for TRACK_SETTLE before it counts. pausing and coming back to the same
+// one isn't a change
+pub struct TrackChangeDetector {
+    last: Option<String>,
+    // the one that's playing but hasn't settled yet, and since when
+    pending: Option<(String, Instant)>,
+    settle: Duration,
+}
+
+impl Default for TrackChangeDetector {
+    fn default() -> Self {
+        Self::new(TRACK_SETTLE)
+    }
+}
+
+impl TrackChangeDetector {
+    pub fn new(settle: Duration) -> Self {
+        TrackChangeDetector {
+            last: None,
+            pending: None,
+            settle,
+        }
+    }
+
+    // call with every answer from spotify, the track that's playing once
+    // it's settled on a new one
+    pub fn observe(&mut self, np: Option<&NowPlaying>) -> Option<NowPlaying> {
+        self.observe_at(np, Instant::now())
+    }
+
+    fn observe_at(&mut self, np: Option<&NowPlaying>, now: Instant) -> Option<NowPlaying> {
+        let Some(np) = np else {
+            self.pending = None;
+            return None;
+        };
+        let key = np
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("{} - {}", np.title, np.artist));
+        if self.last.as_ref() == Some(&key) {
+            self.pending = None;
+            return None;
+        }
+        match &self.pending {
+            Some((pending, since)) if *pending == key => {
+                if now.duration_since(*since) < self.settle {
+                    return None;
+                }
+            }
+            _ => {
+                self.pending = Some((key, now));
+                return None;
+            }
+        }
+        self.pending = None;
+        self.last = Some(key);
+        Some(np.clone())
+    }
+}
+
+// the last now_playing answer, asked again once it's ttl old or the track
+// must have finished by now, whichever comes first
+pub struct TrackCache {
+    last_track: Option<NowPlaying>,
+    fetched_at: Option<Instant>,
+    ttl: Duration,
+}
+
+impl Default for TrackCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACK_TTL)
+    }
+}
+
+impl TrackCache {
+    pub fn new(ttl: Duration) -> Self {
+        TrackCache {
+            last_track: None,
+            fetched_at: None,
+            ttl,
+        }
+    }
+
+    // the cached track with its progress moved on by however long it's been
+    // playing since, or a fresh one from spotify
+    pub fn get_or_fetch(
+        &mut self,
+        client: &Client,
+        token: &mut Token,
+    ) -> Result<Option<NowPlaying>, SpotifyError> {
+        if let Some(track) = self.cached() {
+            return Ok(track);
+        }
+        // a failed ask waits out the ttl too, with the last answer standing
+        // in until then, rather than going again every frame
+        self.fetched_at = Some(Instant::now());
+        let track = client.now_playing(token)?;
+        self.last_track = track.clone();
+        Ok(track)
+    }
+
+    // None when it's time to ask spotify again
+    fn cached(&self) -> Option<Option<NowPlaying>> {
+        let elapsed = self.fetched_at?.elapsed();
+        if elapsed >= self.ttl {
+            return None;
+        }
+        let Some(track) = &self.last_track else {
+            return Some(None);
+        };
+
+        let mut track = track.clone();
+        if track.is_playing {
+            track.progress_ms += elapsed.as_millis() as u64;
+            // it's on to the next one, the cached track is no use
+            if track.duration_ms > 0 && track.progress_ms >= track.duration_ms {
+                return None;
+            }
+        }
+        Some(Some(track))
+    }
+
+    // keeps what's cached, only when it goes stale changes
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    // the next get_or_fetch asks spotify, after a skip say
+    pub fn invalidate(&mut self) {
+        self.fetched_at = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // trimmed down currently-playing answers, spotify sends a lot more
+    const TRACK: &str = r#"{
+        "currently_playing_type": "track",
+        "is_playing": true,
+        "progress_ms": 42000,
+        "item": {
+            "id": "5W3cjX2J3tjhG8zb6u0qHn",
+            "name": "Harder, Better, Faster, Stronger",
+            "duration_ms": 224000,
+            "artists": [{"name": "Daft Punk"}, {"name": "Kanye West"}],
+            "album": {
+                "id": "2noRn2Aes5aoNVsU6iWThc",
+                "name": "Discovery",
+                "images": [
+                    {"url": "https://i.scdn.co/image/640"},
+                    {"url": "https://i.scdn.co/image/64"}
+                ]
+            }
+        }
+    }"#;
+
+    const EPISODE: &str = r#"{
+        "currently_playing_type": "episode",
+        "is_playing": false,
+        "progress_ms": 1000,
+        "item": {
+            "name": "Episode 12: Ink",
+            "duration_ms": 3600000,
+            "show": {
+                "id": "show1",
+                "name": "Paper Talk",
+                "publisher": "E Ink Radio",
+                "images": []
+            }
+        }
+    }"#;
+
+    #[test]
+    fn parses_a_track() {
+        let np = NowPlaying::from_json(TRACK).unwrap().unwrap();
+        assert_eq!(np.kind, PlayingKind::Track);
+        assert_eq!(np.title, "Harder, Better, Faster, Stronger");
+        assert_eq!(np.artist, "Daft Punk, Kanye West");
+        assert_eq!(np.album, "Discovery");
+        assert!(np.is_playing);
+        assert_eq!(np.progress_ms, 42000);
+        assert_eq!(np.duration_ms, 224000);
+        assert_eq!(np.album_id.as_deref(), Some("2noRn2Aes5aoNVsU6iWThc"));
+        assert_eq!(np.art_url.as_deref(), Some("https://i.scdn.co/image/64"));
+        assert_eq!(np.id.as_deref(), Some("5W3cjX2J3tjhG8zb6u0qHn"));
+    }
+
+    #[test]
+    fn parses_an_episode() {
+        let np = NowPlaying::from_json(EPISODE).unwrap().unwrap();
+        assert_eq!(np.kind, PlayingKind::Episode);
+        assert_eq!(np.title, "Episode 12: Ink");
+        assert_eq!(np.artist, "Paper Talk");
+        assert_eq!(np.album, "E Ink Radio");
+        assert!(!np.is_playing);
+        assert_eq!(np.album_id.as_deref(), Some("show1"));
+        assert_eq!(np.art_url, None);
+    }
+
+    fn playing(id: &str) -> NowPlaying {
+        let mut np = NowPlaying::from_json(TRACK).unwrap().unwrap();
+        np.id = Some(id.to_string());
+        np
+    }
+
+    #[test]
+    fn a_track_counts_once_it_has_settled() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut changes = TrackChangeDetector::new(Duration::from_secs(8));
+        assert!(changes.observe_at(Some(&playing("a")), at(0)).is_none());
+        assert!(changes.observe_at(Some(&playing("a")), at(5)).is_none());
+        let changed = changes.observe_at(Some(&playing("a")), at(10)).unwrap();
+        assert_eq!(changed.id.as_deref(), Some("a"));
+        // still the same one, paused or not
+        assert!(changes.observe_at(Some(&playing("a")), at(15)).is_none());
+        assert!(changes.observe_at(None, at(20)).is_none());
+        assert!(changes.observe_at(Some(&playing("a")), at(40)).is_none());
+    }
+
+    #[test]
+    fn skipping_through_only_counts_where_it_stops() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut changes = TrackChangeDetector::new(Duration::from_secs(8));
+        for (i, id) in ["a", "b", "c", "d"].iter().enumerate() {
+            assert!(changes
+                .observe_at(Some(&playing(id)), at(i as u64 * 5))
+                .is_none());
+        }
+        let changed = changes.observe_at(Some(&playing("d")), at(25)).unwrap();
+        assert_eq!(changed.id.as_deref(), Some("d"));
+    }
+
+    #[test]
+    fn nothing_to_show_is_none() {
+        let ad = r#"{"currently_playing_type": "ad", "is_playing": true, "item": {}}"#;
+        assert!(NowPlaying::from_json(ad).unwrap().is_none());
+        let empty = r#"{"currently_playing_type": "track", "item": null}"#;
+        assert!(NowPlaying::from_json(empty).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_cached_track_plays_on_until_it_goes_stale() {
+        let np = NowPlaying::from_json(TRACK).unwrap();
+        let mut tracks = TrackCache::new(Duration::from_secs(5));
+        assert!(tracks.cached().is_none());
+
+        tracks.last_track = np.clone();
+        tracks.fetched_at = Some(Instant::now() - Duration::from_secs(2));
+        let progress = tracks.cached().unwrap().unwrap().progress_ms;
+        assert!((44000..45000).contains(&progress));
+
+        tracks.set_ttl(Duration::from_secs(1));
+        assert!(tracks.cached().is_none());
+        tracks.set_ttl(Duration::from_secs(5));
+        tracks.invalidate();
+        assert!(tracks.cached().is_none());
+    }
+
+    #[test]
+    fn a_cover_is_dithered_down_to_art_size() {
+        // half black, half white, bigger than the art
+        let cover =
+            image::GrayImage::from_fn(128, 128, |x, _| image::Luma([if x < 64 { 0 } else { 255 }]));
+        let mut png = io::Cursor::new(Vec::new());
+        cover.write_to(&mut png, image::ImageFormat::Png).unwrap();
+
+        let art = AlbumArt::decode(png.get_ref()).unwrap();
+        let sprite = art.sprite();
+        assert_eq!((sprite.width, sprite.height), (ART_SIZE, ART_SIZE));
+        let row = ART_SIZE.div_ceil(8) as usize;
+        assert_eq!(sprite.data.len(), row * ART_SIZE as usize);
+        // set bits are black
+        assert_eq!(sprite.data[0], 0xFF);
+        assert_eq!(sprite.data[row - 1], 0x00);
+        assert!(AlbumArt::decode(b"not an image").is_err());
+    }
+
+    #[test]
+    fn a_cover_that_failed_waits_for_the_next_album() {
+        let mut np = NowPlaying::from_json(TRACK).unwrap().unwrap();
+        // nothing listens there, the download fails straight away
+        np.art_url = Some("http://127.0.0.1:1/cover".into());
+        let mut arts = ArtCache::default();
+
+        assert!(arts.get(&np).is_err());
+        assert!(arts.get(&np).unwrap().is_none());
+        np.album_id = Some("another".into());
+        assert!(arts.get(&np).is_err());
+
+        np.art_url = None;
+        assert!(arts.get(&np).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_loaded_token_remembers_where_it_came_from() {
+        let path = std::env::temp_dir().join(format!("piknife-{}-token", std::process::id()));
+        let token = Token {
+            access_token: "access".into(),
+            refresh_token: "refresh".into(),
+            time: 1_700_000_000,
+            path: None,
+        };
+        token.save(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let loaded = Token::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(!text.contains("path"));
+        assert_eq!(loaded.refresh_token, "refresh");
+        assert_eq!(loaded.time, 1_700_000_000);
+        assert_eq!(loaded.path.as_deref(), Some(path.as_path()));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::pet::Mood;
+
+// 1 bit per pixel, rows top to bottom, most significant bit is the leftmost
+// pixel. set bits are drawn black, rows are padded out to whole bytes
+pub struct Sprite<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub data: &'a [u8],
+}
+
+pub const HAPPY: Sprite<'static> = face(&HAPPY_DATA);
+pub const NEUTRAL: Sprite<'static> = face(&NEUTRAL_DATA);
+pub const HUNGRY: Sprite<'static> = face(&HUNGRY_DATA);
+pub const SLEEPING: Sprite<'static> = face(&SLEEPING_DATA);
+pub const DEAD: Sprite<'static> = face(&DEAD_DATA);
+
+pub fn for_mood(mood: Mood) -> &'static Sprite<'static> {
+    match mood {
+        Mood::Happy => &HAPPY,
+        Mood::Neutral => &NEUTRAL,
+        Mood::Hungry => &HUNGRY,
+        Mood::Sleeping => &SLEEPING,
+        Mood::Dead => &DEAD,
+    }
+}
+
+// the pet faces are all 32x32
+const fn face(data: &'static [u8; 128]) -> Sprite<'static> {
+    Sprite {
+        width: 32,
+        height: 32,
+        data,
+    }
+}
+
+#[rustfmt::skip]
+const HAPPY_DATA: [u8; 128] = [
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00111000, 0b00011100, 0b00110000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00010000, 0b00111000, 0b00011100, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00110000, 0b00000000, 0b00000000, 0b00001100,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00100000, 0b00000100, 0b00001000,
+    0b00011000, 0b00010000, 0b00001000, 0b00011000,
+    0b00011000, 0b00001100, 0b00110000, 0b00011000,
+    0b00001100, 0b00000011, 0b11000000, 0b00110000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+];
+
+#[rustfmt::skip]
+const NEUTRAL_DATA: [u8; 128] = [
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00111000, 0b00011100, 0b00110000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00010000, 0b00111000, 0b00011100, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00110000, 0b00000000, 0b00000000, 0b00001100,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00011000, 0b00000000, 0b00000000, 0b00011000,
+    0b00011000, 0b00011111, 0b11111000, 0b00011000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+];
+
+#[rustfmt::skip]
+const HUNGRY_DATA: [u8; 128] = [
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00111000, 0b00011100, 0b00110000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00011000, 0b00111000, 0b00011100, 0b00011000,
+    0b00010000, 0b00111000, 0b00011100, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00110000, 0b00000000, 0b00000000, 0b00001100,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000111, 0b11100000, 0b00001000,
+    0b00011000, 0b00000100, 0b00100000, 0b00011000,
+    0b00011000, 0b00000100, 0b00100000, 0b00011000,
+    0b00001100, 0b00000100, 0b00100000, 0b00110000,
+    0b00001100, 0b00000111, 0b11100000, 0b00110000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+];
+
+#[rustfmt::skip]
+const SLEEPING_DATA: [u8; 128] = [
+    0b00000000, 0b00000000, 0b00000000, 0b00001110,
+    0b00000000, 0b00000000, 0b00000000, 0b00000100,
+    0b00000000, 0b00000000, 0b00000001, 0b11101110,
+    0b00000000, 0b00000000, 0b00000000, 0b01000000,
+    0b00000000, 0b00001111, 0b11110000, 0b10000000,
+    0b00000000, 0b00111111, 0b11111101, 0b11100000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00011000, 0b00000000, 0b00000000, 0b00011000,
+    0b00011000, 0b01000100, 0b00100010, 0b00011000,
+    0b00010000, 0b00111000, 0b00011100, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00110000, 0b00000000, 0b00000000, 0b00001100,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00011000, 0b00000000, 0b00000000, 0b00011000,
+    0b00011000, 0b00000111, 0b11100000, 0b00011000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+];
+
+#[rustfmt::skip]
+const DEAD_DATA: [u8; 128] = [
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00001100, 0b00000000, 0b00000000, 0b00110000,
+    0b00001100, 0b01000100, 0b00100010, 0b00110000,
+    0b00011000, 0b00101000, 0b00010100, 0b00011000,
+    0b00011000, 0b00010000, 0b00001000, 0b00011000,
+    0b00010000, 0b00101000, 0b00010100, 0b00001000,
+    0b00010000, 0b01000100, 0b00100010, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00110000, 0b00000000, 0b00000000, 0b00001100,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00010000, 0b00000000, 0b00000000, 0b00001000,
+    0b00011000, 0b00000011, 0b11000000, 0b00011000,
+    0b00011000, 0b00000100, 0b00100000, 0b00011000,
+    0b00001100, 0b00001000, 0b00010000, 0b00110000,
+    0b00001100, 0b00010000, 0b00001000, 0b00110000,
+    0b00000110, 0b00000000, 0b00000000, 0b01100000,
+    0b00000011, 0b00000000, 0b00000000, 0b11000000,
+    0b00000001, 0b10000000, 0b00000001, 0b10000000,
+    0b00000000, 0b11100000, 0b00000111, 0b00000000,
+    0b00000000, 0b00111111, 0b11111100, 0b00000000,
+    0b00000000, 0b00001111, 0b11110000, 0b00000000,
+    0b00000000, 0b00000000, 0b00000000, 0b00000000,
+];
@@ -0,0 +1,95 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+use crate::pet::{Mood, Pet, PetStage};
+use crate::spotify::NowPlaying;
+
+// changes a slow client hasn't read yet, past this it skips to the latest
+const CHANGES_BUFFER: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PetState {
+    pub hunger: u8,
+    pub happiness: u8,
+    pub energy: u8,
+    pub mood: Mood,
+    pub stage: PetStage,
+}
+
+impl From<&Pet> for PetState {
+    fn from(pet: &Pet) -> Self {
+        PetState {
+            hunger: pet.hunger,
+            happiness: pet.happiness,
+            energy: pet.energy,
+            mood: pet.mood,
+            stage: pet.stage(),
+        }
+    }
+}
+
+// what a companion app gets to see of the device
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceState {
+    pub pet: PetState,
+    // the track cache's last answer, None while nothing is playing or
+    // without a spotify token
+    pub now_playing: Option<NowPlaying>,
+    // percent, None without a battery monitor
+    pub battery: Option<u8>,
+}
+
+impl Default for DeviceState {
+    fn default() -> Self {
+        DeviceState {
+            pet: PetState::from(&Pet::default()),
+            now_playing: None,
+            battery: None,
+        }
+    }
+}
+
+// the display thread writes it, the websocket connections read it and get
+// told when it changes. clones all share the one state
+#[derive(Clone)]
+pub struct SharedState {
+    state: Arc<Mutex<DeviceState>>,
+    changes: broadcast::Sender<DeviceState>,
+}
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedState {
+    pub fn new() -> Self {
+        let (changes, _) = broadcast::channel(CHANGES_BUFFER);
+        SharedState {
+            state: Arc::new(Mutex::new(DeviceState::default())),
+            changes,
+        }
+    }
+
+    pub fn get(&self) -> DeviceState {
+        self.state.lock().unwrap().clone()
+    }
+
+    // quietly, for what changes all the time anyway
+    pub fn set(&self, state: DeviceState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    // set, and sent to everyone subscribed
+    pub fn publish(&self, state: DeviceState) {
+        self.set(state.clone());
+        // nobody listening is fine
+        let _ = self.changes.send(state);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceState> {
+        self.changes.subscribe()
+    }
+}
@@ -0,0 +1,183 @@
+use nix::ifaddrs::getifaddrs;
+use nix::sys::statvfs::statvfs;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// millidegrees celsius, on a pi at least
+const CPU_TEMP_PATH: &str = "/sys/class/thermal/thermal_zone0/temp";
+// seconds since boot, then seconds idle
+const UPTIME_PATH: &str = "/proc/uptime";
+const STAT_PATH: &str = "/proc/stat";
+const MEMINFO_PATH: &str = "/proc/meminfo";
+
+// the system info screen doesn't need to be fresher than this
+pub const REFRESH_EVERY: Duration = Duration::from_secs(10);
+
+// the soc's temperature in celsius, None if there's no thermal zone to read
+pub fn cpu_temp() -> Option<f32> {
+    let text = fs::read_to_string(CPU_TEMP_PATH).ok()?;
+    let millidegrees: f32 = text.trim().parse().ok()?;
+    Some(millidegrees / 1000.0)
+}
+
+pub fn uptime() -> Option<Duration> {
+    let text = fs::read_to_string(UPTIME_PATH).ok()?;
+    let secs: f64 = text.split_whitespace().next()?.parse().ok()?;
+    Some(Duration::from_secs_f64(secs))
+}
+
+// "3d 4h 12m", leaving off the big units while they're 0
+pub fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+// jiffies the cpus have spent since boot, all of them added up. usage is
+// the difference between two of these
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CpuTimes {
+    pub idle: u64,
+    pub total: u64,
+}
+
+// how the pi is doing, everything read in one go
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemSnapshot {
+    // None off a pi, there's no thermal zone
+    pub cpu_temp: Option<f32>,
+    pub cpu_times: CpuTimes,
+    pub mem_total_kb: u64,
+    pub mem_available_kb: u64,
+    // of /, in bytes
+    pub disk_total: u64,
+    pub disk_used: u64,
+    // the first address that isn't loopback, None with no network
+    pub ip: Option<Ipv4Addr>,
+}
+
+impl SystemSnapshot {
+    pub fn collect() -> Result<Self, io::Error> {
+        let (mem_total_kb, mem_available_kb) = read_meminfo()?;
+        let (disk_total, disk_used) = disk_usage()?;
+        Ok(SystemSnapshot {
+            cpu_temp: cpu_temp(),
+            cpu_times: read_cpu_times()?,
+            mem_total_kb,
+            mem_available_kb,
+            disk_total,
+            disk_used,
+            ip: ipv4_address(),
+        })
+    }
+
+    // the busy fraction (0 to 1) of the cpus since earlier. None if no time
+    // has passed between them
+    pub fn cpu_usage(&self, earlier: &SystemSnapshot) -> Option<f32> {
+        let total = self.cpu_times.total.checked_sub(earlier.cpu_times.total)?;
+        let idle = self.cpu_times.idle.saturating_sub(earlier.cpu_times.idle);
+        if total == 0 {
+            return None;
+        }
+        Some(1.0 - idle.min(total) as f32 / total as f32)
+    }
+
+    pub fn mem_used_kb(&self) -> u64 {
+        self.mem_total_kb.saturating_sub(self.mem_available_kb)
+    }
+}
+
+fn bad_data(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+// the first line of /proc/stat: "cpu  user nice system idle iowait irq ..."
+fn read_cpu_times() -> Result<CpuTimes, io::Error> {
+    let text = fs::read_to_string(STAT_PATH)?;
+    let line = text
+        .lines()
+        .find(|line| line.starts_with("cpu "))
+        .ok_or_else(|| bad_data("no cpu line in /proc/stat"))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|f| f.parse().map_err(|_| bad_data("bad number in /proc/stat")))
+        .collect::<Result<_, _>>()?;
+    if fields.len() < 4 {
+        return Err(bad_data("short cpu line in /proc/stat"));
+    }
+    // waiting on io counts as idle
+    let idle = fields[3] + fields.get(4).copied().unwrap_or(0);
+    Ok(CpuTimes {
+        idle,
+        total: fields.iter().sum(),
+    })
+}
+
+// MemTotal and MemAvailable, in kB
+fn read_meminfo() -> Result<(u64, u64), io::Error> {
+    let text = fs::read_to_string(MEMINFO_PATH)?;
+    let field = |name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|kb| kb.parse().ok())
+            .ok_or_else(|| bad_data(&format!("no {} in /proc/meminfo", name)))
+    };
+    Ok((field("MemTotal:")?, field("MemAvailable:")?))
+}
+
+// total and used bytes of the root filesystem, used counts what's reserved
+// for root too, like df
+fn disk_usage() -> Result<(u64, u64), io::Error> {
+    let stat = statvfs("/")?;
+    let block = stat.fragment_size() as u64;
+    let total = stat.blocks() as u64 * block;
+    let free = stat.blocks_free() as u64 * block;
+    Ok((total, total.saturating_sub(free)))
+}
+
+fn ipv4_address() -> Option<Ipv4Addr> {
+    getifaddrs()
+        .ok()?
+        .filter_map(|ifaddr| ifaddr.address?.as_sockaddr_in().map(|a| a.ip()))
+        .find(|ip| !ip.is_loopback())
+}
+
+// the latest snapshot, taken again once it's REFRESH_EVERY old. two are
+// needed for a cpu usage, so that's None until the second one
+#[derive(Default)]
+pub struct SystemMonitor {
+    last: Option<SystemSnapshot>,
+    usage: Option<f32>,
+    taken_at: Option<Instant>,
+}
+
+impl SystemMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the snapshot and the cpu usage since the one before it. None only if
+    // nothing could be read yet
+    pub fn poll(&mut self) -> Option<(&SystemSnapshot, Option<f32>)> {
+        if self.taken_at.is_none_or(|at| at.elapsed() >= REFRESH_EVERY) {
+            self.taken_at = Some(Instant::now());
+            match SystemSnapshot::collect() {
+                Ok(now) => {
+                    self.usage = self.last.as_ref().and_then(|last| now.cpu_usage(last));
+                    self.last = Some(now);
+                }
+                Err(e) => warn!("Failed to read system info: {}", e),
+            }
+        }
+        self.last.as_ref().map(|last| (last, self.usage))
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+use crate::sysinfo;
+
+// each sensor on the 1-wire bus is a directory here, DS18B20s are "28-<id>".
+// needs `dtoverlay=w1-gpio` in /boot/config.txt, data on gpio 4
+const W1_DEVICES_PATH: &str = "/sys/bus/w1/devices";
+const DS18B20_FAMILY: &str = "28-";
+
+// reading w1_slave has the sensor do a conversion, which holds things up
+// most of a second, so it's only read for the screen and no more often
+// than this. the alert only looks at the cpu, that's cheap
+pub const READ_EVERY: Duration = Duration::from_secs(10);
+// the pi starts throttling at 80
+pub const DEFAULT_CPU_ALERT: f32 = 75.0;
+// what the thermometer icon goes from empty to full over
+pub const SCALE_MIN: f32 = -10.0;
+pub const SCALE_MAX: f32 = 100.0;
+// after an alert the cpu has to cool this much under the threshold before
+// it can alert again, so hovering around it doesn't alert every reading
+const ALERT_HYSTERESIS: f32 = 5.0;
+
+// the first DS18B20 on the bus, in celsius
+pub fn read_ds18b20() -> Result<f32, io::Error> {
+    let text = fs::read_to_string(ds18b20_path()?.join("w1_slave"))?;
+    parse_w1_slave(&text)
+}
+
+// lowest id first, so it's the same sensor every time when there's several
+fn ds18b20_path() -> Result<PathBuf, io::Error> {
+    fs::read_dir(W1_DEVICES_PATH)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(DS18B20_FAMILY)
+        })
+        .map(|entry| entry.path())
+        .min()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no DS18B20 on the 1-wire bus"))
+}
+
+// two lines, the crc check then the reading in millidegrees:
+// "72 01 4b 46 7f ff 0e 10 57 : crc=57 YES"
+// "72 01 4b 46 7f ff 0e 10 57 t=23125"
+fn parse_w1_slave(text: &str) -> Result<f32, io::Error> {
+    let bad = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let mut lines = text.lines();
+    if !lines
+        .next()
+        .is_some_and(|line| line.trim_end().ends_with("YES"))
+    {
+        return Err(bad("DS18B20 reading failed its crc"));
+    }
+    let millidegrees: i32 = lines
+        .next()
+        .and_then(|line| line.split_once("t="))
+        .and_then(|(_, t)| t.trim().parse().ok())
+        .ok_or_else(|| bad("no t= in the DS18B20 reading"))?;
+    Ok(millidegrees as f32 / 1000.0)
+}
+
+// how far up the thermometer icon celsius fills it, 0 to 1
+pub fn scale_fraction(celsius: f32) -> f32 {
+    ((celsius - SCALE_MIN) / (SCALE_MAX - SCALE_MIN)).clamp(0.0, 1.0)
+}
+
+// None for either one that couldn't be read
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Temperatures {
+    pub cpu: Option<f32>,
+    pub ambient: Option<f32>,
+}
+
+impl Temperatures {
+    pub fn read() -> Self {
+        let ambient = match read_ds18b20() {
+            Ok(celsius) => Some(celsius),
+            // most pis won't have one, that's not worth more than debug
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("No ambient temperature: {}", e);
+                None
+            }
+            Err(e) => {
+                warn!("Failed to read the DS18B20: {}", e);
+                None
+            }
+        };
+        Temperatures {
+            cpu: sysinfo::cpu_temp(),
+            ambient,
+        }
+    }
+}
+
+// the latest Temperatures, read again once they're READ_EVERY old, and
+// whether the cpu's gone over cpu_alert since it was last under
+pub struct TemperatureMonitor {
+    last: Temperatures,
+    read_at: Option<Instant>,
+    pub cpu_alert: f32,
+    alerted: bool,
+    checked_at: Option<Instant>,
+}
+
+impl Default for TemperatureMonitor {
+    fn default() -> Self {
+        Self::new(DEFAULT_CPU_ALERT)
+    }
+}
+
+impl TemperatureMonitor {
+    pub fn new(cpu_alert: f32) -> Self {
+        TemperatureMonitor {
+            last: Temperatures::default(),
+            read_at: None,
+            cpu_alert,
+            alerted: false,
+            checked_at: None,
+        }
+    }
+
+    pub fn poll(&mut self) -> Temperatures {
+        if self.read_at.is_none_or(|at| at.elapsed() >= READ_EVERY) {
+            self.read_at = Some(Instant::now());
+            self.last = Temperatures::read();
+        }
+        self.last
+    }
+
+    // the cpu temperature the first check it's over cpu_alert, None after
+    // that until it's cooled off again. fine to call every frame, it only
+    // reads every READ_EVERY
+    pub fn check_alert(&mut self) -> Option<f32> {
+        if self.checked_at.is_some_and(|at| at.elapsed() < READ_EVERY) {
+            return None;
+        }
+        self.checked_at = Some(Instant::now());
+        let cpu = sysinfo::cpu_temp()?;
+        if cpu < self.cpu_alert - ALERT_HYSTERESIS {
+            self.alerted = false;
+        } else if cpu >= self.cpu_alert && !self.alerted {
+            self.alerted = true;
+            return Some(cpu);
+        }
+        None
+    }
+}
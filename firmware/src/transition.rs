@@ -0,0 +1,135 @@
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+
+use crate::scheduler::DisplayMode;
+
+// partial refreshes a wipe or a dissolve takes. each one's ~120ms on the
+// 2.13", four looks smooth enough without dragging on
+pub const STEPS: u32 = 4;
+
+// 4x4 ordered dither, which step of a dissolve each pixel changes on
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// the side a wipe starts from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+// how the new screen replaces the old one when the mode switches. in a
+// [[schedule]] entry's `transition` it's "instant", "wipe_left",
+// "wipe_right", "wipe_up", "wipe_down" or "dissolve"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Transition {
+    // one partial refresh of whatever changed
+    #[default]
+    Instant,
+    // a strip at a time from the side it's named after
+    Wipe(Direction),
+    // the changed pixels a quarter at a time, in a dither pattern
+    Dissolve,
+}
+
+impl FromStr for Transition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "instant" => Transition::Instant,
+            "wipe_left" => Transition::Wipe(Direction::Left),
+            "wipe_right" => Transition::Wipe(Direction::Right),
+            "wipe_up" => Transition::Wipe(Direction::Up),
+            "wipe_down" => Transition::Wipe(Direction::Down),
+            "dissolve" => Transition::Dissolve,
+            _ => return Err(format!("\"{}\" isn't a transition", s)),
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for Transition {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// the strip of screen a wipe uncovers on step (1 to STEPS), between where
+// the step before got to and where this one gets to
+pub fn wipe_strip(screen: Rectangle, direction: Direction, step: u32) -> Rectangle {
+    let Size { width, height } = screen.size;
+    let edge = |length: u32, step: u32| (length * step / STEPS) as i32;
+    let (from, to) = match direction {
+        Direction::Left | Direction::Right => (edge(width, step - 1), edge(width, step)),
+        Direction::Up | Direction::Down => (edge(height, step - 1), edge(height, step)),
+    };
+    let origin = screen.top_left;
+    match direction {
+        // from the left edge going right
+        Direction::Left => Rectangle::new(
+            origin + Point::new(from, 0),
+            Size::new((to - from) as u32, height),
+        ),
+        Direction::Right => Rectangle::new(
+            origin + Point::new(width as i32 - to, 0),
+            Size::new((to - from) as u32, height),
+        ),
+        // from the top going down
+        Direction::Up => Rectangle::new(
+            origin + Point::new(0, from),
+            Size::new(width, (to - from) as u32),
+        ),
+        Direction::Down => Rectangle::new(
+            origin + Point::new(0, height as i32 - to),
+            Size::new(width, (to - from) as u32),
+        ),
+    }
+}
+
+// whether p has switched to the new screen by step (1 to STEPS) of a
+// dissolve. all of them have by the last step
+pub fn dissolved(p: Point, step: u32) -> bool {
+    let rank = u32::from(BAYER[p.y.rem_euclid(4) as usize][p.x.rem_euclid(4) as usize]);
+    rank * STEPS / 16 < step
+}
+
+// which mode is up, which was before it and the transition the switch
+// between them still owes the panel
+#[derive(Debug, Default)]
+pub struct DisplayModeManager {
+    current: Option<DisplayMode>,
+    previous: Option<DisplayMode>,
+    pending: Option<Transition>,
+}
+
+impl DisplayModeManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> Option<DisplayMode> {
+        self.current
+    }
+
+    pub fn previous(&self) -> Option<DisplayMode> {
+        self.previous
+    }
+
+    // switching to the mode that's already up doesn't transition
+    pub fn switch(&mut self, mode: DisplayMode, transition: Transition) {
+        if self.current == Some(mode) {
+            return;
+        }
+        self.previous = self.current.replace(mode);
+        self.pending = Some(transition).filter(|t| *t != Transition::Instant);
+    }
+
+    // the transition to push the next frame with, once
+    pub fn take_transition(&mut self) -> Option<Transition> {
+        self.pending.take()
+    }
+}
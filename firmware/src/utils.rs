@@ -1,19 +1,630 @@
+use chrono::{NaiveTime, Timelike};
 use embedded_graphics::{
-    mono_font::MonoTextStyleBuilder,
-    prelude::Point,
+    mono_font::{MonoFont, MonoTextStyleBuilder},
+    prelude::{Dimensions, DrawTarget, Pixel, Point, Primitive, Size},
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
     text::{Baseline, Text, TextStyleBuilder},
     Drawable,
 };
-use epd_waveshare::{color::Color, epd2in13_v2::Display2in13};
+use epd_waveshare::color::Color;
+use serde::Deserialize;
+use std::f32::consts::TAU;
 
-pub fn draw_text(display: &mut Display2in13, text: &str, x: i32, y: i32) {
+use crate::spotify::AlbumArt;
+use crate::sprites::Sprite;
+use crate::EpaperError;
+
+// anything the helpers can draw on: the panel's PanelDisplay, the desktop
+// simulator's window, a scratch buffer for a widget
+pub trait Canvas: DrawTarget<Color = Color> {}
+
+impl<T: DrawTarget<Color = Color>> Canvas for T {}
+
+// fonts the text helpers take, so callers don't need embedded_graphics::mono_font
+pub type Font = MonoFont<'static>;
+pub use embedded_graphics::mono_font::ascii::{FONT_10X20, FONT_6X10, FONT_9X18};
+
+// the text sizes screens pick from, named in lowercase over the websocket.
+// embedded_graphics has nothing bigger than 10x20, so medium is 9x18 and
+// large gets 10x20
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSize {
+    #[default]
+    Small,
+    Medium,
+    Large,
+}
+
+impl FontSize {
+    pub fn font(self) -> &'static Font {
+        match self {
+            FontSize::Small => &FONT_6X10,
+            FontSize::Medium => &FONT_9X18,
+            FontSize::Large => &FONT_10X20,
+        }
+    }
+}
+
+// small white on black text, what the status lines use
+pub fn draw_text(display: &mut impl Canvas, text: &str, x: i32, y: i32) {
+    draw_text_styled(display, text, x, y, &FONT_6X10, Color::White, Color::Black);
+}
+
+pub fn draw_text_styled(
+    display: &mut impl Canvas,
+    text: &str,
+    x: i32,
+    y: i32,
+    font: &Font,
+    fg: Color,
+    bg: Color,
+) {
     let style = MonoTextStyleBuilder::new()
-        .font(&embedded_graphics::mono_font::ascii::FONT_6X10)
-        .text_color(Color::White)
-        .background_color(Color::Black)
+        .font(font)
+        .text_color(fg)
+        .background_color(bg)
         .build();
 
     let text_style = TextStyleBuilder::new().baseline(Baseline::Top).build();
 
     let _ = Text::with_text_style(text, Point::new(x, y), style, text_style).draw(display);
 }
+
+pub fn draw_text_sized(
+    display: &mut impl Canvas,
+    text: &str,
+    x: i32,
+    y: i32,
+    size: FontSize,
+    text_color: Color,
+    bg_color: Color,
+) {
+    draw_text_styled(display, text, x, y, size.font(), text_color, bg_color);
+}
+
+// white on black, centered on the display width
+pub fn draw_text_centered(display: &mut impl Canvas, text: &str, y: i32, size: FontSize) {
+    draw_text_aligned(display, text, y, Alignment::Center, size.font());
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+// x to start text at so it lines up with the display edges (or middle)
+pub fn aligned_x(display: &impl Dimensions, text: &str, alignment: Alignment, font: &Font) -> i32 {
+    let display_width = display.bounding_box().size.width as i32;
+    let width = text_width(text, font) as i32;
+
+    match alignment {
+        Alignment::Left => 0,
+        Alignment::Center => (display_width - width) / 2,
+        Alignment::Right => display_width - width,
+    }
+}
+
+pub fn draw_text_aligned(
+    display: &mut impl Canvas,
+    text: &str,
+    y: i32,
+    alignment: Alignment,
+    font: &Font,
+) {
+    let x = aligned_x(display, text, alignment, font);
+    draw_text_styled(display, text, x, y, font, Color::White, Color::Black);
+}
+
+// draws text broken on spaces so no line is wider than max_width px, words
+// longer than a line get hard broken. returns the height used so callers
+// can put things underneath
+pub fn draw_text_wrapped(
+    display: &mut impl Canvas,
+    text: &str,
+    x: i32,
+    y: i32,
+    max_width: u32,
+) -> u32 {
+    let font = &FONT_6X10;
+    let advance = font.character_size.width + font.character_spacing;
+    let max_chars = ((max_width + font.character_spacing) / advance).max(1) as usize;
+    let line_height = text_height(font);
+
+    let lines = wrap_text(text, max_chars);
+    for (n, line) in lines.iter().enumerate() {
+        draw_text(display, line, x, y + (n as u32 * line_height) as i32);
+    }
+
+    lines.len() as u32 * line_height
+}
+
+// rendered width of a single line of text in pixels, same as the bounding box
+// embedded_graphics gives a mono font text (glyphs plus the spacing between them)
+pub fn text_width(text: &str, font: &Font) -> u32 {
+    let chars = text.chars().count() as u32;
+    if chars == 0 {
+        return 0;
+    }
+
+    chars * font.character_size.width + (chars - 1) * font.character_spacing
+}
+
+// height of a single line of text in pixels
+pub fn text_height(font: &Font) -> u32 {
+    font.character_size.height
+}
+
+// (width, height) in pixels of a single line of text, for doing layout math
+pub fn measure_text(text: &str, size: FontSize) -> (i32, i32) {
+    let font = size.font();
+    (text_width(text, font) as i32, text_height(font) as i32)
+}
+
+// black outline filled in from the left, what the track progress and the pet
+// stat bars use
+pub fn draw_progress_bar(
+    display: &mut impl Canvas,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    fraction: f32,
+) {
+    draw_progress_bar_styled(
+        display,
+        x,
+        y,
+        width,
+        height,
+        fraction,
+        Color::Black,
+        Color::Black,
+    );
+}
+
+// 1px border with the inside filled from the left by percent (0 to 1). the
+// fill stays inside the border, so an empty bar is just the outline
+#[allow(clippy::too_many_arguments)]
+pub fn draw_progress_bar_styled(
+    display: &mut impl Canvas,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+    percent: f32,
+    border_color: Color,
+    fill_color: Color,
+) {
+    let percent = percent.clamp(0.0, 1.0);
+
+    let _ = Rectangle::new(Point::new(x, y), Size::new(width, height))
+        .into_styled(PrimitiveStyle::with_stroke(border_color, 1))
+        .draw(display);
+
+    let inner_width = width.saturating_sub(2);
+    let inner_height = height.saturating_sub(2);
+    let fill_width = (inner_width as f32 * percent).round() as u32;
+    if fill_width == 0 || inner_height == 0 {
+        return;
+    }
+    let _ = Rectangle::new(
+        Point::new(x + 1, y + 1),
+        Size::new(fill_width, inner_height),
+    )
+    .into_styled(PrimitiveStyle::with_fill(fill_color))
+    .draw(display);
+}
+
+// below this the battery icon blinks, the status bar draws it inverted
+// every other frame
+pub const BATTERY_BLINK_PERCENT: u8 = 10;
+
+// battery outline 20x10 with the nub on the right, filled from the left by
+// percent (0-100). inverted is white on a black box
+pub fn draw_battery(display: &mut impl Canvas, x: i32, y: i32, percent: u8, inverted: bool) {
+    let (fg, bg) = if inverted {
+        (Color::White, Color::Black)
+    } else {
+        (Color::Black, Color::White)
+    };
+    let _ = Rectangle::new(Point::new(x, y), Size::new(20, 10))
+        .into_styled(PrimitiveStyle::with_fill(bg))
+        .draw(display);
+    draw_progress_bar_styled(display, x, y, 18, 10, percent as f32 / 100.0, fg, fg);
+    let _ = Rectangle::new(Point::new(x + 18, y + 3), Size::new(2, 4))
+        .into_styled(PrimitiveStyle::with_fill(fg))
+        .draw(display);
+}
+
+// a spotify cover with its top left at (x, y)
+pub fn draw_album_art(display: &mut impl Canvas, art: &AlbumArt, x: i32, y: i32) {
+    draw_sprite(display, &art.sprite(), x, y);
+}
+
+// draws the black pixels of an uncompressed 1 bit BMP with its top left at
+// (x, y), the rest is left alone. black is whichever palette color is darker
+pub fn draw_image_bmp(
+    display: &mut impl Canvas,
+    data: &[u8],
+    x: i32,
+    y: i32,
+) -> Result<(), EpaperError> {
+    let bad = |why: &str| EpaperError::ImageDecode(why.to_string());
+    let u16_at = |at: usize| {
+        data.get(at..at + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .ok_or_else(|| bad("header is cut short"))
+    };
+    let u32_at = |at: usize| {
+        data.get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .ok_or_else(|| bad("header is cut short"))
+    };
+
+    if data.get(0..2) != Some(b"BM") {
+        return Err(bad("not a BMP"));
+    }
+    let pixels_at = u32_at(10)? as usize;
+    let header_size = u32_at(14)? as usize;
+    let width = u32_at(18)? as i32;
+    // positive heights are stored bottom row first
+    let height = u32_at(22)? as i32;
+    let bits = u16_at(28)?;
+    if bits != 1 {
+        return Err(bad(&format!(
+            "{} bits per pixel, only 1 bit BMPs work",
+            bits
+        )));
+    }
+    if u32_at(30)? != 0 {
+        return Err(bad("compressed BMPs aren't supported"));
+    }
+    if width <= 0 || height == 0 {
+        return Err(bad("no pixels"));
+    }
+
+    // two BGRx palette entries after the header
+    let palette = header_size
+        .checked_add(14)
+        .ok_or_else(|| bad("header size is too big"))?;
+    let luma = |i: usize| -> Result<u32, EpaperError> {
+        let c = data
+            .get(palette + i * 4..palette + i * 4 + 3)
+            .ok_or_else(|| bad("palette is cut short"))?;
+        Ok(c[0] as u32 * 11 + c[1] as u32 * 59 + c[2] as u32 * 30)
+    };
+    let black_bit = if luma(1)? < luma(0)? { 1 } else { 0 };
+
+    // rows are padded out to whole 4 byte words
+    let width = width as u32;
+    let rows = height.unsigned_abs();
+    let stride = width.div_ceil(32) as usize * 4;
+    // a made up header can ask for more than a usize holds on the pi
+    let end = stride
+        .checked_mul(rows as usize)
+        .and_then(|size| size.checked_add(pixels_at))
+        .ok_or_else(|| bad("pixel data is too big"))?;
+    let pixels = data
+        .get(pixels_at..end)
+        .ok_or_else(|| bad("pixel data is cut short"))?;
+
+    for (i, row) in pixels.chunks(stride).enumerate() {
+        let row_y = if height > 0 {
+            rows - 1 - i as u32
+        } else {
+            i as u32
+        };
+        for col in 0..width {
+            let bit = (row[(col / 8) as usize] >> (7 - col % 8)) & 1;
+            if bit == black_bit {
+                let p = Point::new(x + col as i32, y + row_y as i32);
+                let _ = Pixel(p, Color::Black).draw(display);
+            }
+        }
+    }
+    Ok(())
+}
+
+// draws the black pixels of a sprite with its top left at (x, y), the rest
+// of the box is left alone
+pub fn draw_sprite(display: &mut impl Canvas, sprite: &Sprite<'_>, x: i32, y: i32) {
+    let row_bytes = sprite.width.div_ceil(8);
+
+    for row in 0..sprite.height {
+        for col in 0..sprite.width {
+            let byte = sprite.data[(row * row_bytes + col / 8) as usize];
+            if byte & (0x80 >> (col % 8)) != 0 {
+                let p = Point::new(x + col as i32, y + row as i32);
+                let _ = Pixel(p, Color::Black).draw(display);
+            }
+        }
+    }
+}
+
+// point `length` px from center at `turns` of a full circle, clockwise from
+// 12 o'clock (screen y grows downwards)
+fn clock_point(center: Point, length: f32, turns: f32) -> Point {
+    let angle = turns * TAU;
+    Point::new(
+        center.x + (length * angle.sin()).round() as i32,
+        center.y - (length * angle.cos()).round() as i32,
+    )
+}
+
+// clock face with hour ticks and hour/minute/second hands, all black
+pub fn draw_analog_clock(display: &mut impl Canvas, center: Point, radius: u32, time: NaiveTime) {
+    let r = radius as f32;
+
+    let _ = Circle::with_center(center, radius * 2 + 1)
+        .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+        .draw(display);
+
+    for hour in 0..12 {
+        let turns = hour as f32 / 12.0;
+        let _ = Line::new(
+            clock_point(center, r * 0.85, turns),
+            clock_point(center, r, turns),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+        .draw(display);
+    }
+
+    for (end, width) in clock_hands(center, radius, time) {
+        let _ = Line::new(center, end)
+            .into_styled(PrimitiveStyle::with_stroke(Color::Black, width))
+            .draw(display);
+    }
+}
+
+// where the hour, minute and second hands end, with how thick each is
+pub fn clock_hands(center: Point, radius: u32, time: NaiveTime) -> [(Point, u32); 3] {
+    let r = radius as f32;
+
+    // the hour and minute hands creep along instead of jumping
+    let seconds = time.second() as f32;
+    let minutes = time.minute() as f32 + seconds / 60.0;
+    let hours = (time.hour() % 12) as f32 + minutes / 60.0;
+
+    [
+        (clock_point(center, r * 0.6, hours / 12.0), 3),
+        (clock_point(center, r * 0.8, minutes / 60.0), 2),
+        (clock_point(center, r * 0.9, seconds / 60.0), 1),
+    ]
+}
+
+// cut down to max_chars, the last two swapped for ".." when any are cut
+pub fn truncate_text(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max_chars.saturating_sub(2)).collect();
+    cut.push_str("..");
+    cut
+}
+
+// breaks text on spaces into lines of at most max_chars characters,
+// words longer than a whole line get split
+pub fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
+    // a character a line at least, nothing would ever fit in 0
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+
+        while word.len() > max_chars {
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(word.drain(..max_chars).collect());
+        }
+        if word.is_empty() {
+            continue;
+        }
+
+        let line_len = line.chars().count();
+        if line_len > 0 && line_len + 1 + word.len() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word);
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::driver::EpdDisplay;
+    use crate::mock::{DrawCall, MockDisplay, MockScreen};
+    use crate::region;
+    use embedded_graphics::mono_font::MonoTextStyle;
+    use epd_waveshare::graphics::DisplayRotation;
+
+    #[test]
+    fn text_width_matches_the_bounding_box() {
+        for font in [&FONT_6X10, &FONT_9X18, &FONT_10X20] {
+            let style = MonoTextStyle::new(font, Color::Black);
+            for text in ["", "a", "hello", "Now playing: 12:34"] {
+                let bounds = Text::new(text, Point::zero(), style).bounding_box();
+                assert_eq!(
+                    text_width(text, font),
+                    bounds.size.width,
+                    "{:?} in {}x{}",
+                    text,
+                    font.character_size.width,
+                    font.character_size.height
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn wrapping_to_nothing_still_gets_a_character_a_line() {
+        assert_eq!(wrap_text("ab c", 0), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn measure_text_is_the_width_and_one_line_high() {
+        assert_eq!(measure_text("", FontSize::Small), (0, 10));
+        assert_eq!(measure_text("hi", FontSize::Small), (12, 10));
+        assert_eq!(measure_text("hi", FontSize::Medium), (18, 18));
+        assert_eq!(measure_text("hello", FontSize::Large), (50, 20));
+    }
+
+    #[test]
+    fn centered_text_sits_in_the_middle() {
+        let mut display = MockDisplay::new();
+        display.set_rotation(DisplayRotation::Rotate270);
+        let Ok(()) = display.clear(Color::White);
+        draw_text_centered(&mut display, "centre", 30, FontSize::Medium);
+
+        // white on black, so the black covers the whole text's box
+        let width = display.bounding_box().size.width as i32;
+        let black: Vec<i32> = (0..width)
+            .filter(|&x| {
+                (30..30 + 18).any(|y| {
+                    region::pixel_at(
+                        display.buffer(),
+                        DisplayRotation::Rotate270,
+                        Point::new(x, y),
+                    ) == Some(Color::Black)
+                })
+            })
+            .collect();
+        let text = text_width("centre", &FONT_9X18) as i32;
+        assert_eq!(black.first(), Some(&((width - text) / 2)));
+        assert_eq!(black.last(), Some(&((width - text) / 2 + text - 1)));
+    }
+
+    // width of the filled part of a 102x8 bar, None while it's only the border
+    fn filled(percent: f32) -> Option<u32> {
+        let mut screen = MockScreen::default();
+        draw_progress_bar_styled(
+            &mut screen,
+            5,
+            5,
+            102,
+            8,
+            percent,
+            Color::Black,
+            Color::Black,
+        );
+        screen
+            .fills()
+            .into_iter()
+            .find(|(area, _)| area.top_left == Point::new(6, 6) && area.size.height == 6)
+            .map(|(area, _)| area.size.width)
+    }
+
+    #[test]
+    fn progress_bar_fills_the_inside_by_percent() {
+        assert_eq!(filled(0.0), None);
+        assert_eq!(filled(0.5), Some(50));
+        assert_eq!(filled(1.0), Some(100));
+    }
+
+    #[test]
+    fn progress_bar_clamps_out_of_range_values() {
+        assert_eq!(filled(-0.5), None);
+        assert_eq!(filled(1.5), Some(100));
+        assert_eq!(filled(f32::INFINITY), Some(100));
+        assert_eq!(filled(f32::NAN), None);
+    }
+
+    // an 8x8 1 bit BMP, palette white then black so set bits are black.
+    // rows are in the order they're stored, a byte each padded to 4
+    fn bmp(height: i32, rows: [u8; 8]) -> Vec<u8> {
+        let mut data = b"BM".to_vec();
+        data.extend(94u32.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(62u32.to_le_bytes());
+        data.extend(40u32.to_le_bytes());
+        data.extend(8i32.to_le_bytes());
+        data.extend(height.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend([0; 24]);
+        data.extend([0xff, 0xff, 0xff, 0, 0, 0, 0, 0]);
+        for row in rows {
+            data.extend([row, 0, 0, 0]);
+        }
+        data
+    }
+
+    // where the black pixels went
+    fn drawn(data: &[u8]) -> Result<Vec<Point>, EpaperError> {
+        let mut screen = MockScreen::default();
+        draw_image_bmp(&mut screen, data, 2, 3)?;
+        Ok(screen
+            .calls
+            .iter()
+            .filter_map(|call| match call {
+                DrawCall::Pixels(Some(area)) => Some(area.top_left),
+                _ => None,
+            })
+            .collect())
+    }
+
+    // the first row stored has its last pixel set, the last row its first
+    const CORNERS: [u8; 8] = [0x01, 0, 0, 0, 0, 0, 0, 0x80];
+
+    #[test]
+    fn bmp_bottom_up_starts_from_the_bottom_row() {
+        let points = drawn(&bmp(8, CORNERS)).unwrap();
+        assert_eq!(points, [Point::new(9, 10), Point::new(2, 3)]);
+    }
+
+    #[test]
+    fn bmp_top_down_starts_from_the_top_row() {
+        let points = drawn(&bmp(-8, CORNERS)).unwrap();
+        assert_eq!(points, [Point::new(9, 3), Point::new(2, 10)]);
+    }
+
+    #[test]
+    fn bmp_cut_short_is_an_error() {
+        let data = bmp(8, CORNERS);
+        let why = |data: &[u8]| match drawn(data) {
+            Err(EpaperError::ImageDecode(why)) => why,
+            other => panic!("decoded {:?}", other.map(|points| points.len())),
+        };
+        assert_eq!(why(&data[..20]), "header is cut short");
+        assert_eq!(why(&data[..58]), "palette is cut short");
+        assert_eq!(why(&data[..90]), "pixel data is cut short");
+        assert_eq!(why(b"PNG"), "not a BMP");
+    }
+
+    #[test]
+    fn clock_hands_point_at_the_hour() {
+        // hour, minute and second hands are 30, 40 and 45 long at radius 50
+        let center = Point::new(60, 60);
+        let at = |h, m| {
+            clock_hands(center, 50, NaiveTime::from_hms_opt(h, m, 0).unwrap()).map(|(end, _)| end)
+        };
+        let up = [Point::new(60, 30), Point::new(60, 20), Point::new(60, 15)];
+
+        assert_eq!(at(12, 0), up);
+        assert_eq!(at(0, 0), up);
+        assert_eq!(at(3, 0), [Point::new(90, 60), up[1], up[2]]);
+        assert_eq!(at(6, 0), [Point::new(60, 90), up[1], up[2]]);
+        // half past, the hour hand is halfway to the next hour
+        assert_eq!(at(6, 30)[1], Point::new(60, 100));
+        assert_eq!(at(6, 30)[0], clock_point(center, 30.0, 6.5 / 12.0));
+    }
+
+    #[test]
+    fn clock_hands_get_thinner_outwards() {
+        let widths = clock_hands(Point::zero(), 50, NaiveTime::MIN).map(|(_, width)| width);
+        assert_eq!(widths, [3, 2, 1]);
+    }
+}
@@ -0,0 +1,139 @@
+use reqwest::Url;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::info;
+
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+// open-meteo only updates every 15 minutes anyway
+const CACHE_FOR: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Error, Debug)]
+pub enum WeatherError {
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+    #[error("Bad response from Open-Meteo: {0}")]
+    BadResponse(String),
+}
+
+// roughly what the sky looks like, picks the icon
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sky {
+    Clear,
+    Cloudy,
+    Rain,
+    Snow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeatherData {
+    pub temperature_c: f64,
+    // WMO weather interpretation code
+    pub code: u8,
+}
+
+impl WeatherData {
+    pub fn temperature_f(&self) -> f64 {
+        self.temperature_c * 9.0 / 5.0 + 32.0
+    }
+
+    pub fn condition(&self) -> &'static str {
+        condition(self.code)
+    }
+
+    pub fn sky(&self) -> Sky {
+        match self.code {
+            0 | 1 => Sky::Clear,
+            51..=67 | 80..=82 | 95..=99 => Sky::Rain,
+            71..=77 | 85 | 86 => Sky::Snow,
+            _ => Sky::Cloudy,
+        }
+    }
+}
+
+// WMO codes as open-meteo documents them, squashed to what fits on screen
+pub fn condition(code: u8) -> &'static str {
+    match code {
+        0 => "Sunny",
+        1 => "Mostly sunny",
+        2 => "Partly cloudy",
+        3 => "Cloudy",
+        45 | 48 => "Fog",
+        51..=57 => "Drizzle",
+        61..=67 => "Rain",
+        71..=77 => "Snow",
+        80..=82 => "Showers",
+        85 | 86 => "Snow showers",
+        95..=99 => "Thunderstorm",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Deserialize)]
+struct Forecast {
+    current: Current,
+}
+
+#[derive(Deserialize)]
+struct Current {
+    temperature_2m: f64,
+    weather_code: u8,
+}
+
+// open-meteo needs no api key. asking again for the same place within 10
+// minutes gets the last answer back without a request
+pub struct WeatherClient {
+    http: reqwest::blocking::Client,
+    last: Option<(f64, f64, Instant, WeatherData)>,
+}
+
+impl Default for WeatherClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeatherClient {
+    pub fn new() -> Self {
+        WeatherClient {
+            http: reqwest::blocking::Client::new(),
+            last: None,
+        }
+    }
+
+    pub fn fetch(&mut self, lat: f64, lon: f64) -> Result<WeatherData, WeatherError> {
+        if let Some((last_lat, last_lon, fetched_at, data)) = self.last {
+            if last_lat == lat && last_lon == lon && fetched_at.elapsed() < CACHE_FOR {
+                return Ok(data);
+            }
+        }
+
+        info!("weather: fetching current conditions for {}, {}", lat, lon);
+        let url = Url::parse_with_params(
+            FORECAST_URL,
+            &[
+                ("latitude", lat.to_string()),
+                ("longitude", lon.to_string()),
+                ("current", "temperature_2m,weather_code".to_string()),
+            ],
+        )
+        .map_err(|e| WeatherError::BadResponse(e.to_string()))?;
+        let response = self.http.get(url).send()?;
+
+        let status = response.status();
+        let body = response.text()?;
+        if !status.is_success() {
+            return Err(WeatherError::BadResponse(format!("{}: {}", status, body)));
+        }
+        let forecast: Forecast =
+            serde_json::from_str(&body).map_err(|e| WeatherError::BadResponse(e.to_string()))?;
+
+        let data = WeatherData {
+            temperature_c: forecast.current.temperature_2m,
+            code: forecast.current.weather_code,
+        };
+        self.last = Some((lat, lon, Instant::now(), data));
+        Ok(data)
+    }
+}
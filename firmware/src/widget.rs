@@ -0,0 +1,860 @@
+use chrono::{DateTime, Local, NaiveTime};
+use embedded_graphics::{
+    draw_target::DrawTargetExt,
+    mono_font::iso_8859_1,
+    prelude::*,
+    primitives::{Arc, Circle, Line, Polyline, PrimitiveStyle, Rectangle},
+};
+use epd_waveshare::color::Color;
+use std::cell::Cell;
+
+use crate::heartrate::PulseReading;
+use crate::network::{NetworkInfo, MAX_BARS};
+use crate::panel::PanelDisplay;
+use crate::region;
+use crate::sysinfo::SystemSnapshot;
+use crate::temperature::{self, Temperatures};
+use crate::utils::{
+    draw_analog_clock, draw_progress_bar, draw_text_styled, text_height, text_width, Alignment,
+    FontSize,
+};
+use crate::weather::{Sky, WeatherData};
+use crate::EpaperError;
+
+// something that can draw itself into a piece of the display. bounds are in
+// display coordinates, the layout clips anything drawn outside them
+pub trait Widget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError>;
+}
+
+// how a layout hands out the display to its widgets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    // side by side, left to right
+    Horizontal,
+    // stacked, top to bottom
+    Vertical,
+    // (cols, rows) filled row by row, widgets past the last cell aren't drawn
+    Grid(u32, u32),
+}
+
+pub struct Layout {
+    widgets: Vec<Box<dyn Widget>>,
+    split: Split,
+}
+
+impl Layout {
+    pub fn new(split: Split) -> Self {
+        Layout {
+            widgets: Vec::new(),
+            split,
+        }
+    }
+
+    pub fn with(mut self, widget: impl Widget + 'static) -> Self {
+        self.widgets.push(Box::new(widget));
+        self
+    }
+
+    pub fn push(&mut self, widget: Box<dyn Widget>) {
+        self.widgets.push(widget);
+    }
+
+    // the area each widget gets, in the same order as the widgets
+    pub fn cells(&self, area: Rectangle) -> Vec<Rectangle> {
+        let n = self.widgets.len() as u32;
+        let (cols, rows) = match self.split {
+            Split::Horizontal => (n, 1),
+            Split::Vertical => (1, n),
+            Split::Grid(cols, rows) => (cols, rows),
+        };
+        if cols == 0 || rows == 0 {
+            return Vec::new();
+        }
+
+        // edges are spread out so the leftover pixels don't all end up in
+        // the last cell
+        let edge = |total: u32, parts: u32, i: u32| (total * i / parts) as i32;
+        let Size { width, height } = area.size;
+
+        (0..n.min(cols * rows))
+            .map(|i| {
+                let (col, row) = (i % cols, i / cols);
+                let (x0, x1) = (edge(width, cols, col), edge(width, cols, col + 1));
+                let (y0, y1) = (edge(height, rows, row), edge(height, rows, row + 1));
+                Rectangle::new(
+                    area.top_left + Point::new(x0, y0),
+                    Size::new((x1 - x0) as u32, (y1 - y0) as u32),
+                )
+            })
+            .collect()
+    }
+
+    pub fn render(&self, display: &mut PanelDisplay) -> Result<(), EpaperError> {
+        let cells = self.cells(display.bounding_box());
+
+        // each widget draws on a scratch copy and only its cell is copied
+        // back, so nothing spills into the neighbours
+        let mut scratch = PanelDisplay::default();
+        scratch.set_rotation(display.rotation());
+
+        for (widget, bounds) in self.widgets.iter().zip(cells) {
+            copy_area(display, &mut scratch, &bounds);
+            widget.render(&mut scratch, bounds)?;
+            copy_area(&scratch, display, &bounds);
+        }
+        Ok(())
+    }
+}
+
+fn copy_area(from: &PanelDisplay, to: &mut PanelDisplay, area: &Rectangle) {
+    for p in area.points() {
+        if let Some(color) = region::get_pixel(from, p) {
+            to.set_pixel(Pixel(p, color));
+        }
+    }
+}
+
+// x to start text at inside bounds
+fn text_x(bounds: &Rectangle, text: &str, alignment: Alignment, size: FontSize) -> i32 {
+    let width = bounds.size.width as i32;
+    let text_width = text_width(text, size.font()) as i32;
+
+    bounds.top_left.x
+        + match alignment {
+            Alignment::Left => 0,
+            Alignment::Center => (width - text_width) / 2,
+            Alignment::Right => width - text_width,
+        }
+}
+
+// y to start text at so it's vertically centered in bounds
+fn text_y(bounds: &Rectangle, size: FontSize) -> i32 {
+    bounds.top_left.y + (bounds.size.height as i32 - text_height(size.font()) as i32) / 2
+}
+
+// a single line of black on white text
+pub struct TextWidget {
+    pub text: String,
+    pub size: FontSize,
+    pub alignment: Alignment,
+}
+
+impl TextWidget {
+    pub fn new(text: impl Into<String>, size: FontSize) -> Self {
+        TextWidget {
+            text: text.into(),
+            size,
+            alignment: Alignment::Left,
+        }
+    }
+
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+}
+
+impl Widget for TextWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        draw_text_styled(
+            display,
+            &self.text,
+            text_x(&bounds, &self.text, self.alignment, self.size),
+            text_y(&bounds, self.size),
+            self.size.font(),
+            Color::Black,
+            Color::White,
+        );
+        Ok(())
+    }
+}
+
+// the time, centered. holds the time to show rather than reading the clock
+// itself so it shows whatever the rtc::Clock says
+pub struct ClockWidget {
+    pub time: DateTime<Local>,
+    pub format: &'static str,
+    pub size: FontSize,
+}
+
+impl ClockWidget {
+    pub fn new(time: DateTime<Local>) -> Self {
+        ClockWidget {
+            time,
+            format: "%H:%M:%S",
+            size: FontSize::Large,
+        }
+    }
+}
+
+impl Widget for ClockWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let text = self.time.format(self.format).to_string();
+        draw_text_styled(
+            display,
+            &text,
+            text_x(&bounds, &text, Alignment::Center, self.size),
+            text_y(&bounds, self.size),
+            self.size.font(),
+            Color::Black,
+            Color::White,
+        );
+        Ok(())
+    }
+}
+
+// the analog face from utils, as big as fits in the middle of bounds
+pub struct AnalogClockWidget {
+    pub time: NaiveTime,
+}
+
+impl AnalogClockWidget {
+    pub fn new(time: NaiveTime) -> Self {
+        AnalogClockWidget { time }
+    }
+}
+
+impl Widget for AnalogClockWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let radius = bounds.size.width.min(bounds.size.height).saturating_sub(1) / 2;
+        draw_analog_clock(display, bounds.center(), radius, self.time);
+        Ok(())
+    }
+}
+
+pub const DEFAULT_SCROLL_SPEED: i32 = 4;
+// space between the end of the text and the start of the next copy
+const SCROLL_GAP: i32 = 40;
+
+// one line too long for its bounds, scrolling right to left. every render
+// moves it on by speed_px_per_frame, so rendering it once a frame from the
+// display loop sets the pace. the text comes in from the right edge and a
+// second copy follows it round, so the line never runs out
+pub struct TextScroller {
+    pub text: String,
+    // from the top of the bounds
+    pub y: i32,
+    pub speed_px_per_frame: i32,
+    pub font: FontSize,
+    offset: Cell<i32>,
+}
+
+impl TextScroller {
+    pub fn new(text: impl Into<String>, y: i32, font: FontSize) -> Self {
+        TextScroller {
+            text: text.into(),
+            y,
+            speed_px_per_frame: DEFAULT_SCROLL_SPEED,
+            font,
+            offset: Cell::new(0),
+        }
+    }
+
+    pub fn with_speed(mut self, speed_px_per_frame: i32) -> Self {
+        self.speed_px_per_frame = speed_px_per_frame;
+        self
+    }
+
+    // starts over from the right edge if it's different text
+    pub fn set_text(&mut self, text: &str) {
+        if text != self.text {
+            self.text = text.to_string();
+            self.offset.set(0);
+        }
+    }
+}
+
+impl Widget for TextScroller {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let width = bounds.size.width as i32;
+        let text_w = text_width(&self.text, self.font.font()) as i32;
+        let span = text_w + SCROLL_GAP;
+
+        let mut offset = self.offset.get() + self.speed_px_per_frame;
+        // by now the second copy is where the first one came in, so going
+        // back a copy's length doesn't show
+        if offset >= width + span {
+            offset -= span;
+        }
+        self.offset.set(offset);
+
+        let x = bounds.top_left.x + width - offset;
+        let y = bounds.top_left.y + self.y;
+        let mut clipped = display.clipped(&bounds);
+        for x in [x, x + span] {
+            draw_text_styled(
+                &mut clipped,
+                &self.text,
+                x,
+                y,
+                self.font.font(),
+                Color::Black,
+                Color::White,
+            );
+        }
+        Ok(())
+    }
+}
+
+// outlined bar filling its bounds, fraction is clamped to 0..1
+pub struct ProgressBarWidget {
+    pub fraction: f32,
+}
+
+impl ProgressBarWidget {
+    pub fn new(fraction: f32) -> Self {
+        ProgressBarWidget { fraction }
+    }
+}
+
+impl Widget for ProgressBarWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        draw_progress_bar(
+            display,
+            bounds.top_left.x,
+            bounds.top_left.y,
+            bounds.size.width,
+            bounds.size.height,
+            self.fraction,
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+// icon on the left, temperature with the condition under it on the right.
+// like ClockWidget it's handed the data, WeatherClient does the fetching
+pub struct WeatherWidget {
+    pub weather: WeatherData,
+    pub unit: TemperatureUnit,
+}
+
+impl WeatherWidget {
+    pub fn new(weather: WeatherData) -> Self {
+        WeatherWidget {
+            weather,
+            unit: TemperatureUnit::Celsius,
+        }
+    }
+
+    pub fn with_unit(mut self, unit: TemperatureUnit) -> Self {
+        self.unit = unit;
+        self
+    }
+}
+
+impl Widget for WeatherWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let icon = bounds.size.height.min(bounds.size.width / 3).min(32);
+        let icon_area = Rectangle::new(
+            bounds.top_left + Point::new(0, (bounds.size.height - icon) as i32 / 2),
+            Size::new(icon, icon),
+        );
+        draw_sky(display, &icon_area, self.weather.sky());
+
+        let temperature = match self.unit {
+            TemperatureUnit::Celsius => format!("{:.0}\u{b0}C", self.weather.temperature_c),
+            TemperatureUnit::Fahrenheit => format!("{:.0}\u{b0}F", self.weather.temperature_f()),
+        };
+        // the ascii fonts have no degree sign, the latin-1 ones are the same size
+        let large = &iso_8859_1::FONT_10X20;
+        let small = FontSize::Small.font();
+
+        let x = bounds.top_left.x + icon as i32 + 4;
+        let lines = (text_height(large) + text_height(small)) as i32;
+        let y = bounds.top_left.y + (bounds.size.height as i32 - lines) / 2;
+        draw_text_styled(
+            display,
+            &temperature,
+            x,
+            y,
+            large,
+            Color::Black,
+            Color::White,
+        );
+        draw_text_styled(
+            display,
+            self.weather.condition(),
+            x,
+            y + text_height(large) as i32,
+            small,
+            Color::Black,
+            Color::White,
+        );
+        Ok(())
+    }
+}
+
+// sun is a circle with rays, cloud is a couple of bumps on a flat bottom,
+// rain and snow hang lines or dots under the cloud
+fn draw_sky(display: &mut PanelDisplay, area: &Rectangle, sky: Sky) {
+    let stroke = PrimitiveStyle::with_stroke(Color::Black, 1);
+    let s = area.size.width as i32;
+    let origin = area.top_left;
+
+    if sky == Sky::Clear {
+        let center = origin + Point::new(s / 2, s / 2);
+        let _ = Circle::with_center(center, (s / 2) as u32)
+            .into_styled(stroke)
+            .draw(display);
+        for i in 0..8 {
+            let angle = i as f32 * std::f32::consts::TAU / 8.0;
+            let (sin, cos) = angle.sin_cos();
+            let at =
+                |r: f32| center + Point::new((cos * r).round() as i32, (sin * r).round() as i32);
+            let _ = Line::new(at(s as f32 * 0.33), at(s as f32 * 0.48))
+                .into_styled(stroke)
+                .draw(display);
+        }
+        return;
+    }
+
+    // the cloud takes the top two thirds, precipitation goes below
+    let bottom = origin.y + s * 2 / 3;
+    let small = (s * 2 / 5) as u32;
+    let big = (s / 2) as u32;
+    let left = origin.x + 1;
+    let right = origin.x + s - small as i32 - 1;
+    let _ = Arc::new(
+        Point::new(left, bottom - small as i32 + 1),
+        small,
+        Angle::from_degrees(90.0),
+        Angle::from_degrees(180.0),
+    )
+    .into_styled(stroke)
+    .draw(display);
+    let _ = Arc::new(
+        Point::new(
+            origin.x + (s - big as i32) / 2,
+            bottom - big as i32 * 5 / 4 + 1,
+        ),
+        big,
+        Angle::from_degrees(180.0),
+        Angle::from_degrees(180.0),
+    )
+    .into_styled(stroke)
+    .draw(display);
+    let _ = Arc::new(
+        Point::new(right, bottom - small as i32 + 1),
+        small,
+        Angle::from_degrees(270.0),
+        Angle::from_degrees(180.0),
+    )
+    .into_styled(stroke)
+    .draw(display);
+    let _ = Line::new(
+        Point::new(left + small as i32 / 2, bottom),
+        Point::new(right + small as i32 / 2, bottom),
+    )
+    .into_styled(stroke)
+    .draw(display);
+
+    for i in 1..4 {
+        let x = origin.x + s * i / 4;
+        match sky {
+            Sky::Rain => {
+                let _ = Line::new(Point::new(x, bottom + 3), Point::new(x - 2, bottom + s / 4))
+                    .into_styled(stroke)
+                    .draw(display);
+            }
+            Sky::Snow => {
+                let _ = Circle::with_center(Point::new(x, bottom + s / 6), 3)
+                    .into_styled(PrimitiveStyle::with_fill(Color::Black))
+                    .draw(display);
+            }
+            _ => {}
+        }
+    }
+}
+
+pub const DEFAULT_STEP_GOAL: u32 = 10_000;
+
+// today's steps from accelerometer::StepCounter over a bar towards the goal
+pub struct StepWidget {
+    pub steps: u32,
+    pub goal: u32,
+}
+
+impl StepWidget {
+    pub fn new(steps: u32) -> Self {
+        StepWidget {
+            steps,
+            goal: DEFAULT_STEP_GOAL,
+        }
+    }
+
+    pub fn with_goal(mut self, goal: u32) -> Self {
+        self.goal = goal;
+        self
+    }
+}
+
+impl Widget for StepWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let count = format!("{} steps", self.steps);
+        let goal = format!("goal {}", self.goal);
+        let (large, small) = (FontSize::Medium, FontSize::Small);
+        let bar_height = 6;
+        let gap = 2;
+
+        let lines = (text_height(large.font()) + gap + bar_height) as i32;
+        let mut y = bounds.top_left.y + (bounds.size.height as i32 - lines) / 2;
+        draw_text_styled(
+            display,
+            &count,
+            bounds.top_left.x,
+            y,
+            large.font(),
+            Color::Black,
+            Color::White,
+        );
+        // goal shares the line, on the right, bottoms lined up
+        draw_text_styled(
+            display,
+            &goal,
+            text_x(&bounds, &goal, Alignment::Right, small),
+            y + (text_height(large.font()) - text_height(small.font())) as i32,
+            small.font(),
+            Color::Black,
+            Color::White,
+        );
+
+        y += (text_height(large.font()) + gap) as i32;
+        let fraction = if self.goal == 0 {
+            1.0
+        } else {
+            self.steps as f32 / self.goal as f32
+        };
+        draw_progress_bar(
+            display,
+            bounds.top_left.x,
+            y,
+            bounds.size.width,
+            bar_height,
+            fraction,
+        );
+        Ok(())
+    }
+}
+
+// the size HeartRateWidget is laid out for, it draws in whatever it gets
+pub const HEART_RATE_SIZE: Size = Size::new(100, 30);
+
+// bpm from heartrate::Max30102 on the left, the pulse it's working from
+// plotted on the right
+pub struct HeartRateWidget {
+    pub reading: PulseReading,
+}
+
+impl HeartRateWidget {
+    pub fn new(reading: PulseReading) -> Self {
+        HeartRateWidget { reading }
+    }
+}
+
+impl Widget for HeartRateWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let (large, small) = (FontSize::Medium, FontSize::Small);
+        // room for three digits
+        let column = text_width("000", large.font());
+        let gap = 4;
+
+        // "---" for no finger and for a pulse too noisy to count alike
+        let bpm = match self.reading.bpm {
+            Some(bpm) if self.reading.finger => bpm.to_string(),
+            _ => "---".to_string(),
+        };
+        let lines = (text_height(large.font()) + text_height(small.font())) as i32;
+        let y = bounds.top_left.y + (bounds.size.height as i32 - lines) / 2;
+        let label = Rectangle::new(bounds.top_left, Size::new(column, bounds.size.height));
+        for (text, size, y) in [
+            (bpm.as_str(), large, y),
+            ("bpm", small, y + text_height(large.font()) as i32),
+        ] {
+            draw_text_styled(
+                display,
+                text,
+                text_x(&label, text, Alignment::Center, size),
+                y,
+                size.font(),
+                Color::Black,
+                Color::White,
+            );
+        }
+
+        let samples = &self.reading.waveform;
+        if !self.reading.finger || samples.len() < 2 {
+            return Ok(());
+        }
+        let plot = Rectangle::new(
+            bounds.top_left + Point::new((column + gap) as i32, 1),
+            Size::new(
+                bounds.size.width.saturating_sub(column + gap),
+                bounds.size.height.saturating_sub(2),
+            ),
+        );
+        if plot.is_zero_sized() {
+            return Ok(());
+        }
+
+        // scaled to fill the height, the pulse is a tiny wobble on top of
+        // a big steady ir reading
+        let low = *samples.iter().min().unwrap_or(&0);
+        let high = *samples.iter().max().unwrap_or(&0);
+        let range = (high - low).max(1) as f32;
+        let last = (samples.len() - 1) as f32;
+        let (width, height) = (plot.size.width - 1, plot.size.height - 1);
+        let points: Vec<Point> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| {
+                let x = (i as f32 / last * width as f32).round() as i32;
+                let y = ((high - s) as f32 / range * height as f32).round() as i32;
+                plot.top_left + Point::new(x, y)
+            })
+            .collect();
+        let _ = Polyline::new(&points)
+            .into_styled(PrimitiveStyle::with_stroke(Color::Black, 1))
+            .draw(display);
+        Ok(())
+    }
+}
+
+// how the pi is doing, from a sysinfo::SystemSnapshot. one row each for the
+// cpu, memory, disk and network
+pub struct SystemInfoWidget {
+    pub snapshot: SystemSnapshot,
+    // since the snapshot before, None for the first one
+    pub cpu_usage: Option<f32>,
+}
+
+impl SystemInfoWidget {
+    pub fn new(snapshot: SystemSnapshot, cpu_usage: Option<f32>) -> Self {
+        SystemInfoWidget {
+            snapshot,
+            cpu_usage,
+        }
+    }
+
+    fn rows(&self) -> [String; 4] {
+        let snapshot = &self.snapshot;
+        let temp = snapshot
+            .cpu_temp
+            .map_or("--".to_string(), |c| format!("{:.0}", c));
+        let usage = self
+            .cpu_usage
+            .map_or("--".to_string(), |u| format!("{:.0}", u * 100.0));
+        let gb = |bytes: u64| bytes as f32 / (1024.0 * 1024.0 * 1024.0);
+        let ip = snapshot.ip.map_or("none".to_string(), |ip| ip.to_string());
+        [
+            format!("CPU: {}\u{b0}C  {}%", temp, usage),
+            format!(
+                "MEM: {}/{} MB",
+                snapshot.mem_used_kb() / 1024,
+                snapshot.mem_total_kb / 1024
+            ),
+            format!(
+                "DSK: {:.1}/{:.1} GB",
+                gb(snapshot.disk_used),
+                gb(snapshot.disk_total)
+            ),
+            format!("IP: {}", ip),
+        ]
+    }
+}
+
+impl Widget for SystemInfoWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        // latin-1 for the degree sign
+        let font = &iso_8859_1::FONT_9X18;
+        let rows = self.rows();
+        let line = text_height(font) as i32;
+        let top = bounds.top_left.y + (bounds.size.height as i32 - rows.len() as i32 * line) / 2;
+        for (i, row) in rows.iter().enumerate() {
+            draw_text_styled(
+                display,
+                row,
+                bounds.top_left.x,
+                top + i as i32 * line,
+                font,
+                Color::Black,
+                Color::White,
+            );
+        }
+        Ok(())
+    }
+}
+
+// the interface, its address and a signal icon, from a network::NetworkInfo
+pub struct NetworkStatusWidget {
+    pub info: NetworkInfo,
+}
+
+impl NetworkStatusWidget {
+    pub fn new(info: NetworkInfo) -> Self {
+        NetworkStatusWidget { info }
+    }
+
+    // MAX_BARS of them, each taller than the last, filled up to bars and
+    // outlined after. bottom right is the corner they stand on
+    fn draw_bars(display: &mut PanelDisplay, bottom_right: Point, bars: u8) {
+        const BAR_WIDTH: u32 = 5;
+        const GAP: u32 = 2;
+        const STEP: u32 = 5;
+        let left = bottom_right.x - (u32::from(MAX_BARS) * (BAR_WIDTH + GAP) - GAP) as i32;
+        for i in 0..MAX_BARS {
+            let height = STEP * (u32::from(i) + 1);
+            let x = left + (u32::from(i) * (BAR_WIDTH + GAP)) as i32;
+            let style = if i < bars {
+                PrimitiveStyle::with_fill(Color::Black)
+            } else {
+                PrimitiveStyle::with_stroke(Color::Black, 1)
+            };
+            let _ = Rectangle::new(
+                Point::new(x, bottom_right.y - height as i32),
+                Size::new(BAR_WIDTH, height),
+            )
+            .into_styled(style)
+            .draw(display);
+        }
+    }
+}
+
+impl Widget for NetworkStatusWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        let font = &iso_8859_1::FONT_9X18;
+        let info = &self.info;
+        let state = if info.up { "up" } else { "down" };
+        let signal = info
+            .rssi_dbm()
+            .map_or("wired".to_string(), |dbm| format!("{} dBm", dbm));
+        let rows = [
+            format!("{} ({})", info.interface, state),
+            info.address_text(),
+            signal,
+        ];
+        let line = text_height(font) as i32;
+        let top = bounds.top_left.y + (bounds.size.height as i32 - rows.len() as i32 * line) / 2;
+        for (i, row) in rows.iter().enumerate() {
+            draw_text_styled(
+                display,
+                row,
+                bounds.top_left.x,
+                top + i as i32 * line,
+                font,
+                Color::Black,
+                Color::White,
+            );
+        }
+
+        // level with the bottom of the text, in from the right edge
+        let bottom_right = Point::new(
+            bounds.top_left.x + bounds.size.width as i32 - 4,
+            top + rows.len() as i32 * line,
+        );
+        Self::draw_bars(display, bottom_right, info.bars());
+        Ok(())
+    }
+}
+
+// the cpu and the room side by side, each a thermometer with the reading
+// next to it. either shows "--" if it couldn't be read
+pub struct ThermometerWidget {
+    pub temperatures: Temperatures,
+    // the cpu's over its alert threshold, its reading goes inverted
+    pub cpu_hot: bool,
+}
+
+impl ThermometerWidget {
+    pub fn new(temperatures: Temperatures) -> Self {
+        ThermometerWidget {
+            temperatures,
+            cpu_hot: false,
+        }
+    }
+
+    pub fn with_cpu_hot(mut self, hot: bool) -> Self {
+        self.cpu_hot = hot;
+        self
+    }
+
+    // a tube with a bulb under it, filled from the bulb up to how far
+    // celsius is along temperature::SCALE_MIN to SCALE_MAX
+    fn draw_thermometer(display: &mut PanelDisplay, area: Rectangle, celsius: Option<f32>) {
+        const TUBE_WIDTH: u32 = 8;
+        const BULB: u32 = 14;
+        let outline = PrimitiveStyle::with_stroke(Color::Black, 1);
+        let fill = PrimitiveStyle::with_fill(Color::Black);
+
+        let center_x = area.top_left.x + area.size.width as i32 / 2;
+        let tube_height = area.size.height.saturating_sub(BULB - 2);
+        let tube = Rectangle::new(
+            Point::new(center_x - TUBE_WIDTH as i32 / 2, area.top_left.y),
+            Size::new(TUBE_WIDTH, tube_height),
+        );
+        let bulb = Circle::with_center(
+            Point::new(
+                center_x,
+                tube.top_left.y + tube_height as i32 + BULB as i32 / 2 - 2,
+            ),
+            BULB,
+        );
+        let _ = tube.into_styled(outline).draw(display);
+
+        let Some(celsius) = celsius else {
+            let _ = bulb.into_styled(outline).draw(display);
+            return;
+        };
+        let level = (temperature::scale_fraction(celsius) * tube_height as f32).round() as u32;
+        let _ = Rectangle::new(
+            Point::new(
+                tube.top_left.x,
+                tube.top_left.y + (tube_height - level) as i32,
+            ),
+            Size::new(TUBE_WIDTH, level),
+        )
+        .into_styled(fill)
+        .draw(display);
+        let _ = bulb.into_styled(fill).draw(display);
+    }
+}
+
+impl Widget for ThermometerWidget {
+    fn render(&self, display: &mut PanelDisplay, bounds: Rectangle) -> Result<(), EpaperError> {
+        // latin-1 for the degree sign
+        let font = &iso_8859_1::FONT_9X18;
+        let line = text_height(font) as i32;
+        let half = bounds.size.width / 2;
+        let readings = [
+            ("CPU", self.temperatures.cpu, self.cpu_hot),
+            ("Room", self.temperatures.ambient, false),
+        ];
+        for (i, (label, celsius, hot)) in readings.into_iter().enumerate() {
+            let left = bounds.top_left.x + (i as u32 * half) as i32;
+            let icon = Rectangle::new(
+                Point::new(left, bounds.top_left.y + 4),
+                Size::new(24, bounds.size.height.saturating_sub(8)),
+            );
+            Self::draw_thermometer(display, icon, celsius);
+
+            let value = celsius.map_or("--".to_string(), |c| format!("{:.1}\u{b0}C", c));
+            let (fg, bg) = if hot {
+                (Color::White, Color::Black)
+            } else {
+                (Color::Black, Color::White)
+            };
+            let x = left + 28;
+            let top = bounds.top_left.y + bounds.size.height as i32 / 2 - line;
+            draw_text_styled(display, label, x, top, font, Color::Black, Color::White);
+            draw_text_styled(display, &value, x, top + line, font, fg, bg);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::{SocketAddr, TcpListener as StdListener};
+use std::sync::mpsc;
+use std::thread;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{self, Duration, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{error, info, warn};
+
+use crate::command::{DisplayCommand, Request};
+use crate::logger;
+use crate::qr;
+use crate::state::{DeviceState, SharedState};
+use crate::utils::FontSize;
+use crate::EpaperError;
+
+// every connection gets pinged this often and dropped if the pong takes
+// longer than PONG_TIMEOUT
+const PING_EVERY: Duration = Duration::from_secs(30);
+const PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+// what remote clients can ask the display to do. sent as json text frames,
+// e.g. {"cmd": "set_text", "text": "hi", "x": 10, "y": 20, "size": "small"}.
+// every one is answered with {"ok": true} or {"error": "..."}. a button
+// press that changes the pet pushes {"event": "state", ...} to everyone
+#[derive(Debug, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum WsCommand {
+    #[serde(alias = "show_text")]
+    SetText {
+        text: String,
+        x: i32,
+        y: i32,
+        // small when left out
+        #[serde(default)]
+        size: FontSize,
+    },
+    // whole screen, 1 bit per pixel, rows padded to whole bytes, set is black
+    ShowImage {
+        bitmap: Vec<u8>,
+    },
+    // a url, usually this websocket's, to scan with a phone
+    ShowQr {
+        data: String,
+        #[serde(default = "default_module_size")]
+        module_size: u8,
+    },
+    Clear,
+    Refresh,
+    ClearScreen,
+    Progress {
+        #[serde(default)]
+        label: String,
+        current: u32,
+        total: u32,
+    },
+    ShowSpotify,
+    ShowPlaylists,
+    ShowSettings,
+    ShowPet,
+    ShowClock,
+    FeedPet,
+    PlayWithPet,
+    PomodoroStart,
+    PomodoroPause,
+    PomodoroSkip,
+    // answered by the websocket thread, the display never sees these
+    GetMetrics,
+    GetState,
+}
+
+fn default_module_size() -> u8 {
+    qr::DEFAULT_MODULE_SIZE
+}
+
+impl WsCommand {
+    // None for what the websocket thread answers itself
+    fn into_display(self) -> Option<DisplayCommand> {
+        Some(match self {
+            WsCommand::SetText { text, x, y, size } => {
+                DisplayCommand::DrawText { text, x, y, size }
+            }
+            WsCommand::ShowImage { bitmap } => DisplayCommand::DrawImage { bitmap },
+            WsCommand::ShowQr { data, module_size } => DisplayCommand::DrawQr { data, module_size },
+            WsCommand::Clear => DisplayCommand::Clear,
+            WsCommand::Refresh => DisplayCommand::Refresh,
+            WsCommand::ClearScreen => DisplayCommand::ClearScreen,
+            WsCommand::Progress {
+                label,
+                current,
+                total,
+            } => DisplayCommand::Progress {
+                label,
+                current,
+                total,
+            },
+            WsCommand::ShowSpotify => DisplayCommand::ShowSpotify,
+            WsCommand::ShowPlaylists => DisplayCommand::ShowPlaylists,
+            WsCommand::ShowSettings => DisplayCommand::ShowSettings,
+            WsCommand::ShowPet => DisplayCommand::ShowPet,
+            WsCommand::ShowClock => DisplayCommand::ShowClock,
+            WsCommand::FeedPet => DisplayCommand::FeedPet,
+            WsCommand::PlayWithPet => DisplayCommand::PlayWithPet,
+            WsCommand::PomodoroStart => DisplayCommand::PomodoroStart,
+            WsCommand::PomodoroPause => DisplayCommand::PomodoroPause,
+            WsCommand::PomodoroSkip => DisplayCommand::PomodoroSkip,
+            WsCommand::GetMetrics | WsCommand::GetState => return None,
+        })
+    }
+}
+
+// listens on 0.0.0.0:port in a thread of its own, commands come out of the
+// receiver for the display loop to handle. state is the display's, for
+// get_state and the pushes
+pub fn spawn(port: u16, state: SharedState) -> Result<mpsc::Receiver<Request>, EpaperError> {
+    // bound here so a port that's taken fails straight away
+    let listener = StdListener::bind(("0.0.0.0", port)).map_err(EpaperError::WebSocket)?;
+    listener
+        .set_nonblocking(true)
+        .map_err(EpaperError::WebSocket)?;
+
+    let (tx, rx) = mpsc::channel();
+    let runtime = tokio::runtime::Runtime::new().map_err(EpaperError::WebSocket)?;
+
+    thread::spawn(move || {
+        runtime.block_on(async move {
+            let listener = match TcpListener::from_std(listener) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("WebSocket server failed to start: {}", e);
+                    logger::record_error(format!("WebSocket server failed to start: {}", e));
+                    return;
+                }
+            };
+
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        tokio::spawn(handle_connection(stream, peer, tx.clone(), state.clone()));
+                    }
+                    Err(e) => warn!("WebSocket accept failed: {}", e),
+                }
+            }
+        });
+    });
+
+    Ok(rx)
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    peer: SocketAddr,
+    tx: mpsc::Sender<Request>,
+    state: SharedState,
+) {
+    let mut ws = match tokio_tungstenite::accept_async(stream).await {
+        Ok(ws) => ws,
+        Err(e) => {
+            warn!("WebSocket handshake failed: {}", e);
+            return;
+        }
+    };
+    info!("WebSocket client {} connected", peer);
+
+    // a phone that walks out of wifi range never closes its end, without
+    // the pings the task would wait on it forever
+    let mut pings = time::interval_at(Instant::now() + PING_EVERY, PING_EVERY);
+    let mut pong_due: Option<Instant> = None;
+    let mut changes = state.subscribe();
+    // false once the display has gone, recv() would only return Closed
+    let mut listening = true;
+
+    loop {
+        let message = tokio::select! {
+            message = ws.next() => message,
+            _ = pings.tick() => {
+                if ws.send(Message::Ping(Default::default())).await.is_err() {
+                    break;
+                }
+                // an older ping still waiting keeps its deadline
+                pong_due.get_or_insert(Instant::now() + PONG_TIMEOUT);
+                continue;
+            }
+            _ = time::sleep_until(pong_due.unwrap_or_else(Instant::now)), if pong_due.is_some() => {
+                warn!("WebSocket client {} stopped answering pings, dropping it", peer);
+                break;
+            }
+            change = changes.recv(), if listening => {
+                match change {
+                    Ok(change) => {
+                        let push = state_message(&change, "event", json!("state"));
+                        if ws.send(Message::text(push.to_string())).await.is_err() {
+                            break;
+                        }
+                    }
+                    // a newer one is still to come
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => listening = false,
+                }
+                continue;
+            }
+        };
+
+        let text = match message {
+            Some(Ok(Message::Text(text))) => text,
+            Some(Ok(Message::Pong(_))) => {
+                pong_due = None;
+                continue;
+            }
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            // tungstenite answers pings by itself, binary isn't part of this
+            Some(Ok(_)) => continue,
+        };
+
+        let response = match serde_json::from_str::<WsCommand>(text.as_str()) {
+            Ok(WsCommand::GetMetrics) => json!({ "ok": true, "metrics": logger::snapshot() }),
+            Ok(WsCommand::GetState) => state_message(&state.get(), "ok", json!(true)),
+            Ok(command) => match command.into_display() {
+                Some(command) => match dispatch(&tx, command).await {
+                    Ok(()) => json!({ "ok": true }),
+                    Err(e) => json!({ "error": e }),
+                },
+                None => json!({ "error": "not a display command" }),
+            },
+            Err(e) => json!({ "error": format!("bad command: {}", e) }),
+        };
+
+        if ws.send(Message::text(response.to_string())).await.is_err() {
+            break;
+        }
+    }
+    info!("WebSocket client {} disconnected", peer);
+}
+
+// the state's fields at the top level with key: value added
+fn state_message(state: &DeviceState, key: &str, value: Value) -> Value {
+    let mut message = serde_json::to_value(state).unwrap_or_else(|_| json!({}));
+    message[key] = value;
+    message
+}
+
+async fn dispatch(tx: &mpsc::Sender<Request>, command: DisplayCommand) -> Result<(), String> {
+    let (reply, done) = oneshot::channel();
+    tx.send(Request { command, reply })
+        .map_err(|_| "display isn't running".to_string())?;
+    done.await
+        .map_err(|_| "display dropped the command".to_string())?
+}